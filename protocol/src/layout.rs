@@ -0,0 +1,127 @@
+//! Describes where the fields of a message are in the frame.
+//!
+//! deku knows the layout of a message, but doesn't expose it. The inspector needs it to show
+//! which bit belongs to which field, so it is described again here. The tests make sure the
+//! descriptions match what deku encodes.
+use crate::{miu, sid, t7, tcm, Message};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Field {
+    pub name: &'static str,
+
+    /// The Trionic symbol the field is read from or written to
+    pub symbol: &'static str,
+
+    /// Offset of the first bit, counted from the most significant bit of the first byte
+    pub start: usize,
+
+    pub bits: usize,
+}
+
+impl Field {
+    pub const fn new(name: &'static str, symbol: &'static str, start: usize, bits: usize) -> Self {
+        Self {
+            name,
+            symbol,
+            start,
+            bits,
+        }
+    }
+
+    pub fn contains(&self, bit: usize) -> bool {
+        (self.start..self.start + self.bits).contains(&bit)
+    }
+
+    /// Reads the value of the field from a frame. Missing bytes are read as zeroes.
+    pub fn value(&self, data: &[u8]) -> u64 {
+        (self.start..self.start + self.bits).fold(0, |value, bit| {
+            let byte = data.get(bit / 8).copied().unwrap_or(0);
+            (value << 1) | u64::from((byte >> (7 - bit % 8)) & 1)
+        })
+    }
+}
+
+pub trait Layout {
+    const NAME: &'static str;
+    const FIELDS: &'static [Field];
+}
+
+/// Returns the name and fields of the message with `id`, if it is known. The experimental MIU
+/// messages aren't, so frames that other nodes send with their ids aren't decoded with a guess.
+pub fn find(id: u32) -> Option<(&'static str, &'static [Field])> {
+    fn entry<T: Layout>() -> Option<(&'static str, &'static [Field])> {
+        Some((T::NAME, T::FIELDS))
+    }
+
+    match id {
+        t7::EngineSpeedAndThrottle::CAN_ID => entry::<t7::EngineSpeedAndThrottle>(),
+        t7::EngineStatus::CAN_ID => entry::<t7::EngineStatus>(),
+        t7::AirAndCoolant::CAN_ID => entry::<t7::AirAndCoolant>(),
+        t7::FuelConsumptionAndBoost::CAN_ID => entry::<t7::FuelConsumptionAndBoost>(),
+        tcm::TransmissionStatus::CAN_ID => entry::<tcm::TransmissionStatus>(),
+        miu::VehicleSpeed::CAN_ID => entry::<miu::VehicleSpeed>(),
+        miu::FuelLevel::CAN_ID => entry::<miu::FuelLevel>(),
+        sid::TextRequest::CAN_ID => entry::<sid::TextRequest>(),
+        sid::TextGrant::CAN_ID => entry::<sid::TextGrant>(),
+        sid::TextFrame::CAN_ID => entry::<sid::TextFrame>(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use deku::{DekuContainerRead, DekuContainerWrite};
+
+    /// Checks that the fields don't overlap, and that exactly the bits covered by fields survive
+    /// decoding and encoding a frame. Bits that are not covered are padding, which deku drops, or
+    /// beyond the end of a short frame.
+    pub fn assert_layout<T>()
+    where
+        T: Layout + for<'a> DekuContainerRead<'a> + DekuContainerWrite,
+    {
+        for (i, field) in T::FIELDS.iter().enumerate() {
+            assert!(
+                field.start + field.bits <= 64,
+                "{} is outside the frame",
+                field.name
+            );
+
+            for other in &T::FIELDS[i + 1..] {
+                assert!(
+                    field.start + field.bits <= other.start,
+                    "{} overlaps or is not before {}",
+                    field.name,
+                    other.name
+                );
+            }
+        }
+
+        for bit in 0..64 {
+            let input = (1_u64 << (63 - bit)).to_be_bytes();
+            let (_, message) = T::from_bytes((&input, 0)).unwrap();
+            let mut output = message.to_bytes().unwrap();
+            output.resize(8, 0);
+
+            let covered = T::FIELDS.iter().any(|field| field.contains(bit));
+            assert_eq!(
+                output.as_slice() == input,
+                covered,
+                "bit {} of {} should be {}",
+                bit,
+                T::NAME,
+                if covered { "a field" } else { "padding" }
+            );
+        }
+    }
+
+    #[test]
+    fn it_reads_field_values() {
+        let data = [0x3c, 0x03, 0x52, 0x80];
+
+        assert_eq!(Field::new("", "", 2, 2).value(&data), 3);
+        assert_eq!(Field::new("", "", 8, 16).value(&data), 850);
+        assert_eq!(Field::new("", "", 24, 1).value(&data), 1);
+        assert_eq!(Field::new("", "", 56, 8).value(&data), 0);
+    }
+}
@@ -0,0 +1,202 @@
+//! Messages sent by the MIU.
+//!
+//! The T7 reads the vehicle speed and fuel level from the MIU, the layouts come from the T7
+//! software. The distance and SID button messages are experimental: their ids and layouts are a
+//! guess that hasn't been checked against a car or a capture, so they are left out of
+//! [`layout::find`](crate::layout::find) and only sent when asked for.
+use alloc::vec::Vec;
+use deku::prelude::*;
+
+use crate::layout::{Field, Layout};
+use crate::Message;
+
+#[derive(Debug, Default, PartialEq, DekuRead, DekuWrite)]
+#[deku(endian = "big")]
+pub struct VehicleSpeed {
+    // CanInRaw.v_Vehicle2Fault
+    #[deku(pad_bits_before = "2", bits = 2)]
+    pub vehicle_speed_fault: u8,
+
+    // CanInRaw.v_Vehicle2
+    #[deku(pad_bits_before = "4")]
+    pub vehicle_speed: u16,
+
+    // ActualIn.ST_BoostMeter
+    #[deku(bits = 1)]
+    pub boost_meter_status: u8,
+}
+
+impl Message for VehicleSpeed {
+    const CAN_ID: u32 = 0x2f0;
+}
+
+impl Layout for VehicleSpeed {
+    const NAME: &'static str = "Vehicle speed";
+    const FIELDS: &'static [Field] = &[
+        Field::new("vehicle_speed_fault", "CanInRaw.v_Vehicle2Fault", 2, 2),
+        Field::new("vehicle_speed", "CanInRaw.v_Vehicle2", 8, 16),
+        Field::new("boost_meter_status", "ActualIn.ST_BoostMeter", 24, 1),
+    ];
+}
+
+#[derive(Debug, Default, PartialEq, DekuRead, DekuWrite)]
+#[deku(endian = "big")]
+pub struct FuelLevel {
+    // CanInRaw.V_FuelTankFault
+    #[deku(pad_bits_before = "6", bits = 2)]
+    pub fuel_level_fault: u8,
+
+    // CanInRaw.V_FuelTank
+    #[deku(pad_bits_before = "32")]
+    pub fuel_level: u16,
+}
+
+impl Message for FuelLevel {
+    const CAN_ID: u32 = 0x631;
+}
+
+impl Layout for FuelLevel {
+    const NAME: &'static str = "Fuel level";
+    const FIELDS: &'static [Field] = &[
+        Field::new("fuel_level_fault", "CanInRaw.V_FuelTankFault", 6, 2),
+        Field::new("fuel_level", "CanInRaw.V_FuelTank", 40, 16),
+    ];
+}
+
+/// Experimental, the id and layout are a guess.
+#[derive(Debug, Default, PartialEq, DekuRead, DekuWrite)]
+#[deku(endian = "big")]
+pub struct Distance {
+    #[deku(pad_bytes_before = "1", bits = 24)]
+    pub total_km: u32,
+
+    /// In units of 100 m
+    #[deku(pad_bytes_after = "2")]
+    pub trip_hm: u16,
+}
+
+impl Message for Distance {
+    const CAN_ID: u32 = 0x3b0;
+}
+
+impl Layout for Distance {
+    const NAME: &'static str = "Distance";
+    const FIELDS: &'static [Field] = &[
+        Field::new("total_km", "", 8, 24),
+        Field::new("trip_hm", "", 32, 16),
+    ];
+}
+
+/// The buttons next to the SID. Experimental, the id and layout are a guess.
+#[derive(Debug, Default, PartialEq, DekuRead, DekuWrite)]
+#[deku(endian = "big")]
+pub struct SidButtons {
+    #[deku(bits = 1)]
+    pub clear: u8,
+
+    #[deku(bits = 1)]
+    pub set: u8,
+
+    #[deku(bits = 1)]
+    pub down: u8,
+
+    #[deku(bits = 1)]
+    pub up: u8,
+
+    #[deku(bits = 1, pad_bits_after = "59")]
+    pub night_panel: u8,
+}
+
+impl Message for SidButtons {
+    const CAN_ID: u32 = 0x290;
+}
+
+impl Layout for SidButtons {
+    const NAME: &'static str = "SID buttons";
+    const FIELDS: &'static [Field] = &[
+        Field::new("clear", "", 0, 1),
+        Field::new("set", "", 1, 1),
+        Field::new("down", "", 2, 1),
+        Field::new("up", "", 3, 1),
+        Field::new("night_panel", "", 4, 1),
+    ];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::tests::assert_layout;
+    use crate::tests::{assert_bit_positions, assert_frame_round_trip};
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn vehicle_speed_round_trips(
+            vehicle_speed_fault in 0..4_u8,
+            vehicle_speed in any::<u16>(),
+            boost_meter_status in 0..2_u8,
+        ) {
+            assert_frame_round_trip(&VehicleSpeed {
+                vehicle_speed_fault,
+                vehicle_speed,
+                boost_meter_status,
+            });
+        }
+
+        #[test]
+        fn fuel_level_round_trips(fuel_level_fault in 0..4_u8, fuel_level in any::<u16>()) {
+            assert_frame_round_trip(&FuelLevel {
+                fuel_level_fault,
+                fuel_level,
+            });
+        }
+    }
+
+    #[rustfmt::skip]
+    #[test]
+    fn vehicle_speed_fields_are_at_their_bit_positions() {
+        assert_bit_positions(&[
+            (VehicleSpeed { vehicle_speed_fault: 3, ..Default::default() }, 0x3000_0000_0000_0000),
+            (VehicleSpeed { vehicle_speed: u16::MAX, ..Default::default() }, 0x00ff_ff00_0000_0000),
+            (VehicleSpeed { boost_meter_status: 1, ..Default::default() }, 0x0000_0080_0000_0000),
+        ]);
+    }
+
+    #[rustfmt::skip]
+    #[test]
+    fn fuel_level_fields_are_at_their_bit_positions() {
+        assert_bit_positions(&[
+            (FuelLevel { fuel_level_fault: 3, ..Default::default() }, 0x0300_0000_0000_0000),
+            (FuelLevel { fuel_level: u16::MAX, ..Default::default() }, 0x0000_0000_00ff_ff00),
+        ]);
+    }
+
+    #[test]
+    fn vehicle_speed_layout_matches_encoding() {
+        assert_layout::<VehicleSpeed>();
+    }
+
+    #[test]
+    fn fuel_level_layout_matches_encoding() {
+        assert_layout::<FuelLevel>();
+    }
+
+    #[test]
+    fn experimental_message_layouts_match_encoding() {
+        assert_layout::<Distance>();
+        assert_layout::<SidButtons>();
+    }
+
+    #[rustfmt::skip]
+    #[test]
+    fn experimental_message_fields_are_at_their_bit_positions() {
+        assert_bit_positions(&[
+            (Distance { total_km: 0xff_ffff, ..Default::default() }, 0x00ff_ffff_0000_0000),
+            (Distance { trip_hm: u16::MAX, ..Default::default() }, 0x0000_0000_ffff_0000),
+        ]);
+        assert_bit_positions(&[
+            (SidButtons { clear: 1, ..Default::default() }, 0x8000_0000_0000_0000),
+            (SidButtons { night_panel: 1, ..Default::default() }, 0x0800_0000_0000_0000),
+        ]);
+    }
+}
@@ -0,0 +1,161 @@
+//! Text messages to and from the Saab Information Display (SID).
+//!
+//! A node asks for the display with a [`TextRequest`], the SID answers with a [`TextGrant`] naming
+//! the node that may write, and the text follows in [`TextFrame`]s. The layouts come from captured
+//! frames.
+use alloc::vec::Vec;
+use deku::prelude::*;
+
+use crate::layout::{Field, Layout};
+use crate::Message;
+
+/// Number of characters in a single text frame.
+pub const CHARS_PER_FRAME: usize = 5;
+
+#[derive(Debug, Default, PartialEq, DekuRead, DekuWrite)]
+#[deku(endian = "big")]
+pub struct TextRequest {
+    pub source: u8,
+
+    // Always 0x02 in captured frames
+    pub unknown: u8,
+
+    /// Bit mask of requested rows, 0 releases the display.
+    pub rows: u8,
+
+    /// Lower values win the arbitration.
+    #[deku(pad_bytes_after = "4")]
+    pub priority: u8,
+}
+
+impl Message for TextRequest {
+    const CAN_ID: u32 = 0x348;
+}
+
+impl Layout for TextRequest {
+    const NAME: &'static str = "SID text request";
+    const FIELDS: &'static [Field] = &[
+        Field::new("source", "", 0, 8),
+        Field::new("unknown", "", 8, 8),
+        Field::new("rows", "", 16, 8),
+        Field::new("priority", "", 24, 8),
+    ];
+}
+
+#[derive(Debug, Default, PartialEq, DekuRead, DekuWrite)]
+#[deku(endian = "big")]
+pub struct TextGrant {
+    /// Bit mask of granted rows
+    pub rows: u8,
+
+    /// The node that is allowed to write
+    #[deku(pad_bytes_after = "6")]
+    pub source: u8,
+}
+
+impl Message for TextGrant {
+    const CAN_ID: u32 = 0x368;
+}
+
+impl Layout for TextGrant {
+    const NAME: &'static str = "SID text grant";
+    const FIELDS: &'static [Field] =
+        &[Field::new("rows", "", 0, 8), Field::new("source", "", 8, 8)];
+}
+
+#[derive(Debug, Default, PartialEq, DekuRead, DekuWrite)]
+#[deku(endian = "big")]
+pub struct TextFrame {
+    /// Set on the first frame of a burst
+    #[deku(pad_bits_before = "1", bits = 1)]
+    pub first: u8,
+
+    /// Number of frames that follow this one
+    #[deku(bits = 6)]
+    pub remaining: u8,
+
+    pub address: u8,
+
+    /// Set when the text differs from the previous burst
+    #[deku(bits = 1)]
+    pub changed: u8,
+
+    #[deku(pad_bits_before = "5", bits = 2)]
+    pub row: u8,
+
+    pub text: [u8; CHARS_PER_FRAME],
+}
+
+impl Message for TextFrame {
+    const CAN_ID: u32 = 0x328;
+}
+
+impl Layout for TextFrame {
+    const NAME: &'static str = "SID text";
+    const FIELDS: &'static [Field] = &[
+        Field::new("first", "", 1, 1),
+        Field::new("remaining", "", 2, 6),
+        Field::new("address", "", 8, 8),
+        Field::new("changed", "", 16, 1),
+        Field::new("row", "", 22, 2),
+        Field::new("text", "", 24, 40),
+    ];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::tests::assert_layout;
+    use crate::tests::{assert_bit_positions, assert_frame_round_trip};
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn text_request_round_trips(bytes in any::<[u8; 4]>()) {
+            assert_frame_round_trip(&TextRequest {
+                source: bytes[0],
+                unknown: bytes[1],
+                rows: bytes[2],
+                priority: bytes[3],
+            });
+        }
+
+        #[test]
+        fn text_frame_round_trips(
+            bits in any::<[bool; 2]>(),
+            remaining in 0..64_u8,
+            address in any::<u8>(),
+            row in 0..4_u8,
+            text in any::<[u8; CHARS_PER_FRAME]>(),
+        ) {
+            assert_frame_round_trip(&TextFrame {
+                first: bits[0].into(),
+                remaining,
+                address,
+                changed: bits[1].into(),
+                row,
+                text,
+            });
+        }
+    }
+
+    #[rustfmt::skip]
+    #[test]
+    fn text_frame_fields_are_at_their_bit_positions() {
+        assert_bit_positions(&[
+            (TextFrame { first: 1, ..Default::default() }, 0x4000_0000_0000_0000),
+            (TextFrame { remaining: 0x3f, ..Default::default() }, 0x3f00_0000_0000_0000),
+            (TextFrame { address: 0x96, ..Default::default() }, 0x0096_0000_0000_0000),
+            (TextFrame { changed: 1, ..Default::default() }, 0x0000_8000_0000_0000),
+            (TextFrame { row: 3, ..Default::default() }, 0x0000_0300_0000_0000),
+            (TextFrame { text: *b"HELLO", ..Default::default() }, 0x0000_0048_454c_4c4f),
+        ]);
+    }
+
+    #[test]
+    fn text_layouts_match_encoding() {
+        assert_layout::<TextRequest>();
+        assert_layout::<TextGrant>();
+        assert_layout::<TextFrame>();
+    }
+}
@@ -0,0 +1,63 @@
+//! Conversions between the raw values in the messages and physical units.
+
+/// Coolant temperatures are sent in °C plus this offset, so freezing temperatures fit a byte.
+pub const COOLANT_TEMPERATURE_OFFSET: i16 = 40;
+
+/// The vehicle speed and fuel level are sent in tenths, as far as we know.
+const TENTHS: u16 = 10;
+
+pub fn coolant_temperature(raw: u8) -> i16 {
+    i16::from(raw) - COOLANT_TEMPERATURE_OFFSET
+}
+
+/// The raw value for `celsius`, limited to what fits.
+pub fn coolant_temperature_raw(celsius: i16) -> u8 {
+    (celsius + COOLANT_TEMPERATURE_OFFSET).clamp(0, i16::from(u8::MAX)) as u8
+}
+
+/// km/h
+pub fn vehicle_speed(raw: u16) -> f32 {
+    f32::from(raw) / f32::from(TENTHS)
+}
+
+pub fn vehicle_speed_raw(kmh: u16) -> u16 {
+    kmh.saturating_mul(TENTHS)
+}
+
+/// Litres
+pub fn fuel_level(raw: u16) -> f32 {
+    f32::from(raw) / f32::from(TENTHS)
+}
+
+pub fn fuel_level_raw(litres: u16) -> u16 {
+    litres.saturating_mul(TENTHS)
+}
+
+/// The position of the boost gauge, from 0 to 1.
+pub fn boost(raw: u8) -> f32 {
+    f32::from(raw) / f32::from(u8::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coolant_temperature_round_trips() {
+        for celsius in -40..=215 {
+            assert_eq!(
+                coolant_temperature(coolant_temperature_raw(celsius)),
+                celsius
+            );
+        }
+        assert_eq!(coolant_temperature_raw(-50), 0);
+        assert_eq!(coolant_temperature_raw(300), u8::MAX);
+    }
+
+    #[test]
+    fn it_converts_tenths() {
+        assert_eq!(vehicle_speed(vehicle_speed_raw(88)), 88.0);
+        assert_eq!(vehicle_speed_raw(u16::MAX), u16::MAX);
+        assert_eq!(fuel_level(fuel_level_raw(65)), 65.0);
+    }
+}
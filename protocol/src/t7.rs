@@ -0,0 +1,522 @@
+use alloc::vec::Vec;
+use deku::prelude::*;
+
+use crate::layout::{Field, Layout};
+use crate::Message;
+
+#[derive(Debug, Default, PartialEq, DekuRead, DekuWrite)]
+#[deku(endian = "big")]
+pub struct EngineSpeedAndThrottle {
+    /// FaultCANOut.n_Engine
+    #[deku(pad_bits_before = "2", bits = 2)]
+    pub speed_fault: u8,
+
+    /// FaultCANOut.m_and_p_AirInlet
+    #[deku(bits = 2)]
+    pub air_inlet_fault: u8,
+
+    /// FaultCANOut.Throttle
+    #[deku(bits = 2)]
+    pub throttle_fault: u8,
+
+    /// Out.n_Engine
+    pub speed: u16,
+
+    /// bOut_M_Engine
+    pub torque: u8,
+
+    /// bOut_M_MaxAtActualRPM
+    pub max_torque_at_rpm: u8,
+
+    /// bOut_X_AccPedal_div10
+    pub accelerator_pedal_position: u8,
+
+    /// bOut_X_AccPedal_shr2
+    pub accelerator_pedal_position_gradient: u8,
+
+    /// bOut_M_DTI
+    pub dti: u8,
+}
+
+impl Message for EngineSpeedAndThrottle {
+    const CAN_ID: u32 = 0x1A0;
+}
+
+impl Layout for EngineSpeedAndThrottle {
+    const NAME: &'static str = "Engine speed and throttle";
+    const FIELDS: &'static [Field] = &[
+        Field::new("speed_fault", "FaultCANOut.n_Engine", 2, 2),
+        Field::new("air_inlet_fault", "FaultCANOut.m_and_p_AirInlet", 4, 2),
+        Field::new("throttle_fault", "FaultCANOut.Throttle", 6, 2),
+        Field::new("speed", "Out.n_Engine", 8, 16),
+        Field::new("torque", "bOut_M_Engine", 24, 8),
+        Field::new("max_torque_at_rpm", "bOut_M_MaxAtActualRPM", 32, 8),
+        Field::new("accelerator_pedal_position", "bOut_X_AccPedal_div10", 40, 8),
+        Field::new(
+            "accelerator_pedal_position_gradient",
+            "bOut_X_AccPedal_shr2",
+            48,
+            8,
+        ),
+        Field::new("dti", "bOut_M_DTI", 56, 8),
+    ];
+}
+
+#[derive(Debug, Default, PartialEq, DekuRead, DekuWrite)]
+#[deku(endian = "big")]
+pub struct EngineStatus {
+    #[deku(pad_bits_before = "2", bits = 2)]
+    pub vehicle_speed_fault: u8,
+
+    #[deku(bits = 1)]
+    pub brake_light_status: u8,
+
+    #[deku(pad_bits_before = "3")]
+    pub actual_gear: u8,
+
+    #[deku(pad_bits_before = "1", bits = 1)]
+    pub cruise_active: u8,
+
+    #[deku(bits = 1)]
+    pub no_ignition_retard: u8,
+
+    #[deku(bits = 1)]
+    pub kick_down: u8,
+
+    #[deku(bits = 1)]
+    pub clutch_brake: u8,
+
+    #[deku(bits = 1)]
+    pub jerk: u8,
+
+    #[deku(bits = 1)]
+    pub brake_light: u8,
+
+    #[deku(bits = 1)]
+    pub warm_up_shift_pattern: u8,
+
+    #[deku(bits = 1)]
+    pub check_filler_cap: u8,
+
+    #[deku(bits = 1)]
+    pub warm_up_cycle: u8,
+
+    #[deku(bits = 1)]
+    pub automatic: u8,
+
+    #[deku(bits = 1)]
+    pub nc_inhibit: u8,
+
+    #[deku(bits = 1)]
+    pub gear_shift_inhibit: u8,
+
+    #[deku(bits = 1)]
+    pub ac_relay: u8,
+
+    #[deku(bits = 1)]
+    pub e_gas_off: u8,
+
+    #[deku(bits = 1)]
+    pub limp_home: u8,
+
+    #[deku(bits = 1)]
+    pub check_engine: u8,
+
+    #[deku(bits = 1)]
+    pub shift_up: u8,
+
+    #[deku(bits = 1)]
+    pub cruise_lamp: u8,
+
+    #[deku(bits = 1)]
+    pub rep: u8,
+
+    #[deku(pad_bits_before = "4", bits = 1)]
+    pub engine_started: u8,
+
+    #[deku(bits = 1)]
+    pub cruise_included: u8,
+
+    #[deku(pad_bits_before = "6")]
+    pub engine_type: u8,
+
+    #[deku(bits = 1)]
+    pub coast_lu_inhibit: u8,
+}
+
+impl Message for EngineStatus {
+    const CAN_ID: u32 = 0x280;
+}
+
+impl Layout for EngineStatus {
+    const NAME: &'static str = "Engine status";
+    const FIELDS: &'static [Field] = &[
+        Field::new("vehicle_speed_fault", "", 2, 2),
+        Field::new("brake_light_status", "", 4, 1),
+        Field::new("actual_gear", "", 8, 8),
+        Field::new("cruise_active", "", 17, 1),
+        Field::new("no_ignition_retard", "", 18, 1),
+        Field::new("kick_down", "", 19, 1),
+        Field::new("clutch_brake", "", 20, 1),
+        Field::new("jerk", "", 21, 1),
+        Field::new("brake_light", "", 22, 1),
+        Field::new("warm_up_shift_pattern", "", 23, 1),
+        Field::new("check_filler_cap", "", 24, 1),
+        Field::new("warm_up_cycle", "", 25, 1),
+        Field::new("automatic", "", 26, 1),
+        Field::new("nc_inhibit", "", 27, 1),
+        Field::new("gear_shift_inhibit", "", 28, 1),
+        Field::new("ac_relay", "", 29, 1),
+        Field::new("e_gas_off", "", 30, 1),
+        Field::new("limp_home", "", 31, 1),
+        Field::new("check_engine", "", 32, 1),
+        Field::new("shift_up", "", 33, 1),
+        Field::new("cruise_lamp", "", 34, 1),
+        Field::new("rep", "", 35, 1),
+        Field::new("engine_started", "", 40, 1),
+        Field::new("cruise_included", "", 41, 1),
+        Field::new("engine_type", "", 48, 8),
+        Field::new("coast_lu_inhibit", "", 56, 1),
+    ];
+}
+
+#[derive(Debug, Default, PartialEq, DekuRead, DekuWrite)]
+#[deku(endian = "big")]
+pub struct AirAndCoolant {
+    // FaultCANOut.T_CoolingSystem
+    #[deku(pad_bits_before = "2", bits = 2)]
+    pub coolant_temperature_1_fault: u8,
+
+    // FaultCANOut.T_CoolingSystem
+    #[deku(bits = 2)]
+    pub coolant_temperature_2_fault: u8,
+
+    // FaultCANOut.p_AirAmbient
+    #[deku(bits = 2)]
+    pub ambient_air_pressure_fault: u8,
+
+    // bOut_T_Engine_plus40
+    pub coolant_temperature_1_plus_40: u8,
+
+    // bOut_T_Engine_plus40
+    pub coolant_temperature_2_plus_40: u8,
+
+    // Out.p_AirBarometric
+    pub ambient_air_pressure: u16,
+}
+
+impl Message for AirAndCoolant {
+    const CAN_ID: u32 = 0x5C0;
+}
+
+impl Layout for AirAndCoolant {
+    const NAME: &'static str = "Air and coolant";
+    const FIELDS: &'static [Field] = &[
+        Field::new(
+            "coolant_temperature_1_fault",
+            "FaultCANOut.T_CoolingSystem",
+            2,
+            2,
+        ),
+        Field::new(
+            "coolant_temperature_2_fault",
+            "FaultCANOut.T_CoolingSystem",
+            4,
+            2,
+        ),
+        Field::new(
+            "ambient_air_pressure_fault",
+            "FaultCANOut.p_AirAmbient",
+            6,
+            2,
+        ),
+        Field::new(
+            "coolant_temperature_1_plus_40",
+            "bOut_T_Engine_plus40",
+            8,
+            8,
+        ),
+        Field::new(
+            "coolant_temperature_2_plus_40",
+            "bOut_T_Engine_plus40",
+            16,
+            8,
+        ),
+        Field::new("ambient_air_pressure", "Out.p_AirBarometric", 24, 16),
+    ];
+}
+
+#[derive(Debug, Default, PartialEq, DekuRead, DekuWrite)]
+#[deku(endian = "big")]
+pub struct FuelConsumptionAndBoost {
+    // FaultCANOut.ST_IgnOn
+    #[deku(pad_bits_before = "2", bits = 2)]
+    pub ignition_on_fault: u8,
+
+    // FaultCANOut.field_1
+    #[deku(bits = 2)]
+    pub unknown: u8,
+
+    // Out.V_FuelConsumed
+    #[deku(pad_bits_before = "2")]
+    pub fuel_consumed: u16,
+
+    // Out.X_BoostMeter
+    pub boost: u8,
+}
+
+impl Message for FuelConsumptionAndBoost {
+    const CAN_ID: u32 = 0x370;
+}
+
+impl Layout for FuelConsumptionAndBoost {
+    const NAME: &'static str = "Fuel consumption and boost";
+    const FIELDS: &'static [Field] = &[
+        Field::new("ignition_on_fault", "FaultCANOut.ST_IgnOn", 2, 2),
+        Field::new("unknown", "FaultCANOut.field_1", 4, 2),
+        Field::new("fuel_consumed", "Out.V_FuelConsumed", 8, 16),
+        Field::new("boost", "Out.X_BoostMeter", 24, 8),
+    ];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::tests::assert_layout;
+    use crate::tests::{assert_bit_positions, assert_frame_round_trip};
+    use proptest::prelude::*;
+
+    fn bit(bits: u32, index: u32) -> u8 {
+        ((bits >> index) & 1) as u8
+    }
+
+    prop_compose! {
+        fn engine_speed_and_throttle()(
+            faults in [0..4_u8, 0..4_u8, 0..4_u8],
+            speed in any::<u16>(),
+            bytes in any::<[u8; 5]>(),
+        ) -> EngineSpeedAndThrottle {
+            EngineSpeedAndThrottle {
+                speed_fault: faults[0],
+                air_inlet_fault: faults[1],
+                throttle_fault: faults[2],
+                speed,
+                torque: bytes[0],
+                max_torque_at_rpm: bytes[1],
+                accelerator_pedal_position: bytes[2],
+                accelerator_pedal_position_gradient: bytes[3],
+                dti: bytes[4],
+            }
+        }
+    }
+
+    prop_compose! {
+        fn engine_status()(
+            vehicle_speed_fault in 0..4_u8,
+            actual_gear in any::<u8>(),
+            engine_type in any::<u8>(),
+            bits in any::<u32>(),
+        ) -> EngineStatus {
+            EngineStatus {
+                vehicle_speed_fault,
+                brake_light_status: bit(bits, 0),
+                actual_gear,
+                cruise_active: bit(bits, 1),
+                no_ignition_retard: bit(bits, 2),
+                kick_down: bit(bits, 3),
+                clutch_brake: bit(bits, 4),
+                jerk: bit(bits, 5),
+                brake_light: bit(bits, 6),
+                warm_up_shift_pattern: bit(bits, 7),
+                check_filler_cap: bit(bits, 8),
+                warm_up_cycle: bit(bits, 9),
+                automatic: bit(bits, 10),
+                nc_inhibit: bit(bits, 11),
+                gear_shift_inhibit: bit(bits, 12),
+                ac_relay: bit(bits, 13),
+                e_gas_off: bit(bits, 14),
+                limp_home: bit(bits, 15),
+                check_engine: bit(bits, 16),
+                shift_up: bit(bits, 17),
+                cruise_lamp: bit(bits, 18),
+                rep: bit(bits, 19),
+                engine_started: bit(bits, 20),
+                cruise_included: bit(bits, 21),
+                engine_type,
+                coast_lu_inhibit: bit(bits, 22),
+            }
+        }
+    }
+
+    prop_compose! {
+        fn air_and_coolant()(
+            faults in [0..4_u8, 0..4_u8, 0..4_u8],
+            temperatures in any::<[u8; 2]>(),
+            ambient_air_pressure in any::<u16>(),
+        ) -> AirAndCoolant {
+            AirAndCoolant {
+                coolant_temperature_1_fault: faults[0],
+                coolant_temperature_2_fault: faults[1],
+                ambient_air_pressure_fault: faults[2],
+                coolant_temperature_1_plus_40: temperatures[0],
+                coolant_temperature_2_plus_40: temperatures[1],
+                ambient_air_pressure,
+            }
+        }
+    }
+
+    prop_compose! {
+        fn fuel_consumption_and_boost()(
+            ignition_on_fault in 0..4_u8,
+            unknown in 0..4_u8,
+            fuel_consumed in any::<u16>(),
+            boost in any::<u8>(),
+        ) -> FuelConsumptionAndBoost {
+            FuelConsumptionAndBoost {
+                ignition_on_fault,
+                unknown,
+                fuel_consumed,
+                boost,
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn engine_speed_and_throttle_round_trips(message in engine_speed_and_throttle()) {
+            assert_frame_round_trip(&message);
+        }
+
+        #[test]
+        fn engine_status_round_trips(message in engine_status()) {
+            assert_frame_round_trip(&message);
+        }
+
+        #[test]
+        fn air_and_coolant_round_trips(message in air_and_coolant()) {
+            assert_frame_round_trip(&message);
+        }
+
+        #[test]
+        fn fuel_consumption_and_boost_round_trips(message in fuel_consumption_and_boost()) {
+            assert_frame_round_trip(&message);
+        }
+    }
+
+    #[rustfmt::skip]
+    #[test]
+    fn engine_speed_and_throttle_fields_are_at_their_bit_positions() {
+        assert_bit_positions(&[
+            (EngineSpeedAndThrottle { speed_fault: 3, ..Default::default() }, 0x3000_0000_0000_0000),
+            (EngineSpeedAndThrottle { air_inlet_fault: 3, ..Default::default() }, 0x0c00_0000_0000_0000),
+            (EngineSpeedAndThrottle { throttle_fault: 3, ..Default::default() }, 0x0300_0000_0000_0000),
+            (EngineSpeedAndThrottle { speed: u16::MAX, ..Default::default() }, 0x00ff_ff00_0000_0000),
+            (EngineSpeedAndThrottle { torque: u8::MAX, ..Default::default() }, 0x0000_00ff_0000_0000),
+            (EngineSpeedAndThrottle { max_torque_at_rpm: u8::MAX, ..Default::default() }, 0x0000_0000_ff00_0000),
+            (EngineSpeedAndThrottle { accelerator_pedal_position: u8::MAX, ..Default::default() }, 0x0000_0000_00ff_0000),
+            (EngineSpeedAndThrottle { accelerator_pedal_position_gradient: u8::MAX, ..Default::default() }, 0x0000_0000_0000_ff00),
+            (EngineSpeedAndThrottle { dti: u8::MAX, ..Default::default() }, 0x0000_0000_0000_00ff),
+        ]);
+    }
+
+    #[rustfmt::skip]
+    #[test]
+    fn engine_status_fields_are_at_their_bit_positions() {
+        assert_bit_positions(&[
+            (EngineStatus { vehicle_speed_fault: 3, ..Default::default() }, 0x3000_0000_0000_0000),
+            (EngineStatus { brake_light_status: 1, ..Default::default() }, 0x0800_0000_0000_0000),
+            (EngineStatus { actual_gear: u8::MAX, ..Default::default() }, 0x00ff_0000_0000_0000),
+            (EngineStatus { cruise_active: 1, ..Default::default() }, 0x0000_4000_0000_0000),
+            (EngineStatus { no_ignition_retard: 1, ..Default::default() }, 0x0000_2000_0000_0000),
+            (EngineStatus { kick_down: 1, ..Default::default() }, 0x0000_1000_0000_0000),
+            (EngineStatus { clutch_brake: 1, ..Default::default() }, 0x0000_0800_0000_0000),
+            (EngineStatus { jerk: 1, ..Default::default() }, 0x0000_0400_0000_0000),
+            (EngineStatus { brake_light: 1, ..Default::default() }, 0x0000_0200_0000_0000),
+            (EngineStatus { warm_up_shift_pattern: 1, ..Default::default() }, 0x0000_0100_0000_0000),
+            (EngineStatus { check_filler_cap: 1, ..Default::default() }, 0x0000_0080_0000_0000),
+            (EngineStatus { warm_up_cycle: 1, ..Default::default() }, 0x0000_0040_0000_0000),
+            (EngineStatus { automatic: 1, ..Default::default() }, 0x0000_0020_0000_0000),
+            (EngineStatus { nc_inhibit: 1, ..Default::default() }, 0x0000_0010_0000_0000),
+            (EngineStatus { gear_shift_inhibit: 1, ..Default::default() }, 0x0000_0008_0000_0000),
+            (EngineStatus { ac_relay: 1, ..Default::default() }, 0x0000_0004_0000_0000),
+            (EngineStatus { e_gas_off: 1, ..Default::default() }, 0x0000_0002_0000_0000),
+            (EngineStatus { limp_home: 1, ..Default::default() }, 0x0000_0001_0000_0000),
+            (EngineStatus { check_engine: 1, ..Default::default() }, 0x0000_0000_8000_0000),
+            (EngineStatus { shift_up: 1, ..Default::default() }, 0x0000_0000_4000_0000),
+            (EngineStatus { cruise_lamp: 1, ..Default::default() }, 0x0000_0000_2000_0000),
+            (EngineStatus { rep: 1, ..Default::default() }, 0x0000_0000_1000_0000),
+            (EngineStatus { engine_started: 1, ..Default::default() }, 0x0000_0000_0080_0000),
+            (EngineStatus { cruise_included: 1, ..Default::default() }, 0x0000_0000_0040_0000),
+            (EngineStatus { engine_type: u8::MAX, ..Default::default() }, 0x0000_0000_0000_ff00),
+            (EngineStatus { coast_lu_inhibit: 1, ..Default::default() }, 0x0000_0000_0000_0080),
+        ]);
+    }
+
+    #[rustfmt::skip]
+    #[test]
+    fn air_and_coolant_fields_are_at_their_bit_positions() {
+        assert_bit_positions(&[
+            (AirAndCoolant { coolant_temperature_1_fault: 3, ..Default::default() }, 0x3000_0000_0000_0000),
+            (AirAndCoolant { coolant_temperature_2_fault: 3, ..Default::default() }, 0x0c00_0000_0000_0000),
+            (AirAndCoolant { ambient_air_pressure_fault: 3, ..Default::default() }, 0x0300_0000_0000_0000),
+            (AirAndCoolant { coolant_temperature_1_plus_40: u8::MAX, ..Default::default() }, 0x00ff_0000_0000_0000),
+            (AirAndCoolant { coolant_temperature_2_plus_40: u8::MAX, ..Default::default() }, 0x0000_ff00_0000_0000),
+            (AirAndCoolant { ambient_air_pressure: u16::MAX, ..Default::default() }, 0x0000_00ff_ff00_0000),
+        ]);
+    }
+
+    #[rustfmt::skip]
+    #[test]
+    fn fuel_consumption_and_boost_fields_are_at_their_bit_positions() {
+        assert_bit_positions(&[
+            (FuelConsumptionAndBoost { ignition_on_fault: 3, ..Default::default() }, 0x3000_0000_0000_0000),
+            (FuelConsumptionAndBoost { unknown: 3, ..Default::default() }, 0x0c00_0000_0000_0000),
+            (FuelConsumptionAndBoost { fuel_consumed: u16::MAX, ..Default::default() }, 0x00ff_ff00_0000_0000),
+            (FuelConsumptionAndBoost { boost: u8::MAX, ..Default::default() }, 0x0000_00ff_0000_0000),
+        ]);
+    }
+
+    #[test]
+    fn it_encodes_a_warm_idling_engine() {
+        let engine = EngineSpeedAndThrottle {
+            speed: 850,
+            torque: 12,
+            ..Default::default()
+        };
+        assert_eq!(
+            engine.to_bytes().unwrap(),
+            [0x00, 0x03, 0x52, 0x0c, 0x00, 0x00, 0x00, 0x00]
+        );
+
+        let air_and_coolant = AirAndCoolant {
+            coolant_temperature_1_plus_40: 90 + 40,
+            coolant_temperature_2_plus_40: 90 + 40,
+            ambient_air_pressure: 1013,
+            ..Default::default()
+        };
+        assert_eq!(
+            air_and_coolant.to_bytes().unwrap(),
+            [0x00, 0x82, 0x82, 0x03, 0xf5]
+        );
+    }
+
+    #[test]
+    fn engine_speed_and_throttle_layout_matches_encoding() {
+        assert_layout::<EngineSpeedAndThrottle>();
+    }
+
+    #[test]
+    fn engine_status_layout_matches_encoding() {
+        assert_layout::<EngineStatus>();
+    }
+
+    #[test]
+    fn air_and_coolant_layout_matches_encoding() {
+        assert_layout::<AirAndCoolant>();
+    }
+
+    #[test]
+    fn fuel_consumption_and_boost_layout_matches_encoding() {
+        assert_layout::<FuelConsumptionAndBoost>();
+    }
+}
@@ -0,0 +1,276 @@
+use alloc::vec::Vec;
+use deku::prelude::*;
+
+use crate::layout::{Field, Layout};
+use crate::Message;
+
+/// Gear lever position or actual gear as reported by the TCM and T7.
+///
+/// Automatic gearboxes report the lever position (P, R, N, D and the limited ranges). What manual
+/// cars report is not known, so their gears aren't named until a capture from a manual car shows
+/// the codes. Values that are not known are kept in `Unknown` so they survive a decode/encode
+/// round trip.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DekuRead, DekuWrite)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[deku(
+    id_type = "u8",
+    endian = "endian",
+    ctx = "endian: deku::ctx::Endian",
+    ctx_default = "deku::ctx::Endian::Big"
+)]
+pub enum Gear {
+    #[deku(id = "1")]
+    Park,
+    #[deku(id = "2")]
+    Reverse,
+    #[deku(id = "3")]
+    Neutral,
+    #[deku(id = "4")]
+    Drive,
+    #[deku(id = "5")]
+    Limit1,
+    #[deku(id = "6")]
+    Limit2,
+    #[deku(id = "7")]
+    Limit3,
+    #[deku(id_pat = "_")]
+    Unknown(u8),
+}
+
+impl Gear {
+    /// All known gears, in order of their raw value.
+    pub const ALL: [Gear; 7] = [
+        Gear::Park,
+        Gear::Reverse,
+        Gear::Neutral,
+        Gear::Drive,
+        Gear::Limit1,
+        Gear::Limit2,
+        Gear::Limit3,
+    ];
+
+    pub fn iter() -> impl Iterator<Item = Gear> {
+        Self::ALL.into_iter()
+    }
+}
+
+impl Default for Gear {
+    fn default() -> Self {
+        Gear::Unknown(0)
+    }
+}
+
+impl From<u8> for Gear {
+    fn from(id: u8) -> Self {
+        match id {
+            1 => Gear::Park,
+            2 => Gear::Reverse,
+            3 => Gear::Neutral,
+            4 => Gear::Drive,
+            5 => Gear::Limit1,
+            6 => Gear::Limit2,
+            7 => Gear::Limit3,
+            id => Gear::Unknown(id),
+        }
+    }
+}
+
+impl From<Gear> for u8 {
+    fn from(gear: Gear) -> Self {
+        match gear {
+            Gear::Park => 1,
+            Gear::Reverse => 2,
+            Gear::Neutral => 3,
+            Gear::Drive => 4,
+            Gear::Limit1 => 5,
+            Gear::Limit2 => 6,
+            Gear::Limit3 => 7,
+            Gear::Unknown(id) => id,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, DekuRead, DekuWrite)]
+#[deku(endian = "big")]
+pub struct TransmissionStatus {
+    // CanInRaw.X_ActualGearFault
+    #[deku(pad_bits_before = "2", bits = 2)]
+    pub actual_gear_fault: u8,
+
+    // CanInRaw.X_GearLeverFault
+    #[deku(bits = 2)]
+    pub gear_lever_fault: u8,
+
+    // CanInRaw.X_ActualGear
+    #[deku(pad_bits_before = "2")]
+    pub actual_gear: Gear,
+
+    // CanInRaw.X_GearLever
+    pub gear_lever: Gear,
+
+    // Check gearbox
+    #[deku(bits = 1)]
+    pub check_gearbox: u8,
+
+    // ActualIn.ST_TCMSport
+    #[deku(bits = 1)]
+    pub sport: u8,
+
+    // TCMWinter
+    #[deku(bits = 1)]
+    pub winter: u8,
+
+    // CanInRaw.ST_Interv bit 6
+    #[deku(bits = 1)]
+    pub unknown: u8,
+
+    // ActualIn.ST_TCMFreezeFrameReq
+    #[deku(pad_bits_before = "2", bits = 1)]
+    pub freeze_frame_request: u8,
+
+    // ActualIn.ST_CheckEngine
+    #[deku(bits = 1)]
+    pub check_engine: u8,
+
+    // CanInRaw.ST_TCMCSLU
+    #[deku(pad_bits_before = "1", bits = 1)]
+    pub tcm_cslu: u8,
+
+    // CanInRaw.field_33
+    #[deku(pad_bits_before = "5")]
+    pub unknown2: u8,
+}
+
+impl Message for TransmissionStatus {
+    const CAN_ID: u32 = 0x3E0;
+}
+
+impl Layout for TransmissionStatus {
+    const NAME: &'static str = "Transmission status";
+    const FIELDS: &'static [Field] = &[
+        Field::new("actual_gear_fault", "CanInRaw.X_ActualGearFault", 2, 2),
+        Field::new("gear_lever_fault", "CanInRaw.X_GearLeverFault", 4, 2),
+        Field::new("actual_gear", "CanInRaw.X_ActualGear", 8, 8),
+        Field::new("gear_lever", "CanInRaw.X_GearLever", 16, 8),
+        Field::new("check_gearbox", "", 24, 1),
+        Field::new("sport", "ActualIn.ST_TCMSport", 25, 1),
+        Field::new("winter", "TCMWinter", 26, 1),
+        Field::new("unknown", "CanInRaw.ST_Interv bit 6", 27, 1),
+        Field::new(
+            "freeze_frame_request",
+            "ActualIn.ST_TCMFreezeFrameReq",
+            30,
+            1,
+        ),
+        Field::new("check_engine", "ActualIn.ST_CheckEngine", 31, 1),
+        Field::new("tcm_cslu", "CanInRaw.ST_TCMCSLU", 33, 1),
+        Field::new("unknown2", "CanInRaw.field_33", 39, 8),
+    ];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::tests::assert_layout;
+    use crate::tests::{assert_bit_positions, assert_frame_round_trip};
+    use proptest::prelude::*;
+
+    #[test]
+    fn it_round_trips_all_raw_gear_values() {
+        for raw in 0..=u8::MAX {
+            assert_eq!(u8::from(Gear::from(raw)), raw);
+
+            let (_, gear) = Gear::from_bytes((&[raw], 0)).unwrap();
+            assert_eq!(gear, Gear::from(raw));
+            assert_eq!(gear.to_bytes().unwrap(), vec![raw]);
+        }
+    }
+
+    #[test]
+    fn it_keeps_unknown_raw_values() {
+        assert_eq!(Gear::from(0), Gear::Unknown(0));
+        assert_eq!(Gear::from(0x42), Gear::Unknown(0x42));
+        assert_eq!(Gear::default(), Gear::Unknown(0));
+    }
+
+    #[test]
+    fn it_lists_every_known_gear_once() {
+        let gears: Vec<Gear> = Gear::iter().collect();
+
+        assert_eq!(gears.len(), Gear::ALL.len());
+        assert!(!gears.iter().any(|gear| matches!(gear, Gear::Unknown(_))));
+        for (i, gear) in gears.iter().enumerate() {
+            assert!(!gears[i + 1..].contains(gear));
+        }
+    }
+
+    prop_compose! {
+        fn transmission_status()(
+            faults in [0..4_u8, 0..4_u8],
+            gears in any::<[u8; 2]>(),
+            bits in any::<[bool; 7]>(),
+            unknown2 in any::<u8>(),
+        ) -> TransmissionStatus {
+            TransmissionStatus {
+                actual_gear_fault: faults[0],
+                gear_lever_fault: faults[1],
+                actual_gear: Gear::from(gears[0]),
+                gear_lever: Gear::from(gears[1]),
+                check_gearbox: bits[0].into(),
+                sport: bits[1].into(),
+                winter: bits[2].into(),
+                unknown: bits[3].into(),
+                freeze_frame_request: bits[4].into(),
+                check_engine: bits[5].into(),
+                tcm_cslu: bits[6].into(),
+                unknown2,
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn transmission_status_round_trips(message in transmission_status()) {
+            assert_frame_round_trip(&message);
+        }
+    }
+
+    #[rustfmt::skip]
+    #[test]
+    fn transmission_status_fields_are_at_their_bit_positions() {
+        assert_bit_positions(&[
+            (TransmissionStatus { actual_gear_fault: 3, ..Default::default() }, 0x3000_0000_0000_0000),
+            (TransmissionStatus { gear_lever_fault: 3, ..Default::default() }, 0x0c00_0000_0000_0000),
+            (TransmissionStatus { actual_gear: Gear::Unknown(u8::MAX), ..Default::default() }, 0x00ff_0000_0000_0000),
+            (TransmissionStatus { gear_lever: Gear::Unknown(u8::MAX), ..Default::default() }, 0x0000_ff00_0000_0000),
+            (TransmissionStatus { check_gearbox: 1, ..Default::default() }, 0x0000_0080_0000_0000),
+            (TransmissionStatus { sport: 1, ..Default::default() }, 0x0000_0040_0000_0000),
+            (TransmissionStatus { winter: 1, ..Default::default() }, 0x0000_0020_0000_0000),
+            (TransmissionStatus { unknown: 1, ..Default::default() }, 0x0000_0010_0000_0000),
+            (TransmissionStatus { freeze_frame_request: 1, ..Default::default() }, 0x0000_0002_0000_0000),
+            (TransmissionStatus { check_engine: 1, ..Default::default() }, 0x0000_0001_0000_0000),
+            (TransmissionStatus { tcm_cslu: 1, ..Default::default() }, 0x0000_0000_4000_0000),
+            (TransmissionStatus { unknown2: u8::MAX, ..Default::default() }, 0x0000_0000_01fe_0000),
+        ]);
+    }
+
+    #[test]
+    fn it_encodes_drive_in_winter_mode() {
+        let transmission_status = TransmissionStatus {
+            actual_gear: Gear::Drive,
+            gear_lever: Gear::Drive,
+            winter: 1,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            transmission_status.to_bytes().unwrap(),
+            [0x00, 0x04, 0x04, 0x20, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn transmission_status_layout_matches_encoding() {
+        assert_layout::<TransmissionStatus>();
+    }
+}
@@ -0,0 +1,662 @@
+//! Helps figuring out what the bits in unknown messages mean.
+//!
+//! While recording, the user marks moments where the stimulus changes, like "pressing brake" and
+//! "releasing brake". Every received frame gets the label of the last mark before it. Bits and
+//! bytes that follow the labels are likely to carry the signal, so those are reported together
+//! with counters, checksums and constant bytes, which helps to separate the signal from the rest.
+//! Frames are recorded in the background, see [`record`], so none are missed while the window
+//! isn't drawn.
+use socketcan::{CanFrame, EmbeddedFrame, Frame};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::{Mutex, PoisonError, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+use crate::can::ReceivedFrame;
+
+/// Bits and bytes with a correlation ratio above this follow the stimulus.
+const CORRELATION_THRESHOLD: f64 = 0.8;
+
+/// Fraction of transitions that has to match for a byte to be considered a counter.
+const COUNTER_THRESHOLD: f64 = 0.95;
+
+/// Stop recording when there are this many frames, to keep memory use in check.
+const MAX_FRAMES: usize = 1_000_000;
+
+#[derive(Clone, Debug)]
+pub struct Mark {
+    pub time: Instant,
+    pub label: String,
+}
+
+struct Sample {
+    time: Instant,
+    data: Vec<u8>,
+}
+
+#[derive(Default)]
+pub struct Analyser {
+    marks: Vec<Mark>,
+    frames: BTreeMap<u32, Vec<Sample>>,
+    frame_count: usize,
+    recording: bool,
+}
+
+impl Analyser {
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Clears the previous recording and records the frames from [`record`] from now on.
+    pub fn start(&mut self) {
+        self.clear();
+        self.recording = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.recording = false;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    pub fn marks(&self) -> &[Mark] {
+        &self.marks
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.frame_count >= MAX_FRAMES
+    }
+
+    /// Starts a new window with `label` at `time`.
+    pub fn mark(&mut self, label: String, time: Instant) {
+        self.marks.push(Mark { time, label });
+    }
+
+    pub fn record(&mut self, frame: &CanFrame, time: Instant) {
+        if self.is_full() {
+            return;
+        }
+
+        self.frame_count += 1;
+        self.frames.entry(frame.raw_id()).or_default().push(Sample {
+            time,
+            data: frame.data().to_vec(),
+        });
+    }
+
+    fn label_at(&self, time: Instant) -> Option<&str> {
+        self.marks
+            .iter()
+            .rev()
+            .find(|mark| mark.time <= time)
+            .map(|mark| mark.label.as_str())
+    }
+
+    pub fn analyse(&self) -> Vec<IdReport> {
+        self.frames
+            .iter()
+            .map(|(id, samples)| self.analyse_id(*id, samples))
+            .collect()
+    }
+
+    fn analyse_id(&self, id: u32, samples: &[Sample]) -> IdReport {
+        let length = samples.iter().map(|s| s.data.len()).max().unwrap_or(0);
+        let byte = |sample: &Sample, index: usize| sample.data.get(index).copied().unwrap_or(0);
+
+        let period = match (samples.first(), samples.last()) {
+            (Some(first), Some(last)) if samples.len() > 1 => {
+                Some(last.time.duration_since(first.time) / (samples.len() as u32 - 1))
+            }
+            _ => None,
+        };
+
+        let labels: Vec<Option<&str>> = samples.iter().map(|s| self.label_at(s.time)).collect();
+
+        let mut report = IdReport {
+            id,
+            frames: samples.len(),
+            length,
+            period,
+            bytes: Vec::with_capacity(length),
+        };
+
+        let values = |index| samples.iter().map(|s| byte(s, index)).collect::<Vec<u8>>();
+        let constant = |values: &[u8]| values.windows(2).all(|w| w[0] == w[1]);
+
+        // When one byte is the exclusive or of the others, every byte is, so only the last
+        // candidate is taken as the checksum.
+        let checksum = (0..length).rev().find_map(|index| {
+            checksum_kind(samples, index)
+                .filter(|_| !constant(&values(index)))
+                .map(|checksum| (index, checksum))
+        });
+
+        for index in 0..length {
+            let values = values(index);
+
+            let kind = if constant(&values) {
+                ByteKind::Constant(values.first().copied().unwrap_or(0))
+            } else if let Some(step) = counter_step(&values, 0xff) {
+                ByteKind::Counter { step, bits: 8 }
+            } else if let Some(step) = counter_step(&values, 0x0f) {
+                ByteKind::Counter { step, bits: 4 }
+            } else if let Some((_, checksum)) = checksum.filter(|(i, _)| *i == index) {
+                ByteKind::Checksum(checksum)
+            } else {
+                let correlation =
+                    correlation_ratio(values.iter().map(|v| f64::from(*v)), labels.iter().copied());
+                let bits = std::array::from_fn(|bit| {
+                    let bit = 7 - bit;
+                    let values = values.iter().map(|v| f64::from((v >> bit) & 1));
+                    correlation_ratio(values, labels.iter().copied())
+                });
+                let changing_bits = values.iter().fold(0, |acc, v| acc | (v ^ values[0]));
+
+                ByteKind::Signal {
+                    correlation,
+                    bits,
+                    changing_bits,
+                }
+            };
+
+            report.bytes.push(kind);
+        }
+
+        report
+    }
+}
+
+/// Returns the step if the masked value increases by the same amount in most frames.
+fn counter_step(values: &[u8], mask: u8) -> Option<u8> {
+    if values.len() < 3 {
+        return None;
+    }
+
+    let mut steps: HashMap<u8, usize> = HashMap::new();
+    for window in values.windows(2) {
+        let step = (window[1] & mask).wrapping_sub(window[0] & mask) & mask;
+        *steps.entry(step).or_default() += 1;
+    }
+
+    let (step, count) = steps.into_iter().max_by_key(|(_, count)| *count)?;
+    let transitions = values.len() - 1;
+
+    (step != 0 && count as f64 / transitions as f64 >= COUNTER_THRESHOLD).then_some(step)
+}
+
+fn checksum_kind(samples: &[Sample], index: usize) -> Option<Checksum> {
+    let mut sum = true;
+    let mut xor = true;
+
+    for sample in samples {
+        let value = sample.data.get(index)?;
+
+        let others = sample
+            .data
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != index)
+            .map(|(_, v)| *v);
+
+        sum &= others.clone().fold(0, u8::wrapping_add) == *value;
+        xor &= others.fold(0, |acc, v| acc ^ v) == *value;
+    }
+
+    if sum {
+        Some(Checksum::Sum)
+    } else if xor {
+        Some(Checksum::Xor)
+    } else {
+        None
+    }
+}
+
+/// The correlation ratio (eta squared) of the values grouped by label. This is 1 when the value
+/// is completely determined by the label and 0 when the label tells nothing about the value.
+/// Values without a label are ignored.
+fn correlation_ratio<'a>(
+    values: impl Iterator<Item = f64>,
+    labels: impl Iterator<Item = Option<&'a str>>,
+) -> f64 {
+    let mut groups: HashMap<&str, (f64, usize)> = HashMap::new();
+    let mut all = Vec::new();
+
+    for (value, label) in values.zip(labels) {
+        if let Some(label) = label {
+            let group = groups.entry(label).or_default();
+            group.0 += value;
+            group.1 += 1;
+            all.push((value, label));
+        }
+    }
+
+    if groups.len() < 2 || all.is_empty() {
+        return 0.0;
+    }
+
+    let mean = all.iter().map(|(v, _)| v).sum::<f64>() / all.len() as f64;
+    let total: f64 = all.iter().map(|(v, _)| (v - mean).powi(2)).sum();
+    if total == 0.0 {
+        return 0.0;
+    }
+
+    let between: f64 = groups
+        .values()
+        .map(|(sum, count)| *count as f64 * (sum / *count as f64 - mean).powi(2))
+        .sum();
+
+    between / total
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Checksum {
+    /// Sum of the other bytes
+    Sum,
+    /// Exclusive or of the other bytes
+    Xor,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ByteKind {
+    Constant(u8),
+    Counter {
+        step: u8,
+        bits: u8,
+    },
+    Checksum(Checksum),
+    Signal {
+        /// Correlation of the whole byte with the stimulus
+        correlation: f64,
+        /// Correlation of each bit, most significant bit first
+        bits: [f64; 8],
+        /// Mask of the bits that changed during the recording
+        changing_bits: u8,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct IdReport {
+    pub id: u32,
+    pub frames: usize,
+    pub length: usize,
+    pub period: Option<Duration>,
+    pub bytes: Vec<ByteKind>,
+}
+
+impl IdReport {
+    /// True when any byte or bit follows the stimulus.
+    pub fn correlated(&self) -> bool {
+        self.bytes.iter().any(|byte| match byte {
+            ByteKind::Signal {
+                correlation, bits, ..
+            } => {
+                *correlation >= CORRELATION_THRESHOLD
+                    || bits.iter().any(|b| *b >= CORRELATION_THRESHOLD)
+            }
+            _ => false,
+        })
+    }
+
+    /// Describes every byte that is not constant.
+    pub fn summary(&self) -> Vec<String> {
+        self.bytes
+            .iter()
+            .enumerate()
+            .filter_map(|(index, byte)| match byte {
+                ByteKind::Constant(_) => None,
+                ByteKind::Counter { step, bits } => Some(format!(
+                    "byte {}: {} bit counter, step {}",
+                    index, bits, step
+                )),
+                ByteKind::Checksum(checksum) => {
+                    Some(format!("byte {}: {:?} checksum", index, checksum))
+                }
+                ByteKind::Signal {
+                    correlation, bits, ..
+                } => {
+                    let correlated: Vec<String> = bits
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, c)| **c >= CORRELATION_THRESHOLD)
+                        .map(|(bit, c)| format!("bit {} ({:.2})", 7 - bit, c))
+                        .collect();
+
+                    Some(format!(
+                        "byte {}: correlation {:.2}{}{}",
+                        index,
+                        correlation,
+                        if correlated.is_empty() { "" } else { ", " },
+                        correlated.join(", ")
+                    ))
+                }
+            })
+            .collect()
+    }
+
+    /// Proposes a deku struct for the message with its `Message` and `Layout` impls, in the style
+    /// of the structs in `miu_protocol::t7`. Constant bits become padding.
+    pub fn proposed_struct(&self) -> String {
+        let mut fields: Vec<ProposedField> = Vec::new();
+        let mut padding = 0;
+
+        let mut push = |name: String, bits: Option<u8>, comment: String, padding: &mut usize| {
+            fields.push(ProposedField {
+                name,
+                bits,
+                pad_bits_before: std::mem::take(padding),
+                comment,
+            });
+        };
+
+        for (index, byte) in self.bytes.iter().enumerate() {
+            match byte {
+                ByteKind::Constant(_) => padding += 8,
+                ByteKind::Counter { bits, .. } => {
+                    padding += usize::from(8 - bits);
+                    let bits = (*bits != 8).then_some(*bits);
+                    push(
+                        format!("counter_{}", index),
+                        bits,
+                        String::from("Counter"),
+                        &mut padding,
+                    );
+                }
+                ByteKind::Checksum(checksum) => push(
+                    format!("checksum_{}", index),
+                    None,
+                    format!("{:?} checksum", checksum),
+                    &mut padding,
+                ),
+                ByteKind::Signal {
+                    correlation,
+                    bits,
+                    changing_bits,
+                } => {
+                    let correlated_bits =
+                        bits.iter().filter(|c| **c >= CORRELATION_THRESHOLD).count();
+
+                    // A byte that follows the stimulus as a whole is probably a value, unless a
+                    // single bit explains it.
+                    if *correlation >= CORRELATION_THRESHOLD && correlated_bits != 1 {
+                        push(
+                            format!("signal_{}", index),
+                            None,
+                            format!("Follows the stimulus ({:.2})", correlation),
+                            &mut padding,
+                        );
+                        continue;
+                    }
+
+                    for (bit, bit_correlation) in bits.iter().enumerate() {
+                        if *bit_correlation >= CORRELATION_THRESHOLD {
+                            push(
+                                format!("signal_{}_{}", index, 7 - bit),
+                                Some(1),
+                                format!("Follows the stimulus ({:.2})", bit_correlation),
+                                &mut padding,
+                            );
+                        } else if changing_bits & (0x80 >> bit) != 0 {
+                            push(
+                                format!("unknown_{}_{}", index, 7 - bit),
+                                Some(1),
+                                String::from("Changes, but not with the stimulus"),
+                                &mut padding,
+                            );
+                        } else {
+                            padding += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        let name = format!("Message{:03X}", self.id);
+        let mut output = String::new();
+
+        let _ = writeln!(
+            output,
+            "#[derive(Debug, Default, PartialEq, DekuRead, DekuWrite)]"
+        );
+        let _ = writeln!(output, "#[deku(endian = \"big\")]");
+        let _ = writeln!(output, "pub struct {} {{", name);
+
+        let last = fields.len().saturating_sub(1);
+        for (i, field) in fields.iter().enumerate() {
+            let mut attributes = Vec::new();
+            if field.pad_bits_before > 0 {
+                attributes.push(format!("pad_bits_before = \"{}\"", field.pad_bits_before));
+            }
+            if let Some(bits) = field.bits {
+                attributes.push(format!("bits = {}", bits));
+            }
+            if i == last && padding > 0 {
+                attributes.push(format!("pad_bits_after = \"{}\"", padding));
+            }
+
+            if i > 0 {
+                let _ = writeln!(output);
+            }
+            let _ = writeln!(output, "    // {}", field.comment);
+            if !attributes.is_empty() {
+                let _ = writeln!(output, "    #[deku({})]", attributes.join(", "));
+            }
+            let _ = writeln!(output, "    pub {}: u8,", field.name);
+        }
+
+        let _ = writeln!(output, "}}");
+        let _ = writeln!(output);
+        let _ = writeln!(output, "impl Message for {} {{", name);
+        let _ = writeln!(output, "    const CAN_ID: u32 = 0x{:X};", self.id);
+        let _ = writeln!(output, "}}");
+        let _ = writeln!(output);
+        let _ = writeln!(output, "impl Layout for {} {{", name);
+        let _ = writeln!(
+            output,
+            "    const NAME: &'static str = \"Message {:03X}\";",
+            self.id
+        );
+        let _ = writeln!(output, "    const FIELDS: &'static [Field] = &[");
+        let mut start = 0;
+        for field in &fields {
+            start += field.pad_bits_before;
+            let bits = usize::from(field.bits.unwrap_or(8));
+            let _ = writeln!(
+                output,
+                "        Field::new(\"{}\", \"\", {}, {}),",
+                field.name, start, bits
+            );
+            start += bits;
+        }
+        let _ = writeln!(output, "    ];");
+        let _ = write!(output, "}}");
+
+        output
+    }
+}
+
+/// Records the frames as they are received while the analyser is recording, until the analyser is
+/// dropped.
+pub async fn record(
+    analyser: Weak<Mutex<Analyser>>,
+    mut frames: broadcast::Receiver<ReceivedFrame>,
+) {
+    loop {
+        let received = match frames.recv().await {
+            Ok(received) => received,
+            Err(broadcast::error::RecvError::Lagged(count)) => {
+                tracing::warn!("analyser missed {} frames", count);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        let Some(analyser) = analyser.upgrade() else {
+            break;
+        };
+
+        let mut analyser = lock(&analyser);
+        if analyser.is_recording() {
+            analyser.record(&received.frame, received.time);
+        }
+    }
+
+    tracing::debug!("analyser recording ended");
+}
+
+/// Locks the analyser, which is always valid even when another thread panicked while holding it.
+pub fn lock(analyser: &Mutex<Analyser>) -> std::sync::MutexGuard<'_, Analyser> {
+    analyser.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+struct ProposedField {
+    name: String,
+    bits: Option<u8>,
+    pad_bits_before: usize,
+    comment: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    const ID: u32 = 0x123;
+
+    fn frame(data: &[u8]) -> CanFrame {
+        CanFrame::from_raw_id(ID, data).unwrap()
+    }
+
+    /// Records 100 frames where byte 1 bit 3 follows the brake pedal, byte 2 counts, byte 3
+    /// changes randomly and byte 4 is a checksum.
+    fn recording() -> Analyser {
+        let mut analyser = Analyser::default();
+        let start = Instant::now();
+
+        for i in 0..100_u8 {
+            let time = start + Duration::from_millis(u64::from(i) * 10);
+
+            if i % 25 == 0 {
+                let label = if i % 50 == 0 { "pressing" } else { "releasing" };
+                analyser.mark(String::from(label), time);
+            }
+
+            let pressed = (i / 25) % 2 == 0;
+            let mut data = [
+                0x42,
+                u8::from(pressed) << 3,
+                i.wrapping_mul(2),
+                (i % 7) * 3 % 7,
+                0,
+            ];
+            data[4] = data[..4].iter().fold(0, |acc, v| acc ^ v);
+            analyser.record(&frame(&data), time);
+        }
+
+        analyser
+    }
+
+    #[test]
+    fn it_finds_bits_that_follow_the_stimulus() {
+        let report = recording().analyse().remove(0);
+
+        assert_eq!(report.id, ID);
+        assert_eq!(report.frames, 100);
+        assert_eq!(report.period, Some(Duration::from_millis(10)));
+        assert!(report.correlated());
+
+        match &report.bytes[1] {
+            ByteKind::Signal { bits, .. } => {
+                assert_eq!(bits[4], 1.0);
+                assert_eq!(bits[0], 0.0);
+            }
+            other => panic!("byte 1 should be a signal, got {:?}", other),
+        }
+
+        match &report.bytes[3] {
+            ByteKind::Signal { correlation, .. } => assert!(*correlation < 0.1),
+            other => panic!("byte 3 should be a signal, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_finds_constants_counters_and_checksums() {
+        let report = recording().analyse().remove(0);
+
+        assert_eq!(report.bytes[0], ByteKind::Constant(0x42));
+        assert_eq!(report.bytes[2], ByteKind::Counter { step: 2, bits: 8 });
+        assert_eq!(report.bytes[4], ByteKind::Checksum(Checksum::Xor));
+    }
+
+    #[test]
+    fn it_ignores_frames_before_the_first_mark() {
+        let mut analyser = Analyser::default();
+        let start = Instant::now();
+
+        analyser.record(&frame(&[1]), start);
+        analyser.mark(String::from("on"), start + Duration::from_millis(1));
+
+        assert_eq!(analyser.label_at(start), None);
+        assert_eq!(
+            analyser.label_at(start + Duration::from_millis(2)),
+            Some("on")
+        );
+    }
+
+    #[test]
+    fn it_detects_nibble_counters() {
+        let values: Vec<u8> = (0..40_u8).map(|i| 0xa0 | (i % 16)).collect();
+
+        assert_eq!(counter_step(&values, 0xff), None);
+        assert_eq!(counter_step(&values, 0x0f), Some(1));
+    }
+
+    #[tokio::test]
+    async fn it_records_in_the_background_while_recording() {
+        let analyser = Arc::new(Mutex::new(Analyser::default()));
+        let (frame_sender, frames) = broadcast::channel(4);
+        let task = tokio::spawn(record(Arc::downgrade(&analyser), frames));
+        let send = |data: &[u8]| {
+            frame_sender
+                .send(ReceivedFrame {
+                    time: Instant::now(),
+                    frame: frame(data),
+                })
+                .unwrap();
+        };
+
+        // Not recording yet
+        send(&[1]);
+        tokio::task::yield_now().await;
+
+        lock(&analyser).start();
+        send(&[2]);
+        send(&[3]);
+        drop(frame_sender);
+        task.await.unwrap();
+
+        assert_eq!(lock(&analyser).frame_count(), 2);
+    }
+
+    #[test]
+    fn it_proposes_a_struct_layout() {
+        let proposal = recording().analyse().remove(0).proposed_struct();
+
+        assert!(proposal.contains("pub struct Message123 {"));
+        assert!(proposal.contains("#[deku(pad_bits_before = \"12\", bits = 1)]"));
+        assert!(proposal.contains("pub signal_1_3: u8,"));
+        assert!(proposal.contains("pad_bits_before = \"3\""));
+        assert!(proposal.contains("pub counter_2: u8,"));
+        assert!(proposal.contains("pub checksum_4: u8,"));
+        assert!(proposal.contains("impl Message for Message123 {"));
+        assert!(proposal.contains("const CAN_ID: u32 = 0x123;"));
+        assert!(proposal.contains("impl Layout for Message123 {"));
+        assert!(proposal.contains("Field::new(\"signal_1_3\", \"\", 12, 1),"));
+        assert!(proposal.contains("Field::new(\"checksum_4\", \"\", 32, 8),"));
+    }
+}
@@ -0,0 +1,286 @@
+//! Local HTTP API to control miu-com from other programs, like a test harness.
+//!
+//! - `GET /state` returns the state
+//! - `PATCH /state` changes the fields in the body and returns the new state
+//! - `GET /connection` returns whether the can bus is connected
+//! - `POST /connect` connects to the interface in the body, `POST /disconnect` disconnects
+//! - `GET /ws` is a WebSocket that streams state changes and received frames
+//! - `GET /metrics` returns counters and the state for Prometheus, see [`crate::metrics`]
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use socketcan::{EmbeddedFrame, Frame};
+use std::net::SocketAddr;
+use std::time::Instant;
+use tokio::sync::{broadcast, watch};
+
+use crate::can::{self, CanClient, ReceivedFrame};
+use crate::metrics;
+use crate::miu_state::{self, MiuState};
+
+/// The address the API listens on when no address is given.
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:7878";
+
+#[derive(Clone)]
+pub struct Api {
+    pub can: CanClient,
+    pub miu_state: watch::Sender<MiuState>,
+}
+
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    WorkerStopped,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            ApiError::BadRequest(message) => (StatusCode::BAD_REQUEST, message),
+            ApiError::WorkerStopped => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                String::from("the can task stopped"),
+            ),
+        };
+
+        (status, Json(serde_json::json!({ "error": message }))).into_response()
+    }
+}
+
+impl From<can::CanClientError> for ApiError {
+    fn from(_: can::CanClientError) -> Self {
+        Self::WorkerStopped
+    }
+}
+
+#[derive(Deserialize)]
+struct Connect {
+    interface: String,
+}
+
+#[derive(Serialize)]
+struct Connection {
+    connected: bool,
+}
+
+/// A message on the WebSocket.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Event {
+    State(Box<MiuState>),
+    Frame {
+        /// Seconds since the WebSocket was opened
+        time: f64,
+        id: u32,
+        data: Vec<u8>,
+    },
+}
+
+pub fn router(api: Api) -> Router {
+    Router::new()
+        .route("/state", get(get_state).patch(patch_state))
+        .route("/connection", get(get_connection))
+        .route("/connect", post(connect))
+        .route("/disconnect", post(disconnect))
+        .route("/ws", get(websocket))
+        .route("/metrics", get(get_metrics))
+        .with_state(api)
+}
+
+/// Serves the API until the task is aborted.
+pub async fn serve(address: SocketAddr, api: Api) -> std::io::Result<()> {
+    let listener = tokio::net::TcpListener::bind(address).await?;
+    tracing::info!("api listening on http://{}", listener.local_addr()?);
+
+    axum::serve(listener, router(api)).await
+}
+
+async fn get_state(State(api): State<Api>) -> Json<MiuState> {
+    Json(*api.miu_state.borrow())
+}
+
+async fn patch_state(
+    State(api): State<Api>,
+    Json(patch): Json<Value>,
+) -> Result<Json<MiuState>, ApiError> {
+    miu_state::patch(&api.miu_state, patch)
+        .map_err(|error| ApiError::BadRequest(error.to_string()))?;
+
+    Ok(Json(*api.miu_state.borrow()))
+}
+
+async fn get_connection(State(api): State<Api>) -> Result<Json<Connection>, ApiError> {
+    Ok(Json(Connection {
+        connected: api.can.state()? == can::State::Connected,
+    }))
+}
+
+async fn connect(
+    State(api): State<Api>,
+    Json(connect): Json<Connect>,
+) -> Result<StatusCode, ApiError> {
+    let miu_state = api.miu_state.subscribe();
+
+    // The client blocks until the command is sent, which is not allowed on the async runtime.
+    tokio::task::spawn_blocking(move || api.can.connect(connect.interface, miu_state))
+        .await
+        .map_err(|_| ApiError::WorkerStopped)??;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn disconnect(State(api): State<Api>) -> Result<StatusCode, ApiError> {
+    tokio::task::spawn_blocking(move || api.can.disconnect())
+        .await
+        .map_err(|_| ApiError::WorkerStopped)??;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_metrics(State(api): State<Api>) -> Result<Response, ApiError> {
+    let totals = api.can.counters();
+    // A stopped can task is what the metrics are there to show, so it's not an error here.
+    let connected = api
+        .can
+        .state()
+        .is_ok_and(|state| state == can::State::Connected);
+
+    // Reading the bus state goes through netlink, which blocks.
+    let interface = totals.interface.clone().filter(|_| connected);
+    let bus_state =
+        tokio::task::spawn_blocking(move || interface.and_then(|i| metrics::bus_state(&i)))
+            .await
+            .map_err(|_| ApiError::WorkerStopped)?;
+
+    let text = metrics::render(&metrics::Snapshot {
+        totals: &totals,
+        connected,
+        bus_state,
+        miu_state: &api.miu_state.borrow(),
+    });
+    Ok(([(header::CONTENT_TYPE, metrics::CONTENT_TYPE)], text).into_response())
+}
+
+async fn websocket(State(api): State<Api>, upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(move |socket| stream_events(socket, api))
+}
+
+async fn stream_events(mut socket: WebSocket, api: Api) {
+    let opened = Instant::now();
+    let mut miu_state = api.miu_state.subscribe();
+    let mut frames = api.can.received_frames();
+
+    // Start with the current state so the client doesn't have to wait for a change.
+    let mut event = Some(Event::State(Box::new(*miu_state.borrow_and_update())));
+
+    loop {
+        if let Some(event) = event.take() {
+            let text = serde_json::to_string(&event).expect("events can always be serialized");
+            if socket.send(Message::Text(text.into())).await.is_err() {
+                break;
+            }
+        }
+
+        event = tokio::select! {
+            result = miu_state.changed() => match result {
+                Ok(()) => Some(Event::State(Box::new(*miu_state.borrow_and_update()))),
+                Err(_) => break,
+            },
+            result = frames.recv() => match result {
+                Ok(ReceivedFrame { time, frame }) => Some(Event::Frame {
+                    time: time.saturating_duration_since(opened).as_secs_f64(),
+                    id: frame.raw_id(),
+                    data: frame.data().to_vec(),
+                }),
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    tracing::warn!("api websocket missed {} frames", count);
+                    None
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            message = socket.recv() => match message {
+                // Messages from the client are ignored, but the socket has to be read to notice
+                // that it is closed.
+                Some(Ok(_)) => None,
+                _ => break,
+            },
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    fn api() -> Api {
+        let (can, _) = can::task(tokio::runtime::Handle::current());
+        Api {
+            can,
+            miu_state: watch::Sender::new(MiuState::default()),
+        }
+    }
+
+    async fn request(api: &Api, method: &str, uri: &str, body: &str) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_owned()))
+            .unwrap();
+        let response = router(api.clone()).oneshot(request).await.unwrap();
+
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn it_serves_the_state() {
+        let api = api();
+
+        let (status, body) = request(&api, "PATCH", "/state", r#"{"vehicle_speed": 88}"#).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["vehicle_speed"], 88);
+        assert_eq!(api.miu_state.borrow().vehicle_speed, 88);
+
+        let (status, body) = request(&api, "GET", "/state", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["vehicle_speed"], 88);
+
+        let (status, body) = request(&api, "PATCH", "/state", r#"{"nope": 1}"#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "unknown field /nope");
+    }
+
+    #[tokio::test]
+    async fn it_serves_metrics() {
+        let api = api();
+        let request = Request::builder()
+            .uri("/metrics")
+            .body(Body::empty())
+            .unwrap();
+        let response = router(api).oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            metrics::CONTENT_TYPE
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        assert!(text.contains("\nmiu_can_connected 0\n"));
+        assert!(text.ends_with("# EOF\n"));
+    }
+}
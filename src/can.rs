@@ -1,37 +1,61 @@
+use futures::StreamExt;
 use socketcan::tokio::CanSocket;
-use socketcan::Frame;
+use socketcan::{EmbeddedFrame, Frame};
 use std::time::Duration;
 use thiserror::Error;
-use tokio::sync::{mpsc, watch};
-use tokio::time;
+use tokio::sync::{broadcast, mpsc, watch};
 
 use crate::miu_state;
+use crate::odometer::Odometer;
 
+pub mod bus_load;
+pub mod cluster;
+pub mod counters;
+pub mod faults;
 pub mod interfaces;
-mod miu;
-mod t7;
-pub mod tcm;
+pub mod messages;
+pub mod miu;
+pub mod nodes;
+pub mod raw;
+pub mod sid;
+pub mod timing;
+
+pub use miu_protocol::{layout, t7, tcm, Message};
 
 pub enum Command {
     Connect(String, watch::Receiver<miu_state::MiuState>),
     Disconnect,
+    SidMessage(Option<sid::Message>),
+    ResetTrip,
+    SendFrame(socketcan::CanFrame),
+    PeriodicFrames(Vec<raw::PeriodicFrame>),
+    MiuEmulation(miu::Emulation),
+    Scheduling(timing::Scheduling),
 }
 
 pub type CommandSender = mpsc::Sender<Command>;
 type CommandReceiver = mpsc::Receiver<Command>;
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, Default, PartialEq)]
 pub enum State {
     Connected,
+    #[default]
     Disconnected,
 }
 
-impl Default for State {
-    fn default() -> Self {
-        Self::Disconnected
-    }
+/// A frame received from the bus, with the moment it was received.
+#[derive(Clone, Debug)]
+pub struct ReceivedFrame {
+    pub time: std::time::Instant,
+    pub frame: socketcan::CanFrame,
 }
 
+/// Number of received frames that are kept for receivers that can't keep up.
+const RECEIVED_FRAMES_CAPACITY: usize = 4096;
+
+/// Number of raw frames that can wait to be sent.
+const RAW_FRAMES_CAPACITY: usize = 64;
+
 type StateSender = watch::Sender<State>;
 pub type StateReceiver = watch::Receiver<State>;
 
@@ -41,12 +65,33 @@ enum CanError {
     IO(std::io::Error),
     #[error("state channel closed")]
     MiuStateChannelClosed,
+    #[error("sid message channel closed")]
+    SidChannelClosed,
+    #[error("raw frame channel closed")]
+    RawFrameChannelClosed,
+    #[error("miu emulation channel closed")]
+    MiuEmulationChannelClosed,
     #[error("unable to serialize can frame")]
     Serialization(deku::error::DekuError),
     #[error("can error")]
     SocketCan(socketcan::Error),
 }
 
+impl CanError {
+    /// A name for the kind of error, for counting them.
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::IO(_) => "io",
+            Self::MiuStateChannelClosed => "miu_state_channel_closed",
+            Self::SidChannelClosed => "sid_channel_closed",
+            Self::RawFrameChannelClosed => "raw_frame_channel_closed",
+            Self::MiuEmulationChannelClosed => "miu_emulation_channel_closed",
+            Self::Serialization(_) => "serialization",
+            Self::SocketCan(_) => "socket_can",
+        }
+    }
+}
+
 impl From<std::io::Error> for CanError {
     fn from(error: std::io::Error) -> Self {
         Self::IO(error)
@@ -65,21 +110,74 @@ impl From<socketcan::Error> for CanError {
     }
 }
 
-const UPDATE_RATE_HZ: u64 = 20;
+/// The broadcast loop runs faster than the periods of the nodes, so messages can be sent at other
+/// periods when faults are injected.
+const TICK_MS: u64 = 5;
 
-/// An infinite task that sends miu state updates at a rate of 20Hz.
+const ABS_STATUS_CAN_ID: u32 = 0x318;
+
+/// A frame that is more than a tick late has missed its deadline, up to a tick is by design.
+const DEADLINE_TOLERANCE: Duration = Duration::from_millis(TICK_MS);
+
+/// Puts a message in a frame.
+pub fn frame<M: Message>(message: &M) -> Result<socketcan::CanFrame, deku::DekuError> {
+    Ok(
+        socketcan::CanFrame::from_raw_id(M::CAN_ID, &message.encode()?)
+            .expect("from_raw_id can not fail because the message ids are known valid"),
+    )
+}
+
+/// An infinite task that sends the messages of the simulated nodes, see [`nodes`].
+///
+/// Every message is scheduled separately so faults can be injected per message. Which frames are
+/// sent depends on the key position and the enabled nodes: the bus is silent with the key off and
+/// the nodes wake up one by one when the ignition is switched on. Raw frames from the user are
+/// sent regardless of the key position. When MIU emulation is enabled the frames of the MIU are
+/// sent as well and requests for the MIU are answered.
+///
+/// The moment every frame is sent is recorded, see [`timing`].
 ///
 /// Note: This task runs forever but it can safely be aborted. The socket will be closed normally
 /// when it goes out of scope.
+#[allow(clippy::too_many_arguments)]
 async fn broadcast_state(
     interface: String,
     mut miu_state: watch::Receiver<miu_state::MiuState>,
+    mut sid_message: watch::Receiver<Option<sid::Message>>,
+    mut raw_frames: broadcast::Receiver<socketcan::CanFrame>,
+    mut periodic_frames: watch::Receiver<Vec<raw::PeriodicFrame>>,
+    mut miu_emulation: watch::Receiver<miu::Emulation>,
+    odometer: watch::Sender<Odometer>,
+    received_frames: broadcast::Sender<ReceivedFrame>,
+    transmit_timing: watch::Sender<timing::Report>,
+    bus_load: watch::Sender<bus_load::BusLoad>,
+    counters: counters::Counters,
+    scheduling: timing::Scheduling,
 ) -> Result<(), CanError> {
     tracing::info!("broadcasting miu state on can bus");
 
     let socket = CanSocket::open(&interface)?;
+    // Reading and writing happen concurrently in the loop below, so use a separate socket for
+    // receiving frames.
+    let mut receiver = CanSocket::open(&interface)?;
     let mut state = *miu_state.borrow_and_update();
-    let mut interval = time::interval(Duration::from_millis(1000 / UPDATE_RATE_HZ));
+    let mut ticker = timing::Ticker::new(Duration::from_millis(TICK_MS), scheduling);
+    let mut scheduler = faults::Scheduler::default();
+    let mut timing = timing::Recorder::new(DEADLINE_TOLERANCE);
+    transmit_timing.send_replace(timing::Report::default());
+    let mut meter = bus_load::Meter::new(bus_load::bitrate(&interface), std::time::Instant::now());
+    bus_load.send_replace(bus_load::BusLoad::default());
+
+    let mut ignition = miu_state::Ignition::default();
+    let mut last_tick = std::time::Instant::now();
+
+    let mut sid_writer = sid::Writer::default();
+    sid_writer.set_message(sid_message.borrow_and_update().clone());
+
+    let mut periodic_sender = raw::PeriodicSender::default();
+    periodic_sender.set_frames(periodic_frames.borrow_and_update().clone());
+
+    let mut emulation = *miu_emulation.borrow_and_update();
 
     loop {
         tokio::select! {
@@ -92,100 +190,176 @@ async fn broadcast_state(
                 state = *miu_state.borrow_and_update();
             }
 
-            _ = interval.tick() => {
-                let engine = t7::EngineSpeedAndThrottle {
-                    speed_fault: state.engine_speed_fault.into(),
-                    air_inlet_fault: false.into(),
-                    throttle_fault: false.into(),
-                    speed: state.engine_speed,
-                    torque: 0,
-                    max_torque_at_rpm: 0,
-                    accelerator_pedal_position: 0,
-                    accelerator_pedal_position_gradient: 0,
-                    dti: 0,
-                };
-                tracing::debug!("sending can message: {:?}", engine);
-                socket.write_frame(engine.try_into()?)?.await?;
-
-                let engine_status = t7::EngineStatus {
-                    vehicle_speed_fault: false.into(),
-                    brake_light_status: 0,
-                    actual_gear: 0,
-                    cruise_active: 0,
-                    no_ignition_retard: 0,
-                    kick_down: 0,
-                    clutch_brake: 0,
-                    jerk: 0,
-                    brake_light: 0,
-                    warm_up_shift_pattern: 0,
-                    check_filler_cap: 0,
-                    warm_up_cycle: 0,
-                    automatic: 1,
-                    nc_inhibit: 0,
-                    gear_shift_inhibit: 0,
-                    ac_relay: 0,
-                    e_gas_off: 0,
-                    limp_home: 0,
-                    check_engine: state.check_engine.into(),
-                    shift_up: 0,
-                    cruise_lamp: state.cruise.into(),
-                    rep: 0,
-                    engine_started: 1,
-                    cruise_included: 1,
-                    engine_type: 146,
-                    coast_lu_inhibit: 0,
-                };
-                tracing::debug!("sending can message: {:?}", engine_status);
-                socket.write_frame(engine_status.try_into()?)?.await?;
-
-                let air_and_coolant = t7::AirAndCoolant {
-                    coolant_temperature_1_fault: state.coolant_temperature_fault.into(),
-                    coolant_temperature_2_fault: state.coolant_temperature_fault.into(),
-                    ambient_air_pressure_fault: false.into(),
-                    coolant_temperature_1_plus_40: state.coolant_temperature + 40,
-                    coolant_temperature_2_plus_40: state.coolant_temperature + 40,
-                    ambient_air_pressure: 0,
-                };
-                tracing::debug!("sending can message: {:?}", air_and_coolant);
-                socket.write_frame(air_and_coolant.try_into()?)?.await?;
-
-                let fuel_consumption_and_boost = t7::FuelConsumptionAndBoost {
-                    ignition_on_fault: false.into(),
-                    unknown: 0,
-                    fuel_consumed: 0,
-                    boost: state.boost,
-                };
-                tracing::debug!("sending can message: {:?}", fuel_consumption_and_boost);
-                socket.write_frame(fuel_consumption_and_boost.try_into()?)?.await?;
-
-                let transmission_status = tcm::TransmissionStatus {
-                    actual_gear_fault: state.actual_gear_fault.into(),
-                    gear_lever_fault: state.gear_lever_fault.into(),
-                    actual_gear: state.actual_gear,
-                    gear_lever: state.gear_lever,
-                    check_gearbox: state.check_gearbox.into(),
-                    sport: state.sport.into(),
-                    winter: state.winter.into(),
-                    unknown: 0,
-                    freeze_frame_request: 0,
-                    check_engine: 0,
-                    tcm_cslu: 0,
-                    unknown2: 0,
-                };
-                tracing::debug!("sending can message: {:?}", transmission_status);
-                socket.write_frame(transmission_status.try_into()?)?.await?;
-
-                // This turns off the abs / traction control warning lights
-                let can_frame = socketcan::CanFrame::from_raw_id(0x318, &[0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
-                tracing::debug!("sending can message: {:?}", can_frame);
-                socket.write_frame(can_frame)?.await?;
-
-                interval.tick().await;
+            result = sid_message.changed() => {
+                if result.is_err() {
+                    tracing::info!("ending miu state broadcast because sid channel closed");
+                    return Err(CanError::SidChannelClosed);
+                }
+
+                sid_writer.set_message(sid_message.borrow_and_update().clone());
+            }
+
+            result = periodic_frames.changed() => {
+                if result.is_err() {
+                    tracing::info!("ending miu state broadcast because periodic frame channel closed");
+                    return Err(CanError::RawFrameChannelClosed);
+                }
+
+                periodic_sender.set_frames(periodic_frames.borrow_and_update().clone());
+            }
+
+            result = miu_emulation.changed() => {
+                if result.is_err() {
+                    tracing::info!("ending miu state broadcast because miu emulation channel closed");
+                    return Err(CanError::MiuEmulationChannelClosed);
+                }
+
+                emulation = *miu_emulation.borrow_and_update();
+            }
+
+            result = raw_frames.recv() => {
+                match result {
+                    Ok(frame) => {
+                        tracing::debug!("sending raw can frame: {:?}", frame);
+                        send(&socket, frame, None, &mut timing, &counters).await?;
+                    }
+                    Err(broadcast::error::RecvError::Lagged(count)) => {
+                        tracing::warn!("dropped {} raw frames", count);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        tracing::info!("ending miu state broadcast because raw frame channel closed");
+                        return Err(CanError::RawFrameChannelClosed);
+                    }
+                }
+            }
+
+            Some(frame) = receiver.next() => {
+                let frame = frame?;
+                meter.receive(&frame);
+                counters.received(frame.raw_id());
+                // The frames miu-com sends come back through the loopback, so the distance is
+                // counted from the speed that was on the bus, faults included.
+                odometer.send_if_modified(|odometer| {
+                    odometer.record(&frame, std::time::Instant::now())
+                });
+
+                // Sending fails when nobody is listening, which is fine.
+                let _ = received_frames.send(ReceivedFrame {
+                    time: std::time::Instant::now(),
+                    frame,
+                });
+
+                if frame.raw_id() == sid::TextGrant::CAN_ID {
+                    if let Ok(grant) = sid::TextGrant::decode(frame.data()) {
+                        tracing::debug!("received can message: {:?}", grant);
+                        sid_writer.handle_grant(&grant);
+                        tracing::debug!("sid writer state: {:?}", sid_writer.state());
+                    }
+                }
+            }
+
+            _ = ticker.tick() => {
+                let now = std::time::Instant::now();
+
+                let previous_mode = ignition.mode();
+                ignition.update(state.key_position, now);
+                if ignition.mode() != previous_mode {
+                    tracing::info!("power mode changed to {:?}", ignition.mode());
+                }
+
+                // Nodes don't all start sending at the same moment after waking up.
+                let awake_for = ignition.awake_for(now);
+
+                // Without the emulated MIU nothing may send the speed, so count the speed of the
+                // state instead, as long as the car could be moving.
+                let elapsed = now.duration_since(last_tick);
+                last_tick = now;
+                if awake_for.is_some() && !emulation.enabled {
+                    odometer.send_if_modified(|odometer| {
+                        odometer.simulate(state.vehicle_speed, elapsed, now)
+                    });
+                }
+
+                let awake = |delay| awake_for.is_some_and(|awake_for| awake_for >= delay);
+                let faults = state.faults;
+
+                for node in &nodes::NODES {
+                    if !state.nodes.get(node.id) || !awake(node.wake_up_delay) {
+                        continue;
+                    }
+
+                    for &id in node.messages {
+                        let fault = faults.get(id);
+                        let period = fault.period_ms.map(Duration::from_millis).unwrap_or(node.period);
+                        if !scheduler.due(id, node.period, &fault, now) {
+                            continue;
+                        }
+
+                        let Some(frame) = messages::encode(id, &state, &ignition) else {
+                            continue;
+                        };
+                        let frame = scheduler.apply(frame?, &fault);
+                        tracing::debug!("sending can message for {}: {:?}", node.id.name(), frame);
+                        send(&socket, frame, Some(period), &mut timing, &counters).await?;
+                    }
+                }
+
+                // The SID is powered in accessory mode as well
+                if state.nodes.sid && state.key_position != miu_state::KeyPosition::Off {
+                    for frame in sid_writer.poll(now) {
+                        tracing::debug!("sending can message: {:?}", frame);
+                        send(&socket, frame, None, &mut timing, &counters).await?;
+                    }
+                }
+
+                for message in miu::messages(&state, &emulation) {
+                    let fault = faults.get(message.id);
+                    let period = fault.period_ms.map(Duration::from_millis).unwrap_or(message.period);
+                    if !scheduler.due(message.id, message.period, &fault, now) {
+                        continue;
+                    }
+
+                    let distance = *odometer.borrow();
+                    let Some(frame) = miu::encode(message.id, &state, &emulation, &distance) else {
+                        continue;
+                    };
+                    let frame = scheduler.apply(frame?, &fault);
+                    tracing::debug!("sending can message for the MIU: {:?}", frame);
+                    send(&socket, frame, Some(period), &mut timing, &counters).await?;
+                }
+
+                for (frame, period) in periodic_sender.poll(now) {
+                    tracing::debug!("sending raw can frame: {:?}", frame);
+                    send(&socket, frame, Some(period), &mut timing, &counters).await?;
+                }
+
+                if let Some(report) = timing.poll(std::time::Instant::now()) {
+                    transmit_timing.send_replace(report);
+                }
+
+                let schedule = || bus_load::schedule(&state, periodic_sender.frames(), &emulation);
+                if let Some(load) = meter.poll(now, schedule) {
+                    bus_load.send_replace(load.clone());
+                }
             }
         }
     }
 }
 
+/// Sends a frame and records when it was sent.
+async fn send(
+    socket: &CanSocket,
+    frame: socketcan::CanFrame,
+    period: Option<Duration>,
+    timing: &mut timing::Recorder,
+    counters: &counters::Counters,
+) -> Result<(), CanError> {
+    socket.write_frame(frame)?.await?;
+    timing.record(frame.raw_id(), period, std::time::Instant::now());
+    counters.sent(frame.raw_id());
+    Ok(())
+}
+
 #[derive(Debug)]
 pub enum CanClientError {
     WorkerStopped,
@@ -203,10 +377,16 @@ impl From<watch::error::RecvError> for CanClientError {
     }
 }
 
+#[derive(Clone)]
 pub struct CanClient {
     runtime: tokio::runtime::Handle,
     command: CommandSender,
     connection_state: StateReceiver,
+    odometer: watch::Receiver<Odometer>,
+    received_frames: broadcast::Sender<ReceivedFrame>,
+    transmit_timing: watch::Receiver<timing::Report>,
+    bus_load: watch::Receiver<bus_load::BusLoad>,
+    counters: counters::Counters,
 }
 
 impl CanClient {
@@ -221,6 +401,15 @@ impl CanClient {
         Ok(())
     }
 
+    pub fn set_sid_message(&self, message: Option<sid::Message>) -> Result<(), CanClientError> {
+        let command = self.command.clone();
+
+        self.runtime
+            .block_on(async { command.send(Command::SidMessage(message)).await })?;
+
+        Ok(())
+    }
+
     pub fn disconnect(&self) -> Result<(), CanClientError> {
         let command = self.command.clone();
 
@@ -230,6 +419,89 @@ impl CanClient {
         Ok(())
     }
 
+    pub fn reset_trip(&self) -> Result<(), CanClientError> {
+        let command = self.command.clone();
+
+        self.runtime
+            .block_on(async { command.send(Command::ResetTrip).await })?;
+
+        Ok(())
+    }
+
+    /// Sends a frame once. The frame is dropped when there is no connection.
+    pub fn send_frame(&self, frame: socketcan::CanFrame) -> Result<(), CanClientError> {
+        let command = self.command.clone();
+
+        self.runtime
+            .block_on(async { command.send(Command::SendFrame(frame)).await })?;
+
+        Ok(())
+    }
+
+    pub fn set_periodic_frames(
+        &self,
+        frames: Vec<raw::PeriodicFrame>,
+    ) -> Result<(), CanClientError> {
+        let command = self.command.clone();
+
+        self.runtime
+            .block_on(async { command.send(Command::PeriodicFrames(frames)).await })?;
+
+        Ok(())
+    }
+
+    pub fn set_miu_emulation(&self, emulation: miu::Emulation) -> Result<(), CanClientError> {
+        let command = self.command.clone();
+
+        self.runtime
+            .block_on(async { command.send(Command::MiuEmulation(emulation)).await })?;
+
+        Ok(())
+    }
+
+    /// Sets how the frames are scheduled, which is used from the next connection on.
+    pub fn set_scheduling(&self, scheduling: timing::Scheduling) -> Result<(), CanClientError> {
+        let command = self.command.clone();
+
+        self.runtime
+            .block_on(async { command.send(Command::Scheduling(scheduling)).await })?;
+
+        Ok(())
+    }
+
+    /// The timing of the frames sent in this connection, updated every
+    /// [`timing::REPORT_PERIOD`].
+    pub fn transmit_timing(&self) -> Result<timing::Report, CanClientError> {
+        self.transmit_timing.has_changed()?;
+        Ok(self.transmit_timing.borrow().clone())
+    }
+
+    /// The load of the bus in this connection, updated every [`bus_load::SAMPLE_PERIOD`].
+    pub fn bus_load(&self) -> Result<bus_load::BusLoad, CanClientError> {
+        self.bus_load.has_changed()?;
+        Ok(self.bus_load.borrow().clone())
+    }
+
+    /// The totals since startup, over all connections.
+    pub fn counters(&self) -> counters::Totals {
+        self.counters.totals()
+    }
+
+    pub fn odometer(&self) -> Result<Odometer, CanClientError> {
+        self.odometer.has_changed()?;
+        Ok(*self.odometer.borrow())
+    }
+
+    /// Subscribes to the frames received while connected.
+    pub fn received_frames(&self) -> broadcast::Receiver<ReceivedFrame> {
+        self.received_frames.subscribe()
+    }
+
+    /// Subscribes to changes of the connection state.
+    pub fn connection_state(&self) -> StateReceiver {
+        self.connection_state.clone()
+    }
+
     pub fn state(&self) -> Result<State, CanClientError> {
         self.connection_state.has_changed()?;
         Ok(*self.connection_state.borrow())
@@ -239,6 +511,16 @@ impl CanClient {
 pub struct CanTask {
     command: CommandReceiver,
     connection_state: StateSender,
+    sid_message: watch::Sender<Option<sid::Message>>,
+    raw_frames: broadcast::Sender<socketcan::CanFrame>,
+    periodic_frames: watch::Sender<Vec<raw::PeriodicFrame>>,
+    miu_emulation: watch::Sender<miu::Emulation>,
+    odometer: watch::Sender<Odometer>,
+    received_frames: broadcast::Sender<ReceivedFrame>,
+    transmit_timing: watch::Sender<timing::Report>,
+    bus_load: watch::Sender<bus_load::BusLoad>,
+    counters: counters::Counters,
+    scheduling: timing::Scheduling,
 }
 
 impl CanTask {
@@ -254,11 +536,44 @@ impl CanTask {
                     tracing::info!("received connect command");
 
                     broadcast_task.abort();
+                    self.counters.connected(&interface);
 
                     let connection_state = self.connection_state.clone();
+                    let sid_message = self.sid_message.subscribe();
+                    let raw_frames = self.raw_frames.subscribe();
+                    let periodic_frames = self.periodic_frames.subscribe();
+                    let miu_emulation = self.miu_emulation.subscribe();
+                    let odometer = self.odometer.clone();
+                    let received_frames = self.received_frames.clone();
+                    let transmit_timing = self.transmit_timing.clone();
+                    let bus_load = self.bus_load.clone();
+                    let counters = self.counters.clone();
+                    let scheduling = self.scheduling;
                     broadcast_task = tokio::spawn(async move {
-                        let result = broadcast_state(interface, miu_state).await;
+                        let broadcast = broadcast_state(
+                            interface,
+                            miu_state,
+                            sid_message,
+                            raw_frames,
+                            periodic_frames,
+                            miu_emulation,
+                            odometer,
+                            received_frames,
+                            transmit_timing,
+                            bus_load,
+                            counters.clone(),
+                            scheduling,
+                        );
+                        let result = match scheduling {
+                            timing::Scheduling::Normal => broadcast.await,
+                            timing::Scheduling::Realtime => timing::run_realtime(broadcast)
+                                .await
+                                .unwrap_or_else(|error| Err(CanError::IO(error))),
+                        };
                         tracing::warn!("broadcasting miu state ended: {:?}", result);
+                        if let Err(error) = &result {
+                            counters.error(error.kind());
+                        }
 
                         // If this send fails the client has gone out of scope, in which case this
                         // state update is not relevant, so we can just ignore the error.
@@ -266,6 +581,42 @@ impl CanTask {
                     });
                     let _ = self.connection_state.send(State::Connected);
                 }
+                Some(Command::SidMessage(message)) => {
+                    tracing::info!("received sid message command: {:?}", message);
+
+                    // The message is kept in the channel so it is picked up by the next
+                    // connection as well.
+                    self.sid_message.send_replace(message);
+                }
+                Some(Command::SendFrame(frame)) => {
+                    tracing::info!("received send frame command: {:?}", frame);
+
+                    // Sending fails when there is no connection, in which case the frame can't be
+                    // sent anyway.
+                    if self.raw_frames.send(frame).is_err() {
+                        tracing::warn!("not sending raw frame because there is no connection");
+                    }
+                }
+                Some(Command::PeriodicFrames(frames)) => {
+                    tracing::info!("received periodic frames command: {:?}", frames);
+
+                    self.periodic_frames.send_replace(frames);
+                }
+                Some(Command::MiuEmulation(emulation)) => {
+                    tracing::info!("received miu emulation command: {:?}", emulation);
+
+                    self.miu_emulation.send_replace(emulation);
+                }
+                Some(Command::Scheduling(scheduling)) => {
+                    tracing::info!("received scheduling command: {:?}", scheduling);
+
+                    self.scheduling = scheduling;
+                }
+                Some(Command::ResetTrip) => {
+                    tracing::info!("received reset trip command");
+
+                    self.odometer.send_modify(|odometer| odometer.reset_trip());
+                }
                 Some(Command::Disconnect) => {
                     tracing::info!("received disconnect command, aborting broadcast task");
 
@@ -294,16 +645,36 @@ pub fn task(runtime: tokio::runtime::Handle) -> (CanClient, CanTask) {
     // because it's really hard to click that fast, but we'll see how it goes.
     let (command_sender, command_receiver) = mpsc::channel::<Command>(8);
     let (state_sender, state_receiver) = watch::channel(State::default());
+    let (odometer_sender, odometer_receiver) = watch::channel(Odometer::default());
+    let (received_frames, _) = broadcast::channel(RECEIVED_FRAMES_CAPACITY);
+    let (transmit_timing_sender, transmit_timing_receiver) = watch::channel(timing::Report::new());
+    let (bus_load_sender, bus_load_receiver) = watch::channel(bus_load::BusLoad::default());
+    let counters = counters::Counters::default();
 
     let client = CanClient {
         runtime,
         command: command_sender,
         connection_state: state_receiver,
+        odometer: odometer_receiver,
+        received_frames: received_frames.clone(),
+        transmit_timing: transmit_timing_receiver,
+        bus_load: bus_load_receiver,
+        counters: counters.clone(),
     };
 
     let task = CanTask {
         command: command_receiver,
         connection_state: state_sender,
+        sid_message: watch::Sender::new(None),
+        raw_frames: broadcast::channel(RAW_FRAMES_CAPACITY).0,
+        periodic_frames: watch::Sender::new(Vec::new()),
+        miu_emulation: watch::Sender::new(miu::Emulation::default()),
+        odometer: odometer_sender,
+        received_frames,
+        transmit_timing: transmit_timing_sender,
+        bus_load: bus_load_sender,
+        counters,
+        scheduling: timing::Scheduling::default(),
     };
 
     (client, task)
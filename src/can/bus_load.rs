@@ -0,0 +1,395 @@
+//! How much of the bit time of the bus is used.
+//!
+//! The receiving socket gets the frames sent by miu-com as well, through the loopback of
+//! socketcan, so the load is measured from the received frames alone. The stuff bits of a frame
+//! are counted exactly, and the worst case for the same frames is kept next to it.
+use socketcan::{CanFrame, EmbeddedFrame, Frame};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use super::{messages, miu, nodes, raw, TICK_MS};
+use crate::miu_state::{Ignition, KeyPosition, MiuState};
+use crate::odometer::Odometer;
+
+/// The bitrate of the bus in the car, used when the interface doesn't have one, like vcan.
+pub const DEFAULT_BITRATE: u32 = 500_000;
+
+/// Time between two samples of the load.
+pub const SAMPLE_PERIOD: Duration = Duration::from_millis(500);
+
+/// Number of samples in the history, a minute.
+pub const HISTORY: usize = 120;
+
+/// Load above which the frames sent by miu-com alone leave too little room for the rest of the
+/// bus.
+pub const SCHEDULE_THRESHOLD: f64 = 0.5;
+
+/// Bits after the CRC that are never stuffed: CRC delimiter, ACK slot and delimiter, end of frame
+/// and the intermission before the next frame.
+const TRAILER_BITS: u32 = 13;
+
+/// Polynomial of the CRC-15 of classic CAN.
+const CRC_POLYNOMIAL: u16 = 0x4599;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BusLoad {
+    pub bitrate: u32,
+
+    /// Share of the bit time used in the last sample, from 0 to 1
+    pub load: f64,
+
+    /// The load of the same frames with the worst case number of stuff bits
+    pub worst_case: f64,
+
+    /// Worst case load of the periodic frames sent by miu-com alone
+    pub schedule: f64,
+
+    /// The load of the last samples, oldest first
+    pub history: VecDeque<f64>,
+}
+
+impl BusLoad {
+    pub fn schedule_too_high(&self) -> bool {
+        self.schedule > SCHEDULE_THRESHOLD
+    }
+}
+
+/// Adds up the bits of the frames on the bus and samples the load.
+pub struct Meter {
+    load: BusLoad,
+    bits: u64,
+    worst_case_bits: u64,
+    sample_start: Instant,
+}
+
+impl Meter {
+    pub fn new(bitrate: u32, now: Instant) -> Self {
+        Self {
+            load: BusLoad {
+                bitrate,
+                ..Default::default()
+            },
+            bits: 0,
+            worst_case_bits: 0,
+            sample_start: now,
+        }
+    }
+
+    pub fn receive(&mut self, frame: &CanFrame) {
+        self.bits += u64::from(frame_bits(frame));
+        self.worst_case_bits += u64::from(worst_case_frame_bits(frame));
+    }
+
+    /// Returns a new sample every [`SAMPLE_PERIOD`]. `schedule` gives the frames sent by miu-com
+    /// with their periods, see [`schedule`].
+    pub fn poll(
+        &mut self,
+        now: Instant,
+        schedule: impl FnOnce() -> Vec<(CanFrame, Duration)>,
+    ) -> Option<&BusLoad> {
+        let elapsed = now.saturating_duration_since(self.sample_start);
+        if elapsed < SAMPLE_PERIOD {
+            return None;
+        }
+        self.sample_start = now;
+
+        let bit_time = f64::from(self.load.bitrate) * elapsed.as_secs_f64();
+        self.load.load = self.bits as f64 / bit_time;
+        self.load.worst_case = self.worst_case_bits as f64 / bit_time;
+        self.bits = 0;
+        self.worst_case_bits = 0;
+
+        if self.load.history.len() == HISTORY {
+            self.load.history.pop_front();
+        }
+        self.load.history.push_back(self.load.load);
+
+        let was_too_high = self.load.schedule_too_high();
+        self.load.schedule = schedule_load(&schedule(), self.load.bitrate);
+        if self.load.schedule_too_high() && !was_too_high {
+            tracing::warn!(
+                "the frames sent by miu-com alone need {:.0}% of the bus at {} kbit/s",
+                self.load.schedule * 100.0,
+                self.load.bitrate / 1000
+            );
+        }
+
+        Some(&self.load)
+    }
+}
+
+/// The bitrate of `interface`, or [`DEFAULT_BITRATE`] when it can't be read.
+pub fn bitrate(interface: &str) -> u32 {
+    let bitrate = socketcan::CanInterface::open(interface)
+        .ok()
+        .and_then(|interface| interface.bit_rate().ok().flatten());
+    match bitrate {
+        Some(bitrate) if bitrate > 0 => bitrate,
+        _ => {
+            tracing::info!(
+                "{} has no bitrate, assuming {} kbit/s for the bus load",
+                interface,
+                DEFAULT_BITRATE / 1000
+            );
+            DEFAULT_BITRATE
+        }
+    }
+}
+
+/// The periodic frames sent for `state`, with their periods. The SID text is left out because it
+/// is only sent when the SID asks for it.
+pub fn schedule(
+    state: &MiuState,
+    periodic_frames: &[raw::PeriodicFrame],
+    emulation: &miu::Emulation,
+) -> Vec<(CanFrame, Duration)> {
+    let mut frames = Vec::new();
+
+    if state.key_position != KeyPosition::Off {
+        let ignition = Ignition::settled(state.key_position);
+        let mut scheduler = super::faults::Scheduler::default();
+        for node in nodes::NODES.iter().filter(|node| state.nodes.get(node.id)) {
+            for &id in node.messages {
+                let fault = state.faults.get(id);
+                if fault.dropout {
+                    continue;
+                }
+                if let Some(Ok(frame)) = messages::encode(id, state, &ignition) {
+                    let period = fault.period_ms.map(Duration::from_millis);
+                    frames.push((
+                        scheduler.apply(frame, &fault),
+                        period.unwrap_or(node.period),
+                    ));
+                }
+            }
+        }
+
+        for message in miu::messages(state, emulation) {
+            let fault = state.faults.get(message.id);
+            if fault.dropout {
+                continue;
+            }
+            let encoded = miu::encode(message.id, state, emulation, &Odometer::default());
+            if let Some(Ok(frame)) = encoded {
+                let period = fault.period_ms.map(Duration::from_millis);
+                frames.push((
+                    scheduler.apply(frame, &fault),
+                    period.unwrap_or(message.period),
+                ));
+            }
+        }
+    }
+
+    for periodic in periodic_frames.iter().filter(|periodic| periodic.enabled) {
+        if let Some(frame) = periodic.frame() {
+            frames.push((frame, Duration::from_millis(periodic.period_ms)));
+        }
+    }
+
+    frames
+}
+
+/// The worst case load of sending every frame at its period. Frames can't be sent faster than
+/// the broadcast loop runs.
+pub fn schedule_load(frames: &[(CanFrame, Duration)], bitrate: u32) -> f64 {
+    let bits_per_second: f64 = frames
+        .iter()
+        .map(|(frame, period)| {
+            let period = (*period).max(Duration::from_millis(TICK_MS));
+            f64::from(worst_case_frame_bits(frame)) / period.as_secs_f64()
+        })
+        .sum();
+    bits_per_second / f64::from(bitrate)
+}
+
+/// The number of bits `frame` takes on the bus, with its stuff bits and the intermission.
+pub fn frame_bits(frame: &CanFrame) -> u32 {
+    let bits = unstuffed_bits(frame);
+    bits.len() as u32 + stuff_bits(&bits) + TRAILER_BITS
+}
+
+/// The most bits a frame with this kind of id and `dlc` can take on the bus.
+pub fn worst_case_bits(extended: bool, dlc: usize) -> u32 {
+    // Start of frame up to and including the CRC
+    let stuffed = if extended { 54 } else { 34 } + 8 * dlc.min(8) as u32;
+    stuffed + (stuffed - 1) / 4 + TRAILER_BITS
+}
+
+fn worst_case_frame_bits(frame: &CanFrame) -> u32 {
+    // Remote frames have a length but no data
+    let length = if frame.is_remote_frame() {
+        0
+    } else {
+        frame.dlc()
+    };
+    worst_case_bits(frame.is_extended(), length)
+}
+
+/// The bits from the start of frame up to and including the CRC, where stuff bits are inserted.
+fn unstuffed_bits(frame: &CanFrame) -> Vec<bool> {
+    let mut bits = Vec::with_capacity(128);
+    let mut push = |value: u32, count: u32| {
+        for bit in (0..count).rev() {
+            bits.push(value >> bit & 1 == 1);
+        }
+    };
+
+    let id = frame.raw_id();
+    let remote = u32::from(frame.is_remote_frame());
+    // Start of frame
+    push(0, 1);
+    if frame.is_extended() {
+        push(id >> 18, 11);
+        // Substitute remote request and identifier extension
+        push(0b11, 2);
+        push(id & 0x3_ffff, 18);
+        push(remote, 1);
+        // Reserved bits
+        push(0, 2);
+    } else {
+        push(id, 11);
+        push(remote, 1);
+        // Identifier extension and reserved bit
+        push(0, 2);
+    }
+    push(frame.dlc() as u32, 4);
+    if !frame.is_remote_frame() {
+        for byte in frame.data() {
+            push(u32::from(*byte), 8);
+        }
+    }
+
+    let crc = crc(&bits);
+    for bit in (0..15).rev() {
+        bits.push(crc >> bit & 1 == 1);
+    }
+    bits
+}
+
+fn crc(bits: &[bool]) -> u16 {
+    bits.iter().fold(0, |crc, bit| {
+        let next = (crc << 1) & 0x7fff;
+        if *bit != (crc >> 14 & 1 == 1) {
+            next ^ CRC_POLYNOMIAL
+        } else {
+            next
+        }
+    })
+}
+
+/// After five equal bits an opposite bit is inserted, which counts towards the next run.
+fn stuff_bits(bits: &[bool]) -> u32 {
+    let mut stuffed = 0;
+    let mut previous = None;
+    let mut run = 0;
+    for &bit in bits {
+        if previous == Some(bit) {
+            run += 1;
+        } else {
+            previous = Some(bit);
+            run = 1;
+        }
+        if run == 5 {
+            stuffed += 1;
+            previous = Some(!bit);
+            run = 1;
+        }
+    }
+    stuffed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use socketcan::{ExtendedId, StandardId};
+
+    #[test]
+    fn it_knows_the_worst_case() {
+        assert_eq!(worst_case_bits(false, 0), 55);
+        assert_eq!(worst_case_bits(false, 8), 135);
+        assert_eq!(worst_case_bits(true, 8), 160);
+    }
+
+    #[test]
+    fn it_counts_the_stuff_bits() {
+        assert_eq!(stuff_bits(&[false; 5]), 1);
+        assert_eq!(stuff_bits(&[false; 10]), 2);
+        assert_eq!(stuff_bits(&[true, false, true, false]), 0);
+        // The stuff bit starts the next run, which is the worst case
+        let worst = [[false; 5].as_slice(), &[true; 4], &[false; 4]].concat();
+        assert_eq!(stuff_bits(&worst), 3);
+
+        // A CRC of zero as well, so 34 zeroes
+        let zeroes = CanFrame::new(StandardId::ZERO, &[]).unwrap();
+        assert_eq!(frame_bits(&zeroes), 47 + 6);
+    }
+
+    #[test]
+    fn it_calculates_the_crc() {
+        // The CRC of a message followed by its CRC is zero
+        let frame = CanFrame::from_raw_id(0x1a0, &[1, 2, 3]).unwrap();
+        assert_eq!(crc(&unstuffed_bits(&frame)), 0);
+    }
+
+    #[test]
+    fn it_samples_the_load() {
+        let now = Instant::now();
+        let mut meter = Meter::new(DEFAULT_BITRATE, now);
+        let frame = CanFrame::new(StandardId::ZERO, &[]).unwrap();
+        // 10% of the bits in half a second
+        for _ in 0..(DEFAULT_BITRATE / 20 / frame_bits(&frame)) {
+            meter.receive(&frame);
+        }
+
+        assert!(meter.poll(now + SAMPLE_PERIOD / 2, Vec::new).is_none());
+        let load = meter.poll(now + SAMPLE_PERIOD, Vec::new).unwrap();
+        assert!((load.load - 0.1).abs() < 0.001);
+        assert_eq!(load.history.len(), 1);
+
+        let load = meter.poll(now + SAMPLE_PERIOD * 2, Vec::new).unwrap();
+        assert_eq!(load.load, 0.0);
+        assert_eq!(load.history.len(), 2);
+    }
+
+    #[test]
+    fn it_calculates_the_load_of_the_schedule() {
+        let state = MiuState {
+            key_position: KeyPosition::On,
+            ..Default::default()
+        };
+        let frames = schedule(&state, &[], &miu::Emulation::default());
+        assert_eq!(frames.len(), 6);
+        // Six frames every 50 ms
+        let bits: u32 = frames
+            .iter()
+            .map(|(frame, _)| worst_case_frame_bits(frame))
+            .sum();
+        let load = schedule_load(&frames, DEFAULT_BITRATE);
+        assert!((load - f64::from(bits) * 20.0 / 500_000.0).abs() < 1e-9);
+
+        let off = schedule(&MiuState::default(), &[], &miu::Emulation::default());
+        assert!(off.is_empty());
+    }
+
+    proptest! {
+        #[test]
+        fn frames_take_no_more_than_the_worst_case(
+            id in 0..=0x1fff_ffffu32,
+            extended: bool,
+            data in proptest::collection::vec(any::<u8>(), 0..=8),
+        ) {
+            let frame = if extended {
+                CanFrame::new(ExtendedId::new(id).unwrap(), &data)
+            } else {
+                CanFrame::new(StandardId::new((id & 0x7ff) as u16).unwrap(), &data)
+            }
+            .unwrap();
+
+            let unstuffed = if extended { 67 } else { 47 } + 8 * data.len() as u32;
+            let bits = frame_bits(&frame);
+            prop_assert!(bits >= unstuffed);
+            prop_assert!(bits <= worst_case_bits(extended, data.len()));
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! Decodes received frames into what an instrument cluster shows.
+//!
+//! This only looks at the bus, not at the local state, so pointed at a car it shows what the real
+//! ECUs send. The vehicle speed and fuel level are the exception: they are read from the frames the
+//! MIU sends itself, since no ECU frame with them is known. They show the emulated MIU, or another
+//! MIU on the bus, rather than a value the cluster received.
+use socketcan::{CanFrame, EmbeddedFrame, Frame};
+use std::time::{Duration, Instant};
+
+use super::{miu, t7, tcm, Message};
+use miu_protocol::signals;
+
+/// A signal that wasn't received for this long is shown as missing.
+pub const SIGNAL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The last received value of a signal.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Signal<T> {
+    pub value: T,
+
+    /// The sender reported the value as unreliable
+    pub fault: bool,
+
+    received: Option<Instant>,
+}
+
+impl<T: Copy> Signal<T> {
+    fn set(&mut self, value: T, fault: u8, time: Instant) {
+        self.value = value;
+        self.fault = fault != 0;
+        self.received = Some(time);
+    }
+
+    /// The value, or `None` when it wasn't received recently.
+    pub fn get(&self, now: Instant) -> Option<T> {
+        self.received
+            .filter(|received| now.saturating_duration_since(*received) < SIGNAL_TIMEOUT)
+            .map(|_| self.value)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Cluster {
+    /// rpm
+    pub engine_speed: Signal<u16>,
+    /// km/h, from the frame the MIU sends
+    pub vehicle_speed: Signal<f32>,
+    /// Position of the boost gauge, from 0 to 255
+    pub boost: Signal<u8>,
+    /// °C
+    pub coolant_temperature: Signal<i16>,
+    /// Litres, from the frame the MIU sends
+    pub fuel_level: Signal<f32>,
+    pub gear_lever: Signal<tcm::Gear>,
+    pub check_engine: Signal<bool>,
+    pub cruise: Signal<bool>,
+    pub limp_home: Signal<bool>,
+    pub check_gearbox: Signal<bool>,
+}
+
+impl Cluster {
+    /// Updates the signals in `frame`. Frames that don't decode are ignored.
+    pub fn receive(&mut self, frame: &CanFrame, time: Instant) {
+        let data = frame.data();
+
+        match frame.raw_id() {
+            t7::EngineSpeedAndThrottle::CAN_ID => {
+                if let Ok(message) = t7::EngineSpeedAndThrottle::decode(data) {
+                    self.engine_speed
+                        .set(message.speed, message.speed_fault, time);
+                }
+            }
+            t7::EngineStatus::CAN_ID => {
+                if let Ok(message) = t7::EngineStatus::decode(data) {
+                    self.check_engine.set(message.check_engine != 0, 0, time);
+                    self.cruise.set(message.cruise_lamp != 0, 0, time);
+                    self.limp_home.set(message.limp_home != 0, 0, time);
+                }
+            }
+            t7::AirAndCoolant::CAN_ID => {
+                if let Ok(message) = t7::AirAndCoolant::decode(data) {
+                    self.coolant_temperature.set(
+                        signals::coolant_temperature(message.coolant_temperature_1_plus_40),
+                        message.coolant_temperature_1_fault,
+                        time,
+                    );
+                }
+            }
+            t7::FuelConsumptionAndBoost::CAN_ID => {
+                if let Ok(message) = t7::FuelConsumptionAndBoost::decode(data) {
+                    self.boost.set(message.boost, 0, time);
+                }
+            }
+            tcm::TransmissionStatus::CAN_ID => {
+                if let Ok(message) = tcm::TransmissionStatus::decode(data) {
+                    self.gear_lever
+                        .set(message.gear_lever, message.gear_lever_fault, time);
+                    self.check_gearbox.set(message.check_gearbox != 0, 0, time);
+                }
+            }
+            miu::VehicleSpeed::CAN_ID => {
+                if let Ok(message) = miu::VehicleSpeed::decode(data) {
+                    self.vehicle_speed.set(
+                        signals::vehicle_speed(message.vehicle_speed),
+                        message.vehicle_speed_fault,
+                        time,
+                    );
+                }
+            }
+            miu::FuelLevel::CAN_ID => {
+                if let Ok(message) = miu::FuelLevel::decode(data) {
+                    self.fuel_level.set(
+                        signals::fuel_level(message.fuel_level),
+                        message.fuel_level_fault,
+                        time,
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::can::messages;
+    use crate::miu_state::{Ignition, KeyPosition, MiuState};
+
+    #[test]
+    fn it_decodes_the_sent_messages() {
+        let state = MiuState {
+            engine_speed: 3000,
+            boost: 128,
+            coolant_temperature: 90,
+            check_engine: true,
+            gear_lever: tcm::Gear::Drive,
+            ..Default::default()
+        };
+        let ignition = Ignition::settled(KeyPosition::Start);
+        let now = Instant::now();
+
+        let mut cluster = Cluster::default();
+        for id in [
+            t7::EngineSpeedAndThrottle::CAN_ID,
+            t7::EngineStatus::CAN_ID,
+            t7::AirAndCoolant::CAN_ID,
+            t7::FuelConsumptionAndBoost::CAN_ID,
+            tcm::TransmissionStatus::CAN_ID,
+        ] {
+            let frame = messages::encode(id, &state, &ignition).unwrap().unwrap();
+            cluster.receive(&frame, now);
+        }
+
+        assert_eq!(cluster.engine_speed.get(now), Some(3000));
+        assert_eq!(cluster.boost.get(now), Some(128));
+        assert_eq!(cluster.coolant_temperature.get(now), Some(90));
+        assert_eq!(cluster.check_engine.get(now), Some(true));
+        assert_eq!(cluster.cruise.get(now), Some(false));
+        assert_eq!(cluster.gear_lever.get(now), Some(tcm::Gear::Drive));
+        assert_eq!(cluster.vehicle_speed.get(now), None);
+    }
+
+    #[test]
+    fn signals_time_out() {
+        let mut signal = Signal::default();
+        let now = Instant::now();
+        signal.set(850_u16, 1, now);
+
+        assert_eq!(signal.get(now), Some(850));
+        assert!(signal.fault);
+        assert_eq!(signal.get(now + SIGNAL_TIMEOUT), None);
+    }
+}
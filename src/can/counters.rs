@@ -0,0 +1,84 @@
+//! Running totals since startup, to see afterwards whether anything went wrong in a long session.
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, PoisonError};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Totals {
+    /// Frames sent per id
+    pub sent: BTreeMap<u32, u64>,
+
+    /// Frames received per id, including the ones miu-com sent through the loopback of socketcan
+    pub received: BTreeMap<u32, u64>,
+
+    /// Connections that ended with an error, per kind of error
+    pub errors: BTreeMap<&'static str, u64>,
+
+    pub connections: u64,
+
+    /// The interface of the last connection
+    pub interface: Option<String>,
+}
+
+impl Totals {
+    /// Connections made after the first one, by the user or a script.
+    pub fn reconnects(&self) -> u64 {
+        self.connections.saturating_sub(1)
+    }
+}
+
+/// The totals, shared between the can task and its clients.
+#[derive(Clone, Default)]
+pub struct Counters(Arc<Mutex<Totals>>);
+
+impl Counters {
+    pub fn totals(&self) -> Totals {
+        self.update(|totals| totals.clone())
+    }
+
+    pub fn sent(&self, id: u32) {
+        self.update(|totals| *totals.sent.entry(id).or_default() += 1);
+    }
+
+    pub fn received(&self, id: u32) {
+        self.update(|totals| *totals.received.entry(id).or_default() += 1);
+    }
+
+    pub fn error(&self, kind: &'static str) {
+        self.update(|totals| *totals.errors.entry(kind).or_default() += 1);
+    }
+
+    pub fn connected(&self, interface: &str) {
+        self.update(|totals| {
+            totals.connections += 1;
+            totals.interface = Some(interface.to_owned());
+        });
+    }
+
+    fn update<T>(&self, update: impl FnOnce(&mut Totals) -> T) -> T {
+        // The totals are always valid, even when another thread panicked while holding the lock.
+        update(&mut self.0.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_counts_per_id() {
+        let counters = Counters::default();
+        counters.sent(0x1a0);
+        counters.sent(0x1a0);
+        counters.received(0x280);
+        counters.error("io");
+        counters.connected("vcan0");
+        counters.clone().connected("can0");
+
+        let totals = counters.totals();
+        assert_eq!(totals.sent[&0x1a0], 2);
+        assert_eq!(totals.received[&0x280], 1);
+        assert_eq!(totals.errors["io"], 1);
+        assert_eq!(totals.reconnects(), 1);
+        assert_eq!(totals.interface.as_deref(), Some("can0"));
+    }
+}
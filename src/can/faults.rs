@@ -0,0 +1,328 @@
+//! Fault injection for the frames sent by the broadcaster.
+//!
+//! Every message can be disturbed independently to see how the cluster deals with missing, late
+//! or broken frames.
+use rand::Rng;
+use serde::de::{Deserializer, IgnoredAny, MapAccess, Visitor};
+use serde::ser::{SerializeMap, Serializer};
+use serde::{Deserialize, Serialize};
+use socketcan::{CanFrame, EmbeddedFrame, Frame};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use super::layout::Layout;
+use super::{miu, t7, tcm, Message, ABS_STATUS_CAN_ID};
+
+/// The messages that can be disturbed, with a name to show to the user: those of the nodes and
+/// those of the emulated MIU that aren't experimental.
+pub const MESSAGES: [(u32, &str); 8] = [
+    (
+        t7::EngineSpeedAndThrottle::CAN_ID,
+        t7::EngineSpeedAndThrottle::NAME,
+    ),
+    (t7::EngineStatus::CAN_ID, t7::EngineStatus::NAME),
+    (t7::AirAndCoolant::CAN_ID, t7::AirAndCoolant::NAME),
+    (
+        t7::FuelConsumptionAndBoost::CAN_ID,
+        t7::FuelConsumptionAndBoost::NAME,
+    ),
+    (
+        tcm::TransmissionStatus::CAN_ID,
+        tcm::TransmissionStatus::NAME,
+    ),
+    (ABS_STATUS_CAN_ID, "ABS status"),
+    (miu::VehicleSpeed::CAN_ID, miu::VehicleSpeed::NAME),
+    (miu::FuelLevel::CAN_ID, miu::FuelLevel::NAME),
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MessageFault {
+    /// Stop sending the message
+    pub dropout: bool,
+
+    /// Send the message at this period instead of the normal one
+    pub period_ms: Option<u64>,
+
+    /// Maximum random deviation from the period
+    pub jitter_ms: u64,
+
+    /// Send the message with this data length, padding with zeroes or cutting off data
+    pub dlc: Option<u8>,
+
+    /// Keep sending the data from the moment this was switched on
+    pub stale: bool,
+}
+
+/// The faults for every message in [`MESSAGES`].
+///
+/// They are serialized as a map keyed by the id in hex, like `"1A0" = { dropout = true }`, so a
+/// patch can change the fault of one message. Ids of messages that aren't sent are skipped.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FaultInjection([MessageFault; MESSAGES.len()]);
+
+impl FaultInjection {
+    fn index(id: u32) -> Option<usize> {
+        MESSAGES.iter().position(|(message, _)| *message == id)
+    }
+
+    pub fn get(&self, id: u32) -> MessageFault {
+        Self::index(id)
+            .map(|index| self.0[index])
+            .unwrap_or_default()
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut MessageFault> {
+        Self::index(id).map(|index| &mut self.0[index])
+    }
+
+    pub fn any(&self) -> bool {
+        self.0.iter().any(|fault| *fault != MessageFault::default())
+    }
+}
+
+impl Serialize for FaultInjection {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(MESSAGES.len()))?;
+        for ((id, _), fault) in MESSAGES.iter().zip(&self.0) {
+            map.serialize_entry(&format!("{:03X}", id), fault)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for FaultInjection {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(FaultInjectionVisitor)
+    }
+}
+
+struct FaultInjectionVisitor;
+
+impl<'de> Visitor<'de> for FaultInjectionVisitor {
+    type Value = FaultInjection;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of faults keyed by the can id in hex")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut faults = FaultInjection::default();
+        while let Some(key) = map.next_key::<String>()? {
+            let fault = u32::from_str_radix(&key, 16)
+                .ok()
+                .and_then(|id| faults.get_mut(id));
+            match fault {
+                Some(fault) => *fault = map.next_value()?,
+                None => {
+                    tracing::warn!("skipping the fault of unknown message {}", key);
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(faults)
+    }
+}
+
+/// Decides when messages are due and applies the faults to the frames.
+#[derive(Default)]
+pub struct Scheduler {
+    next: HashMap<u32, Instant>,
+    stale: HashMap<u32, CanFrame>,
+}
+
+impl Scheduler {
+    /// Returns true when the message with `id`, normally sent every `period`, should be sent at
+    /// `now`.
+    pub fn due(&mut self, id: u32, period: Duration, fault: &MessageFault, now: Instant) -> bool {
+        if fault.dropout {
+            self.next.remove(&id);
+            return false;
+        }
+
+        if self.next.get(&id).is_some_and(|next| now < *next) {
+            return false;
+        }
+
+        let period = fault.period_ms.map(Duration::from_millis).unwrap_or(period);
+        let jitter = Duration::from_millis(fault.jitter_ms);
+        let next = if jitter.is_zero() {
+            now + period
+        } else {
+            let offset = rand::thread_rng().gen_range(Duration::ZERO..=jitter * 2);
+            (now + period + offset).checked_sub(jitter).unwrap_or(now)
+        };
+        self.next.insert(id, next);
+
+        true
+    }
+
+    /// Applies the data faults to a frame that is about to be sent.
+    pub fn apply(&mut self, frame: CanFrame, fault: &MessageFault) -> CanFrame {
+        let id = frame.raw_id();
+
+        let frame = if fault.stale {
+            *self.stale.entry(id).or_insert(frame)
+        } else {
+            self.stale.remove(&id);
+            frame
+        };
+
+        match fault.dlc {
+            Some(dlc) => {
+                let mut data = frame.data().to_vec();
+                data.resize(usize::from(dlc.min(8)), 0);
+                CanFrame::from_raw_id(id, &data).expect("data is at most 8 bytes")
+            }
+            None => frame,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: u32 = t7::EngineStatus::CAN_ID;
+    const PERIOD: Duration = Duration::from_millis(50);
+
+    fn frame(data: &[u8]) -> CanFrame {
+        CanFrame::from_raw_id(ID, data).unwrap()
+    }
+
+    #[test]
+    fn it_sends_at_the_normal_period() {
+        let mut scheduler = Scheduler::default();
+        let fault = MessageFault::default();
+        let now = Instant::now();
+
+        assert!(scheduler.due(ID, PERIOD, &fault, now));
+        assert!(!scheduler.due(ID, PERIOD, &fault, now + PERIOD / 2));
+        assert!(scheduler.due(ID, PERIOD, &fault, now + PERIOD));
+    }
+
+    #[test]
+    fn it_stops_sending_on_dropout() {
+        let mut scheduler = Scheduler::default();
+        let fault = MessageFault {
+            dropout: true,
+            ..Default::default()
+        };
+
+        assert!(!scheduler.due(ID, PERIOD, &fault, Instant::now()));
+    }
+
+    #[test]
+    fn it_sends_at_the_overridden_period() {
+        let mut scheduler = Scheduler::default();
+        let fault = MessageFault {
+            period_ms: Some(500),
+            ..Default::default()
+        };
+        let now = Instant::now();
+
+        assert!(scheduler.due(ID, PERIOD, &fault, now));
+        assert!(!scheduler.due(ID, PERIOD, &fault, now + PERIOD));
+        assert!(scheduler.due(ID, PERIOD, &fault, now + Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn it_keeps_jitter_within_bounds() {
+        let mut scheduler = Scheduler::default();
+        let fault = MessageFault {
+            jitter_ms: 20,
+            ..Default::default()
+        };
+        let now = Instant::now();
+
+        for i in 0..100 {
+            let now = now + Duration::from_secs(i);
+            assert!(scheduler.due(ID, PERIOD, &fault, now));
+            assert!(!scheduler.due(ID, PERIOD, &fault, now + Duration::from_millis(29)));
+            assert!(scheduler.due(ID, PERIOD, &fault, now + Duration::from_millis(70)));
+        }
+    }
+
+    #[test]
+    fn it_changes_the_data_length() {
+        let mut scheduler = Scheduler::default();
+
+        let short = MessageFault {
+            dlc: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            scheduler.apply(frame(&[1, 2, 3, 4]), &short).data(),
+            &[1, 2]
+        );
+
+        let long = MessageFault {
+            dlc: Some(6),
+            ..Default::default()
+        };
+        assert_eq!(
+            scheduler.apply(frame(&[1, 2, 3, 4]), &long).data(),
+            &[1, 2, 3, 4, 0, 0]
+        );
+    }
+
+    #[test]
+    fn it_repeats_stale_data() {
+        let mut scheduler = Scheduler::default();
+        let stale = MessageFault {
+            stale: true,
+            ..Default::default()
+        };
+
+        assert_eq!(scheduler.apply(frame(&[1]), &stale).data(), &[1]);
+        assert_eq!(scheduler.apply(frame(&[2]), &stale).data(), &[1]);
+
+        let fresh = MessageFault::default();
+        assert_eq!(scheduler.apply(frame(&[3]), &fresh).data(), &[3]);
+        assert_eq!(scheduler.apply(frame(&[4]), &stale).data(), &[4]);
+    }
+
+    #[test]
+    fn it_looks_up_faults_by_id() {
+        let mut faults = FaultInjection::default();
+        assert!(!faults.any());
+
+        faults.get_mut(ID).unwrap().dropout = true;
+        assert!(faults.get(ID).dropout);
+        assert!(faults.any());
+
+        assert!(faults.get_mut(0x123).is_none());
+        assert_eq!(faults.get(0x123), MessageFault::default());
+    }
+
+    #[test]
+    fn it_keys_faults_by_id() {
+        let mut faults = FaultInjection::default();
+        faults.get_mut(ID).unwrap().dropout = true;
+
+        let value = serde_json::to_value(faults).unwrap();
+        assert_eq!(value["280"]["dropout"], true);
+        assert_eq!(value["1A0"]["dropout"], false);
+        assert_eq!(
+            serde_json::from_value::<FaultInjection>(value).unwrap(),
+            faults
+        );
+
+        let text = toml::to_string(&faults).unwrap();
+        assert_eq!(toml::from_str::<FaultInjection>(&text).unwrap(), faults);
+    }
+
+    #[test]
+    fn it_skips_unknown_ids() {
+        let faults: FaultInjection = serde_json::from_value(serde_json::json!({
+            "280": { "stale": true },
+            "123": { "dropout": true },
+            "nonsense": 1,
+        }))
+        .unwrap();
+
+        assert!(faults.get(ID).stale);
+        assert_eq!(faults.get(0x123), MessageFault::default());
+    }
+}
@@ -0,0 +1,158 @@
+//! Builds the messages that are sent for a given state.
+use deku::DekuError;
+use socketcan::{CanFrame, Frame};
+
+use super::{frame, t7, tcm, Message, ABS_STATUS_CAN_ID};
+use crate::miu_state::{Ignition, MiuState};
+use miu_protocol::signals;
+
+pub fn engine_speed_and_throttle(
+    state: &MiuState,
+    ignition: &Ignition,
+) -> t7::EngineSpeedAndThrottle {
+    t7::EngineSpeedAndThrottle {
+        speed_fault: state.engine_speed_fault.into(),
+        air_inlet_fault: false.into(),
+        throttle_fault: false.into(),
+        speed: ignition.engine_speed(state),
+        torque: 0,
+        max_torque_at_rpm: 0,
+        accelerator_pedal_position: state.accelerator_pedal.min(100),
+        accelerator_pedal_position_gradient: 0,
+        dti: 0,
+    }
+}
+
+pub fn engine_status(state: &MiuState, ignition: &Ignition) -> t7::EngineStatus {
+    t7::EngineStatus {
+        vehicle_speed_fault: false.into(),
+        brake_light_status: 0,
+        actual_gear: 0,
+        cruise_active: 0,
+        no_ignition_retard: 0,
+        kick_down: 0,
+        clutch_brake: 0,
+        jerk: 0,
+        brake_light: 0,
+        warm_up_shift_pattern: 0,
+        check_filler_cap: 0,
+        warm_up_cycle: 0,
+        automatic: 1,
+        nc_inhibit: 0,
+        gear_shift_inhibit: 0,
+        ac_relay: 0,
+        e_gas_off: 0,
+        limp_home: 0,
+        check_engine: state.check_engine.into(),
+        shift_up: 0,
+        cruise_lamp: state.cruise.into(),
+        rep: 0,
+        engine_started: ignition.engine_started().into(),
+        cruise_included: 1,
+        engine_type: 146,
+        coast_lu_inhibit: 0,
+    }
+}
+
+pub fn air_and_coolant(state: &MiuState) -> t7::AirAndCoolant {
+    t7::AirAndCoolant {
+        coolant_temperature_1_fault: state.coolant_temperature_fault.into(),
+        coolant_temperature_2_fault: state.coolant_temperature_fault.into(),
+        ambient_air_pressure_fault: false.into(),
+        coolant_temperature_1_plus_40: signals::coolant_temperature_raw(
+            state.coolant_temperature.into(),
+        ),
+        coolant_temperature_2_plus_40: signals::coolant_temperature_raw(
+            state.coolant_temperature.into(),
+        ),
+        ambient_air_pressure: 0,
+    }
+}
+
+pub fn fuel_consumption_and_boost(state: &MiuState) -> t7::FuelConsumptionAndBoost {
+    t7::FuelConsumptionAndBoost {
+        ignition_on_fault: false.into(),
+        unknown: 0,
+        fuel_consumed: 0,
+        boost: state.boost,
+    }
+}
+
+pub fn transmission_status(state: &MiuState) -> tcm::TransmissionStatus {
+    tcm::TransmissionStatus {
+        actual_gear_fault: state.actual_gear_fault.into(),
+        gear_lever_fault: state.gear_lever_fault.into(),
+        actual_gear: state.actual_gear,
+        gear_lever: state.gear_lever,
+        check_gearbox: state.check_gearbox.into(),
+        sport: state.sport.into(),
+        winter: state.winter.into(),
+        unknown: 0,
+        freeze_frame_request: 0,
+        check_engine: 0,
+        tcm_cslu: 0,
+        unknown2: 0,
+    }
+}
+
+/// This turns off the abs / traction control warning lights
+pub fn abs_status() -> CanFrame {
+    CanFrame::from_raw_id(ABS_STATUS_CAN_ID, &[0, 0, 0, 0, 0, 0, 0, 0])
+        .expect("from_raw_id can not fail because the id is static and known valid")
+}
+
+/// Encodes the message with `id` the way it is sent for `state`, or `None` if the message is not
+/// sent by miu-com.
+pub fn encode(
+    id: u32,
+    state: &MiuState,
+    ignition: &Ignition,
+) -> Option<Result<CanFrame, DekuError>> {
+    match id {
+        t7::EngineSpeedAndThrottle::CAN_ID => {
+            Some(frame(&engine_speed_and_throttle(state, ignition)))
+        }
+        t7::EngineStatus::CAN_ID => Some(frame(&engine_status(state, ignition))),
+        t7::AirAndCoolant::CAN_ID => Some(frame(&air_and_coolant(state))),
+        t7::FuelConsumptionAndBoost::CAN_ID => Some(frame(&fuel_consumption_and_boost(state))),
+        tcm::TransmissionStatus::CAN_ID => Some(frame(&transmission_status(state))),
+        ABS_STATUS_CAN_ID => Some(Ok(abs_status())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::can::nodes::NODES;
+    use crate::miu_state::KeyPosition;
+    use socketcan::EmbeddedFrame;
+
+    #[test]
+    fn it_encodes_every_sent_message() {
+        let state = MiuState::default();
+        let ignition = Ignition::settled(KeyPosition::On);
+
+        for &id in NODES.iter().flat_map(|node| node.messages) {
+            let frame = encode(id, &state, &ignition).unwrap().unwrap();
+            assert_eq!(frame.raw_id(), id);
+            assert!(frame.data().len() <= 8);
+        }
+
+        assert!(encode(0x123, &state, &ignition).is_none());
+    }
+
+    #[test]
+    fn it_sends_the_accelerator_pedal_position() {
+        let state = MiuState {
+            accelerator_pedal: 42,
+            ..Default::default()
+        };
+        let ignition = Ignition::settled(KeyPosition::On);
+
+        let frame = encode(t7::EngineSpeedAndThrottle::CAN_ID, &state, &ignition)
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame.data()[5], 42);
+    }
+}
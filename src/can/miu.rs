@@ -1,37 +1,184 @@
-use deku::prelude::*;
+//! Emulation of the MIU for benches without a cluster.
+//!
+//! The messages are defined in [`miu_protocol::miu`]. Only the vehicle speed and fuel level are
+//! sent by default. The distance and SID button messages are a guess, they are only sent when the
+//! experimental messages are switched on.
+use deku::DekuError;
+use socketcan::CanFrame;
+use std::time::Duration;
 
-#[derive(Debug, PartialEq, DekuRead, DekuWrite)]
-#[deku(endian = "big")]
-pub struct VehicleSpeed {
-    // CanInRaw.v_Vehicle2Fault
-    #[deku(pad_bits_before = "2", bits = 2)]
-    pub vehicle_speed_fault: u8,
+use super::Message;
+use crate::miu_state::{KeyPosition, MiuState};
+use crate::odometer::Odometer;
+use miu_protocol::signals;
 
-    // CanInRaw.v_Vehicle2
-    #[deku(pad_bits_before = "4")]
-    pub vehicle_speed: u16,
+pub use miu_protocol::miu::*;
 
-    // ActualIn.ST_BoostMeter
-    #[deku(bits = 1)]
-    pub boost_meter_status: u8,
+/// Period of the messages that change quickly, like the vehicle speed and the buttons.
+const FAST_PERIOD: Duration = Duration::from_millis(100);
+
+/// Period of the messages that change slowly, like the fuel level and the distance.
+const SLOW_PERIOD: Duration = Duration::from_millis(1000);
+
+/// Which of the SID buttons are held down.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Buttons {
+    pub clear: bool,
+    pub set: bool,
+    pub down: bool,
+    pub up: bool,
+    pub night_panel: bool,
+}
+
+/// What the emulated MIU does besides following the state.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Emulation {
+    pub enabled: bool,
+
+    /// Also send the distance and SID button messages, whose ids and layouts are a guess
+    pub experimental: bool,
+
+    pub buttons: Buttons,
 }
 
-impl VehicleSpeed {
-    pub const CAN_ID: u32 = 0x2f0;
+/// A message the emulated MIU sends while the key is in.
+#[derive(Debug)]
+pub struct EmulatedMessage {
+    pub id: u32,
+
+    /// Time between two frames, unless a fault says otherwise
+    pub period: Duration,
+
+    /// Only sent with [`Emulation::experimental`], the id and layout are a guess
+    pub experimental: bool,
+}
+
+pub const MESSAGES: [EmulatedMessage; 4] = [
+    EmulatedMessage {
+        id: VehicleSpeed::CAN_ID,
+        period: FAST_PERIOD,
+        experimental: false,
+    },
+    EmulatedMessage {
+        id: SidButtons::CAN_ID,
+        period: FAST_PERIOD,
+        experimental: true,
+    },
+    EmulatedMessage {
+        id: FuelLevel::CAN_ID,
+        period: SLOW_PERIOD,
+        experimental: false,
+    },
+    EmulatedMessage {
+        id: Distance::CAN_ID,
+        period: SLOW_PERIOD,
+        experimental: true,
+    },
+];
+
+/// The messages that are sent with `emulation`. The MIU is powered as long as the key is in.
+pub fn messages(
+    state: &MiuState,
+    emulation: &Emulation,
+) -> impl Iterator<Item = &'static EmulatedMessage> {
+    let powered = emulation.enabled && state.key_position != KeyPosition::Off;
+    let experimental = emulation.experimental;
+    MESSAGES
+        .iter()
+        .filter(move |message| powered && (experimental || !message.experimental))
 }
 
-#[derive(Debug, PartialEq, DekuRead, DekuWrite)]
-#[deku(endian = "big")]
-pub struct FuelLevel {
-    // CanInRaw.V_FuelTankFault
-    #[deku(pad_bits_before = "6", bits = 2)]
-    pub fuel_level_fault: u8,
+/// Encodes the emulated message with `id`, if there is one.
+pub fn encode(
+    id: u32,
+    state: &MiuState,
+    emulation: &Emulation,
+    odometer: &Odometer,
+) -> Option<Result<CanFrame, DekuError>> {
+    match id {
+        VehicleSpeed::CAN_ID => Some(super::frame(&vehicle_speed(state))),
+        SidButtons::CAN_ID => Some(super::frame(&sid_buttons(&emulation.buttons))),
+        FuelLevel::CAN_ID => Some(super::frame(&fuel_level(state))),
+        Distance::CAN_ID => Some(super::frame(&distance(odometer))),
+        _ => None,
+    }
+}
 
-    // CanInRaw.V_FuelTank
-    #[deku(pad_bits_before = "32")]
-    pub fuel_level: u16,
+pub fn vehicle_speed(state: &MiuState) -> VehicleSpeed {
+    VehicleSpeed {
+        vehicle_speed_fault: state.vehicle_speed_fault.into(),
+        vehicle_speed: signals::vehicle_speed_raw(state.vehicle_speed),
+        boost_meter_status: 1,
+    }
 }
 
-impl FuelLevel {
-    pub const CAN_ID: u32 = 0x631;
+pub fn fuel_level(state: &MiuState) -> FuelLevel {
+    FuelLevel {
+        fuel_level_fault: state.fuel_level_fault.into(),
+        fuel_level: signals::fuel_level_raw(state.fuel_level),
+    }
+}
+
+pub fn distance(odometer: &Odometer) -> Distance {
+    Distance {
+        total_km: (odometer.total_km() as u32).min(0xff_ffff),
+        trip_hm: (odometer.trip_km() * 10.0) as u16,
+    }
+}
+
+pub fn sid_buttons(buttons: &Buttons) -> SidButtons {
+    SidButtons {
+        clear: buttons.clear.into(),
+        set: buttons.set.into(),
+        down: buttons.down.into(),
+        up: buttons.up.into(),
+        night_panel: buttons.night_panel.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(state: &MiuState, emulation: &Emulation) -> Vec<u32> {
+        messages(state, emulation)
+            .map(|message| message.id)
+            .collect()
+    }
+
+    #[test]
+    fn the_emulator_sends_while_the_key_is_in() {
+        let enabled = Emulation {
+            enabled: true,
+            ..Default::default()
+        };
+        let mut state = MiuState::default();
+        assert!(ids(&state, &enabled).is_empty());
+
+        state.key_position = KeyPosition::Accessory;
+        assert_eq!(
+            ids(&state, &enabled),
+            [VehicleSpeed::CAN_ID, FuelLevel::CAN_ID]
+        );
+        assert!(ids(&state, &Emulation::default()).is_empty());
+    }
+
+    #[test]
+    fn the_emulator_only_sends_experimental_messages_when_asked() {
+        let emulation = Emulation {
+            enabled: true,
+            experimental: true,
+            ..Default::default()
+        };
+        let state = MiuState {
+            key_position: KeyPosition::On,
+            ..Default::default()
+        };
+
+        assert_eq!(ids(&state, &emulation).len(), 4);
+        for id in ids(&state, &emulation) {
+            let frame = encode(id, &state, &emulation, &Odometer::default());
+            assert!(frame.is_some_and(|frame| frame.is_ok()));
+        }
+    }
 }
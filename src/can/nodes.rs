@@ -0,0 +1,153 @@
+//! The control units that are simulated on the bus.
+//!
+//! Every node wakes up on its own after the ignition is switched on and sends its own messages.
+//! Nodes can be switched off separately to see how the cluster reacts to a missing control unit.
+//!
+//! The wake-up delay and period of every node are fixed in [`NODES`], only whether a node is on
+//! the bus is part of the state. Timing and data faults are set per message of a node, see
+//! [`super::faults`], so a node can be disturbed by disturbing all of its messages.
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use super::{t7, tcm, Message, ABS_STATUS_CAN_ID};
+use crate::miu_state;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeId {
+    T7,
+    Tcm,
+    Abs,
+    Sid,
+}
+
+impl NodeId {
+    pub const ALL: [NodeId; 4] = [NodeId::T7, NodeId::Tcm, NodeId::Abs, NodeId::Sid];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NodeId::T7 => "T7",
+            NodeId::Tcm => "TCM",
+            NodeId::Abs => "ABS",
+            NodeId::Sid => "SID",
+        }
+    }
+}
+
+/// A node that sends its messages periodically.
+#[derive(Debug)]
+pub struct Node {
+    pub id: NodeId,
+
+    /// Time between the ignition switching on and the first message, an approximation, see
+    /// [`miu_state::WAKE_UP_DURATION`]
+    pub wake_up_delay: Duration,
+
+    /// Time between two frames of the same message, unless a fault says otherwise
+    pub period: Duration,
+
+    pub messages: &'static [u32],
+}
+
+/// The periodic nodes. The SID is not in here because it only sends text when it gets the display,
+/// see [`super::sid::Writer`].
+pub const NODES: [Node; 3] = [
+    Node {
+        id: NodeId::T7,
+        wake_up_delay: Duration::from_millis(100),
+        period: Duration::from_millis(50),
+        messages: &[
+            t7::EngineSpeedAndThrottle::CAN_ID,
+            t7::EngineStatus::CAN_ID,
+            t7::AirAndCoolant::CAN_ID,
+            t7::FuelConsumptionAndBoost::CAN_ID,
+        ],
+    },
+    Node {
+        id: NodeId::Tcm,
+        wake_up_delay: miu_state::WAKE_UP_DURATION,
+        period: Duration::from_millis(50),
+        messages: &[tcm::TransmissionStatus::CAN_ID],
+    },
+    Node {
+        id: NodeId::Abs,
+        wake_up_delay: Duration::from_millis(200),
+        period: Duration::from_millis(50),
+        messages: &[ABS_STATUS_CAN_ID],
+    },
+];
+
+/// Which nodes are on the bus.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Nodes {
+    pub t7: bool,
+    pub tcm: bool,
+    pub abs: bool,
+    pub sid: bool,
+}
+
+impl Default for Nodes {
+    fn default() -> Self {
+        Self {
+            t7: true,
+            tcm: true,
+            abs: true,
+            sid: true,
+        }
+    }
+}
+
+impl Nodes {
+    pub fn get(&self, node: NodeId) -> bool {
+        match node {
+            NodeId::T7 => self.t7,
+            NodeId::Tcm => self.tcm,
+            NodeId::Abs => self.abs,
+            NodeId::Sid => self.sid,
+        }
+    }
+
+    pub fn get_mut(&mut self, node: NodeId) -> &mut bool {
+        match node {
+            NodeId::T7 => &mut self.t7,
+            NodeId::Tcm => &mut self.tcm,
+            NodeId::Abs => &mut self.abs,
+            NodeId::Sid => &mut self.sid,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::can::faults::MESSAGES;
+    use crate::can::miu;
+
+    #[test]
+    fn every_message_belongs_to_one_node() {
+        for (id, name) in MESSAGES {
+            // The emulated MIU counts as a node
+            let owners = NODES
+                .iter()
+                .filter(|node| node.messages.contains(&id))
+                .count()
+                + miu::MESSAGES
+                    .iter()
+                    .filter(|message| message.id == id)
+                    .count();
+            assert_eq!(owners, 1, "{} is sent by {} nodes", name, owners);
+        }
+    }
+
+    #[test]
+    fn nodes_can_be_switched_off_from_a_patch() {
+        let mut state = miu_state::MiuState::default();
+        assert!(state.nodes.get(NodeId::Tcm));
+
+        state
+            .patch(serde_json::json!({ "nodes": { "tcm": false } }))
+            .unwrap();
+        assert!(!state.nodes.get(NodeId::Tcm));
+        assert!(state.nodes.get(NodeId::T7));
+    }
+}
@@ -0,0 +1,236 @@
+//! Frames typed in by the user, for experiments with messages miu-com doesn't know about.
+use serde::{Deserialize, Serialize};
+use socketcan::{CanFrame, EmbeddedFrame, StandardId};
+use std::time::{Duration, Instant};
+
+/// A frame that is sent over and over while it is enabled.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PeriodicFrame {
+    pub enabled: bool,
+    pub id: u32,
+    pub data: Vec<u8>,
+    pub period_ms: u64,
+}
+
+impl Default for PeriodicFrame {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            id: 0,
+            data: Vec::new(),
+            period_ms: 100,
+        }
+    }
+}
+
+impl PeriodicFrame {
+    pub fn frame(&self) -> Option<CanFrame> {
+        frame(self.id, &self.data)
+    }
+}
+
+/// The periodic frames as they are stored in the config dir.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct PeriodicFrames {
+    #[serde(default)]
+    pub frames: Vec<PeriodicFrame>,
+}
+
+impl PeriodicFrames {
+    pub const CONFIG_NAME: &'static str = "periodic_frames";
+}
+
+/// Builds a standard frame, or `None` if the id or data doesn't fit.
+pub fn frame(id: u32, data: &[u8]) -> Option<CanFrame> {
+    let id = StandardId::new(u16::try_from(id).ok()?)?;
+    CanFrame::new(id, data)
+}
+
+/// Parses a hexadecimal standard id, like `318` or `0x318`.
+pub fn parse_id(text: &str) -> Option<u32> {
+    let text = text.trim();
+    u32::from_str_radix(text.strip_prefix("0x").unwrap_or(text), 16)
+        .ok()
+        .filter(|id| *id <= u32::from(StandardId::MAX.as_raw()))
+}
+
+/// Parses up to 8 hexadecimal bytes, with or without spaces in between.
+pub fn parse_data(text: &str) -> Option<Vec<u8>> {
+    let digits: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if !digits.is_ascii() || !digits.len().is_multiple_of(2) || digits.len() > 16 {
+        return None;
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+        .collect()
+}
+
+pub fn format_data(data: &[u8]) -> String {
+    data.iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Decides when the periodic frames are due.
+#[derive(Default)]
+pub struct PeriodicSender {
+    frames: Vec<PeriodicFrame>,
+
+    /// When every frame is due next, `None` until it's polled for the first time
+    next: Vec<Option<Instant>>,
+}
+
+impl PeriodicSender {
+    /// Replaces the frames. Frames with the same id and period as before keep their schedule, so
+    /// editing the data or dragging another frame's period doesn't send them early. The others are
+    /// sent at the next poll.
+    pub fn set_frames(&mut self, frames: Vec<PeriodicFrame>) {
+        let mut previous: Vec<(&PeriodicFrame, Option<Instant>)> = self
+            .frames
+            .iter()
+            .zip(self.next.iter().copied().chain(std::iter::repeat(None)))
+            .collect();
+
+        self.next = frames
+            .iter()
+            .map(|frame| {
+                let index = previous
+                    .iter()
+                    .position(|(old, _)| old.id == frame.id && old.period_ms == frame.period_ms)?;
+                previous.remove(index).1
+            })
+            .collect();
+        self.frames = frames;
+    }
+
+    pub fn frames(&self) -> &[PeriodicFrame] {
+        &self.frames
+    }
+
+    /// Returns the frames that should be sent at `now`, with their periods.
+    pub fn poll(&mut self, now: Instant) -> Vec<(CanFrame, Duration)> {
+        self.next.resize(self.frames.len(), None);
+
+        self.frames
+            .iter()
+            .zip(self.next.iter_mut())
+            .filter(|(periodic, _)| periodic.enabled)
+            .filter_map(|(periodic, next)| {
+                if next.is_some_and(|next| now < next) {
+                    return None;
+                }
+
+                // Frames with an invalid period are sent as fast as the broadcast loop runs
+                let period = Duration::from_millis(periodic.period_ms);
+                *next = Some(now + period);
+                periodic.frame().map(|frame| (frame, period))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use socketcan::Frame;
+
+    #[test]
+    fn it_parses_ids() {
+        assert_eq!(parse_id("318"), Some(0x318));
+        assert_eq!(parse_id(" 0x7ff "), Some(0x7ff));
+        assert_eq!(parse_id("800"), None);
+        assert_eq!(parse_id("x"), None);
+    }
+
+    #[test]
+    fn it_parses_data() {
+        assert_eq!(parse_data(""), Some(vec![]));
+        assert_eq!(parse_data("01 02 ff"), Some(vec![1, 2, 0xff]));
+        assert_eq!(parse_data("0102FF"), Some(vec![1, 2, 0xff]));
+        assert_eq!(parse_data("1"), None);
+        assert_eq!(parse_data("00 11 22 33 44 55 66 77 88"), None);
+        assert_eq!(parse_data("zz"), None);
+        assert_eq!(parse_data("äa"), None);
+    }
+
+    #[test]
+    fn it_formats_data() {
+        let data = [0x00, 0x1a, 0xff];
+        assert_eq!(format_data(&data), "00 1A FF");
+        assert_eq!(parse_data(&format_data(&data)).unwrap(), data);
+    }
+
+    #[test]
+    fn it_sends_enabled_frames_at_their_period() {
+        let mut sender = PeriodicSender::default();
+        sender.set_frames(vec![
+            PeriodicFrame {
+                enabled: true,
+                id: 0x318,
+                data: vec![0; 8],
+                period_ms: 100,
+            },
+            PeriodicFrame {
+                enabled: true,
+                id: 0x123,
+                data: vec![1],
+                period_ms: 250,
+            },
+            PeriodicFrame {
+                enabled: false,
+                id: 0x456,
+                data: vec![],
+                period_ms: 10,
+            },
+        ]);
+
+        let now = Instant::now();
+        let ids = |frames: Vec<(CanFrame, Duration)>| {
+            frames.iter().map(|(f, _)| f.raw_id()).collect::<Vec<_>>()
+        };
+
+        let first = sender.poll(now);
+        assert_eq!(first[1].1, Duration::from_millis(250));
+        assert_eq!(ids(first), [0x318, 0x123]);
+        assert!(ids(sender.poll(now + Duration::from_millis(50))).is_empty());
+        assert_eq!(ids(sender.poll(now + Duration::from_millis(100))), [0x318]);
+        assert_eq!(
+            ids(sender.poll(now + Duration::from_millis(250))),
+            [0x318, 0x123]
+        );
+    }
+
+    #[test]
+    fn it_keeps_the_schedule_of_unchanged_frames() {
+        let frame = |id, period_ms| PeriodicFrame {
+            enabled: true,
+            id,
+            data: vec![0],
+            period_ms,
+        };
+        let mut sender = PeriodicSender::default();
+        sender.set_frames(vec![frame(0x318, 100), frame(0x123, 100)]);
+
+        let now = Instant::now();
+        assert_eq!(sender.poll(now).len(), 2);
+
+        // The period of 0x123 changes and a frame is added in front, only those are due
+        sender.set_frames(vec![
+            frame(0x456, 100),
+            PeriodicFrame {
+                data: vec![1],
+                ..frame(0x318, 100)
+            },
+            frame(0x123, 200),
+        ]);
+        let due: Vec<u32> = sender
+            .poll(now + Duration::from_millis(10))
+            .iter()
+            .map(|(frame, _)| frame.raw_id())
+            .collect();
+        assert_eq!(due, [0x456, 0x123]);
+    }
+}
@@ -0,0 +1,324 @@
+//! Text messaging to the Saab Information Display (SID).
+//!
+//! The SID only shows text from one node at a time. A node that wants to show text asks for it
+//! with a [`TextRequest`] and the SID answers with a [`TextGrant`] naming the node that is allowed
+//! to write. The node with the lowest priority value wins. Once granted, the text is written with
+//! a burst of [`TextFrame`]s, three frames of five characters per row. The text has to be
+//! written again regularly, otherwise the SID falls back to its own display.
+//!
+//! The frames are defined in [`miu_protocol::sid`].
+use socketcan::CanFrame;
+use std::time::{Duration, Instant};
+
+pub use miu_protocol::sid::*;
+
+/// Number of characters the SID can show on a single row.
+pub const ROW_LENGTH: usize = 12;
+
+const ROWS: usize = 2;
+const ALL_ROWS: u8 = 0b11;
+const FRAMES_PER_ROW: usize = 3;
+
+/// The node id we use when asking for the display. This is the id the SID uses for the radio.
+pub const SOURCE_ID: u8 = 0x32;
+
+/// Address byte found in every text frame.
+const TEXT_ADDRESS: u8 = 0x96;
+
+const REQUEST_INTERVAL: Duration = Duration::from_millis(1000);
+const WRITE_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// Text to show on the SID.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    pub rows: [String; ROWS],
+    pub priority: u8,
+
+    /// Write the text without waiting for a grant. Useful when there is no SID on the bus.
+    pub ignore_arbitration: bool,
+}
+
+impl Default for Message {
+    fn default() -> Self {
+        Self {
+            rows: Default::default(),
+            priority: 0x19,
+            ignore_arbitration: false,
+        }
+    }
+}
+
+impl Message {
+    /// Splits the text into the frames of a single burst.
+    pub fn text_frames(&self, changed: bool) -> Vec<TextFrame> {
+        let total = ROWS * FRAMES_PER_ROW;
+
+        self.rows
+            .iter()
+            .enumerate()
+            .flat_map(|(row, text)| {
+                let encoded = encode_row(text);
+
+                (0..FRAMES_PER_ROW).map(move |i| {
+                    let index = row * FRAMES_PER_ROW + i;
+                    let mut chars = [0; CHARS_PER_FRAME];
+                    let start = i * CHARS_PER_FRAME;
+                    let end = (start + CHARS_PER_FRAME).min(ROW_LENGTH);
+                    chars[..end - start].copy_from_slice(&encoded[start..end]);
+
+                    TextFrame {
+                        first: (index == 0).into(),
+                        remaining: (total - index - 1) as u8,
+                        address: TEXT_ADDRESS,
+                        changed: changed.into(),
+                        row: row as u8 + 1,
+                        text: chars,
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+/// The SID only knows a subset of ASCII, so anything else is replaced with a question mark. Short
+/// rows are padded with spaces to clear text from a previous message.
+fn encode_row(text: &str) -> [u8; ROW_LENGTH] {
+    let mut row = [b' '; ROW_LENGTH];
+
+    for (i, c) in text.chars().take(ROW_LENGTH).enumerate() {
+        row[i] = if c.is_ascii() && !c.is_ascii_control() {
+            c as u8
+        } else {
+            b'?'
+        };
+    }
+
+    row
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WriterState {
+    Idle,
+    Requesting,
+    Granted,
+    Denied,
+}
+
+/// Keeps track of the arbitration with the SID and decides when frames have to be sent.
+pub struct Writer {
+    message: Option<Message>,
+    state: WriterState,
+    changed: bool,
+    last_request: Option<Instant>,
+    last_write: Option<Instant>,
+}
+
+impl Default for Writer {
+    fn default() -> Self {
+        Self {
+            message: None,
+            state: WriterState::Idle,
+            changed: false,
+            last_request: None,
+            last_write: None,
+        }
+    }
+}
+
+impl Writer {
+    pub fn state(&self) -> WriterState {
+        self.state
+    }
+
+    pub fn set_message(&mut self, message: Option<Message>) {
+        if message == self.message {
+            return;
+        }
+
+        if message.is_some() {
+            self.state = WriterState::Requesting;
+            self.changed = true;
+            self.last_request = None;
+            self.last_write = None;
+        }
+
+        self.message = message;
+    }
+
+    pub fn handle_grant(&mut self, grant: &TextGrant) {
+        if self.message.is_none() {
+            return;
+        }
+
+        self.state = if grant.source == SOURCE_ID && grant.rows != 0 {
+            WriterState::Granted
+        } else {
+            WriterState::Denied
+        };
+    }
+
+    /// Returns the frames that should be sent at `now`.
+    pub fn poll(&mut self, now: Instant) -> Vec<CanFrame> {
+        let due = |last: Option<Instant>, interval| match last {
+            Some(last) => now.duration_since(last) >= interval,
+            None => true,
+        };
+
+        let mut frames = Vec::new();
+
+        let Some(message) = &self.message else {
+            // Release the display once, so the SID doesn't wait for us to time out.
+            if self.state != WriterState::Idle {
+                self.state = WriterState::Idle;
+                frames.push(request_frame(0, 0xff));
+            }
+
+            return frames;
+        };
+
+        if due(self.last_request, REQUEST_INTERVAL) {
+            self.last_request = Some(now);
+            frames.push(request_frame(ALL_ROWS, message.priority));
+        }
+
+        let may_write = self.state == WriterState::Granted || message.ignore_arbitration;
+        if may_write && (self.changed || due(self.last_write, WRITE_INTERVAL)) {
+            self.last_write = Some(now);
+            frames.extend(
+                message.text_frames(self.changed).iter().map(|frame| {
+                    super::frame(frame).expect("text frames always fit in a can frame")
+                }),
+            );
+            self.changed = false;
+        }
+
+        frames
+    }
+}
+
+fn request_frame(rows: u8, priority: u8) -> CanFrame {
+    super::frame(&TextRequest {
+        source: SOURCE_ID,
+        unknown: 0x02,
+        rows,
+        priority,
+    })
+    .expect("text requests always fit in a can frame")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::can::Message as _;
+    use socketcan::{EmbeddedFrame, Frame};
+
+    fn message() -> Message {
+        Message {
+            rows: [String::from("HELLO"), String::from("WORLD")],
+            ..Default::default()
+        }
+    }
+
+    fn ids(frames: &[CanFrame]) -> Vec<u32> {
+        frames.iter().map(|frame| frame.raw_id()).collect()
+    }
+
+    #[test]
+    fn it_splits_rows_into_frames() {
+        let frames = message().text_frames(true);
+
+        assert_eq!(frames.len(), 6);
+        assert_eq!(
+            frames[0].encode().unwrap(),
+            vec![0x45, 0x96, 0x81, b'H', b'E', b'L', b'L', b'O']
+        );
+        assert_eq!(
+            frames[2].encode().unwrap(),
+            vec![0x03, 0x96, 0x81, b' ', b' ', 0, 0, 0]
+        );
+        assert_eq!(
+            frames[5].encode().unwrap(),
+            vec![0x00, 0x96, 0x82, b' ', b' ', 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn it_replaces_unsupported_characters() {
+        assert_eq!(&encode_row("Tå\n")[..4], b"T?? ");
+        assert_eq!(encode_row("THIS IS WAY TOO LONG"), *b"THIS IS WAY ");
+    }
+
+    #[test]
+    fn it_waits_for_a_grant_before_writing() {
+        let mut writer = Writer::default();
+        let now = Instant::now();
+
+        writer.set_message(Some(message()));
+        assert_eq!(ids(&writer.poll(now)), vec![TextRequest::CAN_ID]);
+        assert_eq!(writer.state(), WriterState::Requesting);
+
+        writer.handle_grant(&TextGrant {
+            rows: ALL_ROWS,
+            source: SOURCE_ID,
+        });
+        assert_eq!(writer.state(), WriterState::Granted);
+        assert_eq!(ids(&writer.poll(now)), vec![TextFrame::CAN_ID; 6]);
+
+        // Nothing new to send until the intervals have passed
+        assert!(writer.poll(now + Duration::from_millis(50)).is_empty());
+        assert_eq!(
+            ids(&writer.poll(now + REQUEST_INTERVAL)).len(),
+            1 + FRAMES_PER_ROW * ROWS
+        );
+    }
+
+    #[test]
+    fn it_stops_writing_when_another_node_wins() {
+        let mut writer = Writer::default();
+        let now = Instant::now();
+
+        writer.set_message(Some(message()));
+        writer.handle_grant(&TextGrant {
+            rows: ALL_ROWS,
+            source: SOURCE_ID,
+        });
+        writer.poll(now);
+
+        writer.handle_grant(&TextGrant {
+            rows: ALL_ROWS,
+            source: 0x12,
+        });
+        assert_eq!(writer.state(), WriterState::Denied);
+        assert_eq!(
+            ids(&writer.poll(now + REQUEST_INTERVAL)),
+            vec![TextRequest::CAN_ID]
+        );
+    }
+
+    #[test]
+    fn it_releases_the_display_when_the_message_is_cleared() {
+        let mut writer = Writer::default();
+        let now = Instant::now();
+
+        writer.set_message(Some(message()));
+        writer.poll(now);
+        writer.set_message(None);
+
+        let frames = writer.poll(now);
+        assert_eq!(ids(&frames), vec![TextRequest::CAN_ID]);
+        assert_eq!(frames[0].data()[2], 0);
+        assert!(writer.poll(now).is_empty());
+    }
+
+    #[test]
+    fn it_writes_without_grant_when_ignoring_arbitration() {
+        let mut writer = Writer::default();
+
+        writer.set_message(Some(Message {
+            ignore_arbitration: true,
+            ..message()
+        }));
+
+        assert_eq!(ids(&writer.poll(Instant::now())).len(), 7);
+    }
+}
@@ -0,0 +1,396 @@
+//! How well the broadcaster keeps to the periods of the messages it sends.
+//!
+//! The send time of a frame is taken when the socket has accepted it, so the statistics include
+//! the delays of the scheduler as well as the socket. A gap of many periods, like after a dropout
+//! or with the key off, starts a new series instead of counting as a late frame.
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+use tokio::time;
+
+/// Number of periods per message that the jitter percentile is taken over.
+const HISTORY: usize = 1000;
+
+/// A gap of this many periods means the message was stopped rather than late.
+const RESTART_PERIODS: u32 = 10;
+
+/// Time between two reports.
+pub const REPORT_PERIOD: Duration = Duration::from_secs(1);
+
+/// Time between two summaries in the log.
+const LOG_PERIOD: Duration = Duration::from_secs(30);
+
+/// Time spent spinning before every tick with real-time scheduling. Timers in tokio have a
+/// resolution of a millisecond, so they wake up the thread a bit early and the rest is waited out.
+const SPIN_TIME: Duration = Duration::from_millis(1);
+
+/// Priority of the broadcast thread with real-time scheduling, below the interrupt threads of the
+/// kernel at 50.
+const REALTIME_PRIORITY: i32 = 40;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum Scheduling {
+    /// Run on the shared tokio runtime with its normal timers
+    #[default]
+    Normal,
+    /// Run on a thread of its own with a real-time priority and spin for the last moment before
+    /// every tick
+    Realtime,
+}
+
+/// The timing of the frames sent with one id.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MessageTiming {
+    pub id: u32,
+
+    /// The period the frames are supposed to be sent at, unknown for frames that aren't periodic
+    pub period: Option<Duration>,
+
+    pub frames: u64,
+    pub mean_period: Duration,
+    pub max_period: Duration,
+
+    /// 99th percentile of the deviation from the period, or from the mean when the period is
+    /// unknown
+    pub p99_jitter: Duration,
+
+    /// Number of frames that came later than the period plus the tolerance
+    pub missed_deadlines: u64,
+}
+
+/// The timing of every id that was sent since connecting, ordered by id.
+pub type Report = Vec<MessageTiming>;
+
+struct Series {
+    period: Option<Duration>,
+    last: Option<Instant>,
+    frames: u64,
+    intervals: u32,
+    total: Duration,
+    max: Duration,
+    recent: VecDeque<Duration>,
+    missed: u64,
+    logged_missed: u64,
+}
+
+impl Series {
+    fn new(period: Option<Duration>) -> Self {
+        Self {
+            period,
+            last: None,
+            frames: 0,
+            intervals: 0,
+            total: Duration::ZERO,
+            max: Duration::ZERO,
+            recent: VecDeque::with_capacity(HISTORY),
+            missed: 0,
+            logged_missed: 0,
+        }
+    }
+
+    fn mean(&self) -> Duration {
+        self.total.checked_div(self.intervals).unwrap_or_default()
+    }
+
+    fn timing(&self, id: u32) -> MessageTiming {
+        let mean = self.mean();
+        let reference = self.period.unwrap_or(mean);
+        let mut deviations: Vec<Duration> = self
+            .recent
+            .iter()
+            .map(|interval| interval.abs_diff(reference))
+            .collect();
+        deviations.sort_unstable();
+        // The nearest rank, so one outlier in a hundred periods doesn't show up
+        let p99_jitter = match deviations.len() {
+            0 => Duration::ZERO,
+            len => deviations[(len * 99).div_ceil(100) - 1],
+        };
+
+        MessageTiming {
+            id,
+            period: self.period,
+            frames: self.frames,
+            mean_period: mean,
+            max_period: self.max,
+            p99_jitter,
+            missed_deadlines: self.missed,
+        }
+    }
+}
+
+/// Keeps the statistics of the frames sent since connecting.
+pub struct Recorder {
+    tolerance: Duration,
+    series: BTreeMap<u32, Series>,
+    next_report: Option<Instant>,
+    next_log: Option<Instant>,
+}
+
+impl Recorder {
+    /// A frame misses its deadline when it comes later than its period plus `tolerance`.
+    pub fn new(tolerance: Duration) -> Self {
+        Self {
+            tolerance,
+            series: BTreeMap::new(),
+            next_report: None,
+            next_log: None,
+        }
+    }
+
+    /// Records that a frame with `id`, normally sent every `period`, was sent at `sent`.
+    pub fn record(&mut self, id: u32, period: Option<Duration>, sent: Instant) {
+        let series = self.series.entry(id).or_insert_with(|| Series::new(period));
+        // The period changes when a fault is injected, the old statistics don't apply anymore.
+        if series.period != period {
+            *series = Series {
+                frames: series.frames,
+                ..Series::new(period)
+            };
+        }
+        series.frames += 1;
+
+        let Some(last) = series.last.replace(sent) else {
+            return;
+        };
+        let interval = sent.saturating_duration_since(last);
+        if period.is_some_and(|period| interval > period * RESTART_PERIODS) {
+            return;
+        }
+
+        series.intervals += 1;
+        series.total += interval;
+        series.max = series.max.max(interval);
+        if series.recent.len() == HISTORY {
+            series.recent.pop_front();
+        }
+        series.recent.push_back(interval);
+        if period.is_some_and(|period| interval > period + self.tolerance) {
+            series.missed += 1;
+        }
+    }
+
+    pub fn report(&self) -> Report {
+        self.series
+            .iter()
+            .map(|(id, series)| series.timing(*id))
+            .collect()
+    }
+
+    /// Returns a report every [`REPORT_PERIOD`], and logs the missed deadlines since the last one
+    /// and a summary every now and then.
+    pub fn poll(&mut self, now: Instant) -> Option<Report> {
+        let next_report = *self.next_report.get_or_insert(now + REPORT_PERIOD);
+        if now < next_report {
+            return None;
+        }
+        self.next_report = Some(now + REPORT_PERIOD);
+
+        for (id, series) in &mut self.series {
+            if series.missed > series.logged_missed {
+                tracing::warn!(
+                    "{:03X} missed {} deadlines",
+                    id,
+                    series.missed - series.logged_missed
+                );
+            }
+            series.logged_missed = series.missed;
+        }
+
+        let report = self.report();
+        let next_log = *self.next_log.get_or_insert(now + LOG_PERIOD);
+        if now >= next_log {
+            self.next_log = Some(now + LOG_PERIOD);
+            for timing in &report {
+                tracing::info!(
+                    "{:03X}: {} frames, mean period {:?}, max {:?}, p99 jitter {:?}, {} missed deadlines",
+                    timing.id,
+                    timing.frames,
+                    timing.mean_period,
+                    timing.max_period,
+                    timing.p99_jitter,
+                    timing.missed_deadlines
+                );
+            }
+        }
+
+        Some(report)
+    }
+}
+
+/// The clock of the broadcast loop.
+pub enum Ticker {
+    Interval(time::Interval),
+    Spin { period: Duration, next: Instant },
+}
+
+impl Ticker {
+    pub fn new(period: Duration, scheduling: Scheduling) -> Self {
+        match scheduling {
+            Scheduling::Normal => Self::Interval(time::interval(period)),
+            Scheduling::Realtime => Self::Spin {
+                period,
+                next: Instant::now(),
+            },
+        }
+    }
+
+    /// Waits for the next tick. This is cancel safe, a tick is only used up when it's returned.
+    pub async fn tick(&mut self) {
+        match self {
+            Self::Interval(interval) => {
+                interval.tick().await;
+            }
+            Self::Spin { period, next } => {
+                let wake_up = next.checked_sub(SPIN_TIME).unwrap_or(*next);
+                time::sleep_until(wake_up.into()).await;
+                while Instant::now() < *next {
+                    std::hint::spin_loop();
+                }
+
+                // Skip the ticks that were missed completely instead of catching up in a burst
+                *next += *period;
+                let now = Instant::now();
+                if *next < now {
+                    *next = now + *period;
+                }
+            }
+        }
+    }
+}
+
+/// Runs `future` on a thread of its own, with a real-time priority when the system allows it.
+///
+/// The thread stops when the returned future is dropped, so aborting the task it runs in works
+/// the same as with a normal task.
+pub async fn run_realtime<F>(future: F) -> std::io::Result<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let (output_sender, output) = oneshot::channel();
+    let (_stop, stopped) = oneshot::channel::<()>();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    std::thread::Builder::new()
+        .name(String::from("can-broadcast"))
+        .spawn(move || {
+            set_realtime_priority();
+            runtime.block_on(async {
+                tokio::select! {
+                    output = future => {
+                        let _ = output_sender.send(output);
+                    }
+                    _ = stopped => {}
+                }
+            });
+        })?;
+
+    output
+        .await
+        .map_err(|_| std::io::Error::other("the broadcast thread stopped"))
+}
+
+fn set_realtime_priority() {
+    let parameters = libc::sched_param {
+        sched_priority: REALTIME_PRIORITY,
+    };
+    // SAFETY: the parameters outlive the call and 0 is the calling thread.
+    let result = unsafe { libc::sched_setscheduler(0, libc::SCHED_FIFO, &parameters) };
+    if result == 0 {
+        tracing::info!("broadcasting with real-time priority {}", REALTIME_PRIORITY);
+    } else {
+        tracing::warn!(
+            "unable to get a real-time priority, this needs CAP_SYS_NICE or an rtprio limit: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: u32 = 0x1a0;
+    const PERIOD: Duration = Duration::from_millis(50);
+    const TOLERANCE: Duration = Duration::from_millis(5);
+
+    fn record(intervals_ms: &[u64]) -> MessageTiming {
+        let mut recorder = Recorder::new(TOLERANCE);
+        let mut sent = Instant::now();
+        recorder.record(ID, Some(PERIOD), sent);
+        for interval in intervals_ms {
+            sent += Duration::from_millis(*interval);
+            recorder.record(ID, Some(PERIOD), sent);
+        }
+        recorder.report().remove(0)
+    }
+
+    #[test]
+    fn it_measures_the_periods() {
+        let timing = record(&[50, 50, 48, 52, 60]);
+
+        assert_eq!(timing.frames, 6);
+        assert_eq!(timing.mean_period, Duration::from_millis(52));
+        assert_eq!(timing.max_period, Duration::from_millis(60));
+        assert_eq!(timing.p99_jitter, Duration::from_millis(10));
+        assert_eq!(timing.missed_deadlines, 1);
+    }
+
+    #[test]
+    fn it_takes_the_99th_percentile_of_the_jitter() {
+        let mut intervals = vec![50; 98];
+        intervals.extend([53, 80]);
+
+        let timing = record(&intervals);
+        assert_eq!(timing.p99_jitter, Duration::from_millis(3));
+        assert_eq!(timing.max_period, Duration::from_millis(80));
+        assert_eq!(timing.missed_deadlines, 1);
+    }
+
+    #[test]
+    fn it_starts_over_after_a_long_gap() {
+        let timing = record(&[50, 5000, 50]);
+
+        assert_eq!(timing.frames, 4);
+        assert_eq!(timing.max_period, PERIOD);
+        assert_eq!(timing.missed_deadlines, 0);
+    }
+
+    #[test]
+    fn it_uses_the_mean_for_frames_without_a_period() {
+        let mut recorder = Recorder::new(TOLERANCE);
+        let now = Instant::now();
+        for sent in [0, 100, 300, 400] {
+            recorder.record(ID, None, now + Duration::from_millis(sent));
+        }
+
+        let timing = &recorder.report()[0];
+        assert_eq!(timing.mean_period, Duration::from_millis(400) / 3);
+        assert_eq!(timing.missed_deadlines, 0);
+    }
+
+    #[test]
+    fn it_reports_every_report_period() {
+        let mut recorder = Recorder::new(TOLERANCE);
+        let now = Instant::now();
+        recorder.record(ID, Some(PERIOD), now);
+
+        assert!(recorder.poll(now).is_none());
+        assert!(recorder.poll(now + REPORT_PERIOD / 2).is_none());
+        assert_eq!(recorder.poll(now + REPORT_PERIOD).map(|r| r.len()), Some(1));
+        assert!(recorder.poll(now + REPORT_PERIOD).is_none());
+    }
+
+    #[tokio::test]
+    async fn it_runs_futures_on_a_thread() {
+        let name = run_realtime(async { std::thread::current().name().map(String::from) })
+            .await
+            .unwrap();
+        assert_eq!(name.as_deref(), Some("can-broadcast"));
+    }
+}
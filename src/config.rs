@@ -0,0 +1,84 @@
+//! Files that are kept between sessions, stored as TOML in the user's config directory.
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("no config directory for this user")]
+    NoConfigDir,
+    #[error("unable to read or write config file")]
+    IO(#[from] std::io::Error),
+    #[error("unable to parse config file")]
+    Parse(#[from] toml::de::Error),
+    #[error("unable to serialize config file")]
+    Serialize(#[from] toml::ser::Error),
+}
+
+/// The directory the config files are kept in, `~/.config/miu-com` on Linux.
+pub fn dir() -> Result<PathBuf, ConfigError> {
+    dirs::config_dir()
+        .map(|dir| dir.join(env!("CARGO_PKG_NAME")))
+        .ok_or(ConfigError::NoConfigDir)
+}
+
+fn path(name: &str) -> Result<PathBuf, ConfigError> {
+    Ok(dir()?.join(name).with_extension("toml"))
+}
+
+/// Loads the config file `name`, or the default when it doesn't exist yet.
+pub fn load<T: DeserializeOwned + Default>(name: &str) -> Result<T, ConfigError> {
+    read(&path(name)?)
+}
+
+pub fn save<T: Serialize>(name: &str, value: &T) -> Result<(), ConfigError> {
+    write(&path(name)?, value)
+}
+
+fn read<T: DeserializeOwned + Default>(path: &Path) -> Result<T, ConfigError> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => Ok(toml::from_str(&contents)?),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+fn write<T: Serialize>(path: &Path, value: &T) -> Result<(), ConfigError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    std::fs::write(path, toml::to_string_pretty(value)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+    struct Example {
+        name: String,
+        values: Vec<u8>,
+    }
+
+    #[test]
+    fn it_writes_and_reads_back() {
+        let path = std::env::temp_dir()
+            .join(format!("miu-com-test-{}", std::process::id()))
+            .join("example.toml");
+        let example = Example {
+            name: String::from("example"),
+            values: vec![1, 2, 3],
+        };
+
+        assert_eq!(read::<Example>(&path).unwrap(), Example::default());
+
+        write(&path, &example).unwrap();
+        assert_eq!(read::<Example>(&path).unwrap(), example);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,640 @@
+//! Turns recorded frames into a table of physical signals, for pandas or a spreadsheet.
+//!
+//! Every known frame is decoded with the message structs. The table has a row per decoded frame,
+//! or a row per period when resampling, and a column per signal. A signal keeps its last value
+//! until the next frame with it arrives, and is empty until the first one.
+use clap::ValueEnum;
+use parquet::basic::Compression;
+use parquet::data_type::DoubleType;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use socketcan::{EmbeddedFrame, Frame};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::can::{miu, t7, tcm, Message};
+use crate::recording::{self, RecordedFrame, RecordingError};
+use miu_protocol::signals;
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("unable to write export")]
+    IO(#[from] std::io::Error),
+    #[error("unable to read recording")]
+    Recording(#[from] RecordingError),
+    #[error("unable to write parquet file")]
+    Parquet(#[from] parquet::errors::ParquetError),
+    #[error("unknown signal {0}, the known signals are {1}")]
+    UnknownSignal(String, String),
+    #[error("unknown output format for {0}, use --format")]
+    UnknownFormat(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Format {
+    Csv,
+    Parquet,
+}
+
+impl Format {
+    /// The format that goes with the extension of `path`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "csv" => Some(Self::Csv),
+            "parquet" => Some(Self::Parquet),
+            _ => None,
+        }
+    }
+}
+
+/// How the values within a period are combined when resampling.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum Aggregation {
+    /// The last value received before the end of the period
+    #[default]
+    Last,
+    /// The mean of the values received in the period, or the last value when there are none
+    Mean,
+}
+
+pub struct Signal {
+    pub name: &'static str,
+    pub unit: &'static str,
+    id: u32,
+    value: fn(&[u8]) -> Option<f64>,
+}
+
+fn field<M: Message>(data: &[u8], value: impl Fn(&M) -> f64) -> Option<f64> {
+    M::decode(data).ok().map(|message| value(&message))
+}
+
+pub static SIGNALS: [Signal; 15] = [
+    Signal {
+        name: "engine_speed",
+        unit: "rpm",
+        id: t7::EngineSpeedAndThrottle::CAN_ID,
+        value: |data| field(data, |m: &t7::EngineSpeedAndThrottle| f64::from(m.speed)),
+    },
+    Signal {
+        name: "accelerator_pedal_position",
+        unit: "raw",
+        id: t7::EngineSpeedAndThrottle::CAN_ID,
+        value: |data| {
+            field(data, |m: &t7::EngineSpeedAndThrottle| {
+                f64::from(m.accelerator_pedal_position)
+            })
+        },
+    },
+    Signal {
+        name: "torque",
+        unit: "raw",
+        id: t7::EngineSpeedAndThrottle::CAN_ID,
+        value: |data| field(data, |m: &t7::EngineSpeedAndThrottle| f64::from(m.torque)),
+    },
+    Signal {
+        name: "check_engine",
+        unit: "",
+        id: t7::EngineStatus::CAN_ID,
+        value: |data| field(data, |m: &t7::EngineStatus| f64::from(m.check_engine)),
+    },
+    Signal {
+        name: "cruise_lamp",
+        unit: "",
+        id: t7::EngineStatus::CAN_ID,
+        value: |data| field(data, |m: &t7::EngineStatus| f64::from(m.cruise_lamp)),
+    },
+    Signal {
+        name: "limp_home",
+        unit: "",
+        id: t7::EngineStatus::CAN_ID,
+        value: |data| field(data, |m: &t7::EngineStatus| f64::from(m.limp_home)),
+    },
+    Signal {
+        name: "coolant_temperature",
+        unit: "°C",
+        id: t7::AirAndCoolant::CAN_ID,
+        value: |data| {
+            field(data, |m: &t7::AirAndCoolant| {
+                f64::from(signals::coolant_temperature(
+                    m.coolant_temperature_1_plus_40,
+                ))
+            })
+        },
+    },
+    Signal {
+        name: "ambient_air_pressure",
+        unit: "raw",
+        id: t7::AirAndCoolant::CAN_ID,
+        value: |data| {
+            field(data, |m: &t7::AirAndCoolant| {
+                f64::from(m.ambient_air_pressure)
+            })
+        },
+    },
+    Signal {
+        name: "fuel_consumed",
+        unit: "raw",
+        id: t7::FuelConsumptionAndBoost::CAN_ID,
+        value: |data| {
+            field(data, |m: &t7::FuelConsumptionAndBoost| {
+                f64::from(m.fuel_consumed)
+            })
+        },
+    },
+    Signal {
+        name: "boost",
+        unit: "%",
+        id: t7::FuelConsumptionAndBoost::CAN_ID,
+        value: |data| {
+            field(data, |m: &t7::FuelConsumptionAndBoost| {
+                f64::from(signals::boost(m.boost)) * 100.0
+            })
+        },
+    },
+    Signal {
+        name: "gear_lever",
+        unit: "code",
+        id: tcm::TransmissionStatus::CAN_ID,
+        value: |data| {
+            field(data, |m: &tcm::TransmissionStatus| {
+                f64::from(u8::from(m.gear_lever))
+            })
+        },
+    },
+    Signal {
+        name: "actual_gear",
+        unit: "code",
+        id: tcm::TransmissionStatus::CAN_ID,
+        value: |data| {
+            field(data, |m: &tcm::TransmissionStatus| {
+                f64::from(u8::from(m.actual_gear))
+            })
+        },
+    },
+    Signal {
+        name: "check_gearbox",
+        unit: "",
+        id: tcm::TransmissionStatus::CAN_ID,
+        value: |data| {
+            field(data, |m: &tcm::TransmissionStatus| {
+                f64::from(m.check_gearbox)
+            })
+        },
+    },
+    Signal {
+        name: "vehicle_speed",
+        unit: "km/h",
+        id: miu::VehicleSpeed::CAN_ID,
+        value: |data| {
+            field(data, |m: &miu::VehicleSpeed| {
+                f64::from(signals::vehicle_speed(m.vehicle_speed))
+            })
+        },
+    },
+    Signal {
+        name: "fuel_level",
+        unit: "l",
+        id: miu::FuelLevel::CAN_ID,
+        value: |data| {
+            field(data, |m: &miu::FuelLevel| {
+                f64::from(signals::fuel_level(m.fuel_level))
+            })
+        },
+    },
+];
+
+/// The signals with `names`, or all of them when `names` is empty.
+pub fn select(names: &[String]) -> Result<Vec<&'static Signal>, ExportError> {
+    if names.is_empty() {
+        return Ok(SIGNALS.iter().collect());
+    }
+
+    names
+        .iter()
+        .map(|name| {
+            SIGNALS
+                .iter()
+                .find(|signal| signal.name == name)
+                .ok_or_else(|| {
+                    let known: Vec<String> = SIGNALS
+                        .iter()
+                        .map(|signal| match signal.unit {
+                            "" => signal.name.to_string(),
+                            unit => format!("{} ({})", signal.name, unit),
+                        })
+                        .collect();
+                    ExportError::UnknownSignal(name.clone(), known.join(", "))
+                })
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Resampling {
+    /// A row every period instead of a row per frame
+    pub period: Option<Duration>,
+    pub aggregation: Aggregation,
+}
+
+/// Decoded signals, with a column per signal.
+pub struct Table {
+    pub signals: Vec<&'static Signal>,
+    /// Seconds since the unix epoch, and a value per signal
+    pub rows: Vec<(f64, Vec<Option<f64>>)>,
+}
+
+/// The values in the current row, or period when resampling.
+struct Accumulator {
+    last: Vec<Option<f64>>,
+    sums: Vec<(f64, u32)>,
+}
+
+impl Accumulator {
+    fn new(columns: usize) -> Self {
+        Self {
+            last: vec![None; columns],
+            sums: vec![(0.0, 0); columns],
+        }
+    }
+
+    fn add(&mut self, column: usize, value: f64) {
+        self.last[column] = Some(value);
+        let (sum, count) = &mut self.sums[column];
+        *sum += value;
+        *count += 1;
+    }
+
+    /// The row for the period that just ended, which starts the next one.
+    fn take(&mut self, aggregation: Aggregation) -> Vec<Option<f64>> {
+        let row = match aggregation {
+            Aggregation::Last => self.last.clone(),
+            Aggregation::Mean => self
+                .sums
+                .iter()
+                .zip(&self.last)
+                .map(|((sum, count), last)| match count {
+                    0 => *last,
+                    count => Some(sum / f64::from(*count)),
+                })
+                .collect(),
+        };
+        self.sums.fill((0.0, 0));
+        row
+    }
+}
+
+/// Decodes the signals in `frames`, which have to be in time order.
+pub fn decode(
+    frames: &[RecordedFrame],
+    signals: Vec<&'static Signal>,
+    resampling: Resampling,
+) -> Table {
+    let mut accumulator = Accumulator::new(signals.len());
+    let mut rows = Vec::new();
+    let start = frames.first().map(|frame| frame.time).unwrap_or_default();
+    let mut period_start = start;
+    let period = resampling.period.filter(|period| !period.is_zero());
+
+    for recorded in frames {
+        if let Some(period) = period {
+            while recorded.time >= period_start + period {
+                rows.push((
+                    period_start.as_secs_f64(),
+                    accumulator.take(resampling.aggregation),
+                ));
+                period_start += period;
+            }
+        }
+
+        let mut decoded = false;
+        for (column, signal) in signals.iter().enumerate() {
+            if signal.id != recorded.frame.raw_id() || recorded.frame.is_remote_frame() {
+                continue;
+            }
+            if let Some(value) = (signal.value)(recorded.frame.data()) {
+                accumulator.add(column, value);
+                decoded = true;
+            }
+        }
+
+        if period.is_none() && decoded {
+            rows.push((
+                recorded.time.as_secs_f64(),
+                accumulator.take(Aggregation::Last),
+            ));
+        }
+    }
+
+    if period.is_some() && !frames.is_empty() {
+        rows.push((
+            period_start.as_secs_f64(),
+            accumulator.take(resampling.aggregation),
+        ));
+    }
+
+    Table { signals, rows }
+}
+
+pub fn write_csv(table: &Table, mut writer: impl Write) -> std::io::Result<()> {
+    write!(writer, "time_s")?;
+    for signal in &table.signals {
+        write!(writer, ",{}", signal.name)?;
+    }
+    writeln!(writer)?;
+
+    for (time, values) in &table.rows {
+        write!(writer, "{:.6}", time)?;
+        for value in values {
+            match value {
+                Some(value) => write!(writer, ",{}", value)?,
+                None => write!(writer, ",")?,
+            }
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Writes a column of doubles per signal, where missing values are null.
+pub fn write_parquet(
+    table: &Table,
+    writer: impl Write + Send,
+) -> Result<(), parquet::errors::ParquetError> {
+    let columns: String = table
+        .signals
+        .iter()
+        .map(|signal| format!("OPTIONAL DOUBLE {};", signal.name))
+        .collect();
+    let schema = parse_message_type(&format!(
+        "message signals {{ REQUIRED DOUBLE time_s; {} }}",
+        columns
+    ))?;
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = SerializedFileWriter::new(writer, Arc::new(schema), Arc::new(properties))?;
+
+    let mut row_group = writer.next_row_group()?;
+    let mut index = 0;
+    while let Some(mut column) = row_group.next_column()? {
+        if index == 0 {
+            let times: Vec<f64> = table.rows.iter().map(|(time, _)| *time).collect();
+            column
+                .typed::<DoubleType>()
+                .write_batch(&times, None, None)?;
+        } else {
+            let cells = table.rows.iter().map(|(_, values)| values[index - 1]);
+            let values: Vec<f64> = cells.clone().flatten().collect();
+            let definitions: Vec<i16> = cells.map(|value| i16::from(value.is_some())).collect();
+            column
+                .typed::<DoubleType>()
+                .write_batch(&values, Some(&definitions), None)?;
+        }
+        column.close()?;
+        index += 1;
+    }
+    row_group.close()?;
+    writer.close()?;
+    Ok(())
+}
+
+/// Writes `table` to `path`, in `format` or the format that goes with the extension.
+pub fn write(table: &Table, path: &Path, format: Option<Format>) -> Result<(), ExportError> {
+    let format = format
+        .or_else(|| Format::from_path(path))
+        .ok_or_else(|| ExportError::UnknownFormat(path.display().to_string()))?;
+    let file = std::fs::File::create(path)?;
+
+    match format {
+        Format::Csv => {
+            let mut writer = std::io::BufWriter::new(file);
+            write_csv(table, &mut writer)?;
+            writer.flush()?;
+        }
+        Format::Parquet => write_parquet(table, file)?,
+    }
+    Ok(())
+}
+
+/// Where the frames come from.
+pub enum Source {
+    /// A recording, in the format or the format that goes with its extension
+    Log(PathBuf, Option<recording::Format>),
+    /// Frames received on an interface until ctrl-c is pressed, which are also written to the
+    /// candump log at the path when there is one
+    Interface(String, Option<PathBuf>),
+}
+
+/// Exports the signals from `source` to `output`.
+pub fn run(
+    runtime: &tokio::runtime::Handle,
+    source: Source,
+    output: &Path,
+    format: Option<Format>,
+    names: &[String],
+    resampling: Resampling,
+) -> Result<(), ExportError> {
+    let signals = select(names)?;
+    let mut frames = match source {
+        Source::Log(path, format) => recording::read(&path, format)?,
+        Source::Interface(interface, log) => {
+            tracing::info!("recording {}, press ctrl-c to stop and export", interface);
+            let frames = runtime.block_on(recording::record(&interface, async {
+                let _ = tokio::signal::ctrl_c().await;
+            }))?;
+            if let Some(log) = log {
+                let mut writer = std::io::BufWriter::new(std::fs::File::create(log)?);
+                recording::candump::write(&mut writer, &frames, &interface)?;
+                writer.flush()?;
+            }
+            frames
+        }
+    };
+    frames.sort_by_key(|frame| frame.time);
+
+    let table = decode(&frames, signals, resampling);
+    write(&table, output, format)?;
+    tracing::info!(
+        "exported {} rows from {} frames to {}",
+        table.rows.len(),
+        frames.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::can::frame;
+
+    fn recorded(millis: u64, frame: socketcan::CanFrame) -> RecordedFrame {
+        RecordedFrame {
+            time: Duration::from_millis(millis),
+            frame,
+        }
+    }
+
+    fn engine_speed(millis: u64, speed: u16) -> RecordedFrame {
+        recorded(
+            millis,
+            frame(&t7::EngineSpeedAndThrottle {
+                speed,
+                ..Default::default()
+            })
+            .unwrap(),
+        )
+    }
+
+    fn vehicle_speed(millis: u64, kmh: u16) -> RecordedFrame {
+        recorded(
+            millis,
+            frame(&miu::VehicleSpeed {
+                vehicle_speed: signals::vehicle_speed_raw(kmh),
+                ..Default::default()
+            })
+            .unwrap(),
+        )
+    }
+
+    fn names(names: &[&str]) -> Vec<&'static Signal> {
+        select(
+            &names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn it_selects_signals_by_name() {
+        assert_eq!(select(&[]).unwrap().len(), SIGNALS.len());
+        assert_eq!(names(&["boost"])[0].unit, "%");
+        assert!(matches!(
+            select(&[String::from("lambda")]),
+            Err(ExportError::UnknownSignal(name, _)) if name == "lambda"
+        ));
+    }
+
+    #[test]
+    fn it_holds_values_between_frames() {
+        let frames = [
+            engine_speed(0, 800),
+            vehicle_speed(10, 50),
+            // Not selected, so no row
+            recorded(15, crate::can::raw::frame(0x123, &[1]).unwrap()),
+            engine_speed(20, 900),
+        ];
+        let table = decode(
+            &frames,
+            names(&["engine_speed", "vehicle_speed"]),
+            Resampling::default(),
+        );
+
+        assert_eq!(
+            table.rows,
+            [
+                (0.0, vec![Some(800.0), None]),
+                (0.01, vec![Some(800.0), Some(50.0)]),
+                (0.02, vec![Some(900.0), Some(50.0)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_resamples() {
+        let frames = [
+            engine_speed(0, 800),
+            engine_speed(40, 1000),
+            engine_speed(120, 2000),
+            engine_speed(250, 3000),
+        ];
+        let period = Some(Duration::from_millis(100));
+
+        let last = decode(
+            &frames,
+            names(&["engine_speed"]),
+            Resampling {
+                period,
+                aggregation: Aggregation::Last,
+            },
+        );
+        let mean = decode(
+            &frames,
+            names(&["engine_speed"]),
+            Resampling {
+                period,
+                aggregation: Aggregation::Mean,
+            },
+        );
+
+        assert_eq!(
+            last.rows,
+            [
+                (0.0, vec![Some(1000.0)]),
+                (0.1, vec![Some(2000.0)]),
+                (0.2, vec![Some(3000.0)]),
+            ]
+        );
+        assert_eq!(
+            mean.rows,
+            [
+                (0.0, vec![Some(900.0)]),
+                (0.1, vec![Some(2000.0)]),
+                (0.2, vec![Some(3000.0)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_writes_csv() {
+        let table = decode(
+            &[engine_speed(0, 800), vehicle_speed(500, 30)],
+            names(&["engine_speed", "vehicle_speed"]),
+            Resampling::default(),
+        );
+
+        let mut csv = Vec::new();
+        write_csv(&table, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "time_s,engine_speed,vehicle_speed\n\
+             0.000000,800,\n\
+             0.500000,800,30\n"
+        );
+    }
+
+    #[test]
+    fn it_writes_parquet() {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+
+        let table = decode(
+            &[engine_speed(1000, 800), vehicle_speed(1500, 30)],
+            names(&["engine_speed", "vehicle_speed"]),
+            Resampling::default(),
+        );
+        let path =
+            std::env::temp_dir().join(format!("miu-com-export-{}.parquet", std::process::id()));
+        write_parquet(&table, std::fs::File::create(&path).unwrap()).unwrap();
+
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let rows: Vec<String> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| row.unwrap().to_string())
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                "{time_s: 1.0, engine_speed: 800.0, vehicle_speed: null}",
+                "{time_s: 1.5, engine_speed: 800.0, vehicle_speed: 30.0}",
+            ]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
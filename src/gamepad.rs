@@ -0,0 +1,504 @@
+//! Control the state with a gamepad or joystick through evdev.
+//!
+//! The bindings are read from `~/.config/miu-com/gamepad.toml`, names are the evdev codes:
+//!
+//! ```toml
+//! # The name or path of the device, the first gamepad is used when this is left out
+//! device = "Xbox Wireless Controller"
+//!
+//! [[axes]]
+//! axis = "ABS_RZ"
+//! target = "throttle"
+//!
+//! [[axes]]
+//! axis = "ABS_Y"
+//! target = "engine_speed"
+//! invert = true
+//!
+//! [[buttons]]
+//! button = "BTN_TR"
+//! action = "gear_up"
+//!
+//! [[buttons]]
+//! button = "BTN_WEST"
+//! action = "toggle"
+//! field = "engine_speed_fault"
+//! ```
+use evdev::{AbsoluteAxisCode, Device, EventSummary, KeyCode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::watch;
+
+use crate::can::tcm::Gear;
+use crate::config::{self, ConfigError};
+use crate::miu_state::MiuState;
+
+/// How long to wait before looking for the device again after it was lost.
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The lever positions the gear buttons shift between, gear down moves towards the end. Park,
+/// reverse and neutral are left out, so shifting while driving never ends up in them.
+const GEARS: [Gear; 4] = [Gear::Drive, Gear::Limit3, Gear::Limit2, Gear::Limit1];
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AxisTarget {
+    /// The accelerator pedal position
+    Throttle,
+    EngineSpeed,
+    VehicleSpeed,
+}
+
+impl AxisTarget {
+    /// Sets the target to `position`, which goes from 0 to 1.
+    fn apply(self, state: &mut MiuState, position: f32) {
+        match self {
+            AxisTarget::Throttle => state.accelerator_pedal = (position * 100.0).round() as u8,
+            AxisTarget::EngineSpeed => state.engine_speed = (position * 7000.0).round() as u16,
+            AxisTarget::VehicleSpeed => state.vehicle_speed = (position * 300.0).round() as u16,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ButtonAction {
+    GearUp,
+    GearDown,
+    /// Switch a field that is on or off, like `cruise` or `engine_speed_fault`
+    Toggle {
+        field: String,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct AxisBinding {
+    pub axis: String,
+    pub target: AxisTarget,
+    #[serde(default)]
+    pub invert: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ButtonBinding {
+    pub button: String,
+    #[serde(flatten)]
+    pub action: ButtonAction,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Bindings {
+    /// The name or path of the device, the first gamepad is used when this is left out
+    pub device: Option<String>,
+    pub axes: Vec<AxisBinding>,
+    pub buttons: Vec<ButtonBinding>,
+}
+
+impl Default for Bindings {
+    /// Triggers and sticks for the values, shoulder buttons for the gears and the face buttons
+    /// for cruise and faults.
+    fn default() -> Self {
+        let axis = |axis: &str, target, invert| AxisBinding {
+            axis: axis.to_owned(),
+            target,
+            invert,
+        };
+        let button = |button: &str, action| ButtonBinding {
+            button: button.to_owned(),
+            action,
+        };
+        let toggle = |field: &str| ButtonAction::Toggle {
+            field: field.to_owned(),
+        };
+
+        Self {
+            device: None,
+            axes: vec![
+                axis("ABS_RZ", AxisTarget::Throttle, false),
+                axis("ABS_Y", AxisTarget::EngineSpeed, true),
+                axis("ABS_RY", AxisTarget::VehicleSpeed, true),
+            ],
+            buttons: vec![
+                button("BTN_TR", ButtonAction::GearUp),
+                button("BTN_TL", ButtonAction::GearDown),
+                button("BTN_SOUTH", toggle("cruise")),
+                button("BTN_NORTH", toggle("check_engine")),
+                button("BTN_WEST", toggle("engine_speed_fault")),
+                button("BTN_EAST", toggle("vehicle_speed_fault")),
+            ],
+        }
+    }
+}
+
+impl Bindings {
+    pub const CONFIG_NAME: &'static str = "gamepad";
+
+    pub fn load() -> Result<Self, ConfigError> {
+        config::load(Self::CONFIG_NAME)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum GamepadError {
+    #[error("unable to load the gamepad bindings")]
+    Config(#[from] ConfigError),
+    #[error("unknown axis {0}")]
+    UnknownAxis(String),
+    #[error("unknown button {0}")]
+    UnknownButton(String),
+    #[error("{0} is not a field that can be toggled")]
+    NotToggleable(String),
+}
+
+/// The bindings with the names resolved to evdev codes.
+#[derive(Debug)]
+struct Mapping {
+    axes: HashMap<AbsoluteAxisCode, (AxisTarget, bool)>,
+    buttons: HashMap<KeyCode, ButtonAction>,
+}
+
+impl Mapping {
+    fn new(bindings: &Bindings) -> Result<Self, GamepadError> {
+        let state =
+            serde_json::to_value(MiuState::default()).expect("the state can always be serialized");
+
+        let axes = bindings
+            .axes
+            .iter()
+            .map(|binding| {
+                let axis = binding
+                    .axis
+                    .parse()
+                    .map_err(|_| GamepadError::UnknownAxis(binding.axis.clone()))?;
+                Ok((axis, (binding.target, binding.invert)))
+            })
+            .collect::<Result<_, GamepadError>>()?;
+
+        let buttons = bindings
+            .buttons
+            .iter()
+            .map(|binding| {
+                let button = binding
+                    .button
+                    .parse()
+                    .map_err(|_| GamepadError::UnknownButton(binding.button.clone()))?;
+                if let ButtonAction::Toggle { field } = &binding.action {
+                    if !state.get(field).is_some_and(|value| value.is_boolean()) {
+                        return Err(GamepadError::NotToggleable(field.clone()));
+                    }
+                }
+                Ok((button, binding.action.clone()))
+            })
+            .collect::<Result<_, GamepadError>>()?;
+
+        Ok(Self { axes, buttons })
+    }
+
+    /// Applies an event to the state, `ranges` are the minimum and maximum of the device's axes.
+    fn apply(
+        &self,
+        event: EventSummary,
+        ranges: &HashMap<AbsoluteAxisCode, (i32, i32)>,
+        state: &mut MiuState,
+    ) {
+        match event {
+            EventSummary::AbsoluteAxis(_, axis, value) => {
+                let (Some((target, invert)), Some(&(min, max))) =
+                    (self.axes.get(&axis), ranges.get(&axis))
+                else {
+                    return;
+                };
+                if max <= min {
+                    return;
+                }
+
+                let position = ((value - min) as f32 / (max - min) as f32).clamp(0.0, 1.0);
+                target.apply(state, if *invert { 1.0 - position } else { position });
+            }
+            // Only presses count, not releases or repeats
+            EventSummary::Key(_, button, 1) => match self.buttons.get(&button) {
+                Some(ButtonAction::GearUp) => shift(state, 1),
+                Some(ButtonAction::GearDown) => shift(state, -1),
+                Some(ButtonAction::Toggle { field }) => toggle(state, field),
+                None => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+/// Moves the lever `steps` positions, gear down moves to a lower gear.
+/// Shifts between drive and the limited ranges. From any other position, like park, the lever
+/// goes to drive.
+fn shift(state: &mut MiuState, steps: isize) {
+    let next = match GEARS.iter().position(|gear| *gear == state.gear_lever) {
+        Some(current) => current.saturating_add_signed(-steps).min(GEARS.len() - 1),
+        None => 0,
+    };
+
+    state.gear_lever = GEARS[next];
+    state.actual_gear = GEARS[next];
+}
+
+fn toggle(state: &mut MiuState, field: &str) {
+    let value = serde_json::to_value(*state).expect("the state can always be serialized");
+    if let Some(value) = value.get(field).and_then(|value| value.as_bool()) {
+        if let Err(error) = state.patch(serde_json::json!({ field: !value })) {
+            tracing::warn!("unable to toggle {}: {}", field, error);
+        }
+    }
+}
+
+/// Reads the gamepad on its own thread and applies its events to the state. The device is looked
+/// for again when it's not there or disconnects.
+pub fn spawn(
+    bindings: Bindings,
+    miu_state: watch::Sender<MiuState>,
+) -> Result<std::thread::JoinHandle<()>, GamepadError> {
+    let mapping = Mapping::new(&bindings)?;
+
+    Ok(std::thread::spawn(move || loop {
+        match open(bindings.device.as_deref()) {
+            Some((path, device)) => {
+                tracing::info!(
+                    "using gamepad {} at {}",
+                    device.name().unwrap_or("without a name"),
+                    path.display()
+                );
+                if let Err(error) = read(device, &mapping, &miu_state) {
+                    tracing::warn!("lost the gamepad: {}", error);
+                }
+            }
+            None => tracing::debug!("no gamepad found"),
+        }
+
+        std::thread::sleep(RETRY_INTERVAL);
+    }))
+}
+
+/// Finds the device by name or path, or the first device with gamepad or joystick buttons.
+fn open(wanted: Option<&str>) -> Option<(PathBuf, Device)> {
+    evdev::enumerate().find(|(path, device)| match wanted {
+        Some(wanted) => device.name() == Some(wanted) || path.to_str() == Some(wanted),
+        None => device.supported_keys().is_some_and(|keys| {
+            keys.contains(KeyCode::BTN_SOUTH) || keys.contains(KeyCode::BTN_TRIGGER)
+        }),
+    })
+}
+
+fn read(
+    mut device: Device,
+    mapping: &Mapping,
+    miu_state: &watch::Sender<MiuState>,
+) -> std::io::Result<()> {
+    let ranges: HashMap<_, _> = device
+        .get_absinfo()?
+        .map(|(axis, info)| (axis, (info.minimum(), info.maximum())))
+        .collect();
+
+    loop {
+        let events: Vec<_> = device.fetch_events()?.collect();
+        miu_state.send_if_modified(|state| {
+            let previous = *state;
+            for event in &events {
+                mapping.apply(event.destructure(), &ranges, state);
+            }
+            *state != previous
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evdev::{EventType, InputEvent};
+
+    fn axis(axis: AbsoluteAxisCode, value: i32) -> EventSummary {
+        InputEvent::new(EventType::ABSOLUTE.0, axis.0, value).destructure()
+    }
+
+    fn press(button: KeyCode) -> EventSummary {
+        InputEvent::new(EventType::KEY.0, button.0, 1).destructure()
+    }
+
+    #[test]
+    fn it_maps_axes() {
+        let mapping = Mapping::new(&Bindings::default()).unwrap();
+        let ranges = HashMap::from([
+            (AbsoluteAxisCode::ABS_RZ, (0, 255)),
+            (AbsoluteAxisCode::ABS_Y, (-32768, 32767)),
+        ]);
+        let mut state = MiuState::default();
+
+        mapping.apply(axis(AbsoluteAxisCode::ABS_RZ, 255), &ranges, &mut state);
+        assert_eq!(state.accelerator_pedal, 100);
+
+        // Inverted, so pushing the stick forward revs the engine
+        mapping.apply(axis(AbsoluteAxisCode::ABS_Y, -32768), &ranges, &mut state);
+        assert_eq!(state.engine_speed, 7000);
+        mapping.apply(axis(AbsoluteAxisCode::ABS_Y, 32767), &ranges, &mut state);
+        assert_eq!(state.engine_speed, 0);
+
+        // Axes the device doesn't report a range for are ignored
+        mapping.apply(axis(AbsoluteAxisCode::ABS_RY, 100), &ranges, &mut state);
+        assert_eq!(state.vehicle_speed, 0);
+    }
+
+    #[test]
+    fn it_maps_buttons() {
+        let mapping = Mapping::new(&Bindings::default()).unwrap();
+        let ranges = HashMap::new();
+        let mut state = MiuState::default();
+
+        mapping.apply(press(KeyCode::BTN_SOUTH), &ranges, &mut state);
+        assert!(state.cruise);
+        mapping.apply(press(KeyCode::BTN_SOUTH), &ranges, &mut state);
+        assert!(!state.cruise);
+
+        let release = InputEvent::new(EventType::KEY.0, KeyCode::BTN_WEST.0, 0).destructure();
+        mapping.apply(release, &ranges, &mut state);
+        assert!(!state.engine_speed_fault);
+        mapping.apply(press(KeyCode::BTN_WEST), &ranges, &mut state);
+        assert!(state.engine_speed_fault);
+    }
+
+    #[test]
+    fn it_shifts_gears() {
+        let mut state = MiuState {
+            gear_lever: Gear::Park,
+            ..Default::default()
+        };
+
+        shift(&mut state, -1);
+        assert_eq!(state.gear_lever, Gear::Drive);
+        assert_eq!(state.actual_gear, Gear::Drive);
+        shift(&mut state, -1);
+        assert_eq!(state.gear_lever, Gear::Limit3);
+        for _ in 0..3 {
+            shift(&mut state, -1);
+        }
+        assert_eq!(state.gear_lever, Gear::Limit1);
+        for _ in 0..3 {
+            shift(&mut state, 1);
+        }
+        assert_eq!(state.gear_lever, Gear::Drive);
+    }
+
+    #[test]
+    fn it_stays_in_drive_when_shifting_up() {
+        let mut state = MiuState {
+            gear_lever: Gear::Drive,
+            ..Default::default()
+        };
+
+        shift(&mut state, 1);
+        assert_eq!(state.gear_lever, Gear::Drive);
+        assert_eq!(state.actual_gear, Gear::Drive);
+    }
+
+    #[test]
+    fn it_rejects_invalid_bindings() {
+        let mut bindings = Bindings::default();
+        bindings.buttons[0].button = String::from("BTN_NOPE");
+        assert!(matches!(
+            Mapping::new(&bindings),
+            Err(GamepadError::UnknownButton(_))
+        ));
+
+        let mut bindings = Bindings::default();
+        bindings.buttons[0].action = ButtonAction::Toggle {
+            field: String::from("engine_speed"),
+        };
+        assert!(matches!(
+            Mapping::new(&bindings),
+            Err(GamepadError::NotToggleable(_))
+        ));
+    }
+
+    #[test]
+    fn it_parses_bindings() {
+        let bindings: Bindings = toml::from_str(
+            r#"
+            [[axes]]
+            axis = "ABS_Z"
+            target = "vehicle_speed"
+
+            [[buttons]]
+            button = "BTN_SOUTH"
+            action = "toggle"
+            field = "cruise"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(bindings.axes[0].target, AxisTarget::VehicleSpeed);
+        assert!(!bindings.axes[0].invert);
+        assert_eq!(
+            bindings.buttons[0].action,
+            ButtonAction::Toggle {
+                field: String::from("cruise")
+            }
+        );
+        assert!(Mapping::new(&bindings).is_ok());
+    }
+
+    /// Drives the whole thing with a virtual device.
+    #[test]
+    #[ignore = "needs write access to /dev/uinput"]
+    fn it_reads_a_virtual_device() {
+        use evdev::uinput::VirtualDevice;
+        use evdev::{AbsInfo, AttributeSet, UinputAbsSetup};
+
+        let name = format!("miu-com test gamepad {}", std::process::id());
+        let mut device = VirtualDevice::builder()
+            .unwrap()
+            .name(&name)
+            .with_keys(&AttributeSet::from_iter([KeyCode::BTN_SOUTH]))
+            .unwrap()
+            .with_absolute_axis(&UinputAbsSetup::new(
+                AbsoluteAxisCode::ABS_RZ,
+                AbsInfo::new(0, 0, 255, 0, 0, 1),
+            ))
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let miu_state = watch::Sender::new(MiuState::default());
+        let mut receiver = miu_state.subscribe();
+        spawn(
+            Bindings {
+                device: Some(name),
+                ..Default::default()
+            },
+            miu_state,
+        )
+        .unwrap();
+
+        // Give the reader some time to find the device
+        std::thread::sleep(Duration::from_millis(500));
+        device
+            .emit(&[
+                InputEvent::new(EventType::ABSOLUTE.0, AbsoluteAxisCode::ABS_RZ.0, 255),
+                InputEvent::new(EventType::KEY.0, KeyCode::BTN_SOUTH.0, 1),
+            ])
+            .unwrap();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let state = runtime
+            .block_on(tokio::time::timeout(
+                Duration::from_secs(5),
+                receiver.wait_for(|state| state.cruise),
+            ))
+            .unwrap()
+            .map(|state| *state)
+            .unwrap();
+        assert_eq!(state.accelerator_pedal, 100);
+    }
+}
@@ -1,33 +1,126 @@
-use crate::can;
+use crate::can::{self, bus_load};
 use crate::miu_state;
+use crate::settings::{Settings, Windows};
+use egui_plot::{Line, Plot, PlotPoints};
 use tokio::sync::watch;
 
+mod analyser;
+mod cluster;
+mod inspector;
+mod plot;
+mod presets;
+mod scenario;
+mod script;
+mod timing;
+mod transmitter;
+
+pub use analyser::AnalyserWindow;
+pub use cluster::ClusterWindow;
+pub use inspector::InspectorWindow;
+pub use plot::PlotWindow;
+pub use presets::PresetBar;
+pub use scenario::ScenarioWindow;
+pub use script::ScriptWindow;
+pub use timing::TimingWindow;
+pub use transmitter::TransmitterWindow;
+
 pub struct Gui {
     pub can: can::CanClient,
     pub interfaces: can::interfaces::InterfacesClient,
     pub selected_interface: Option<String>,
+    pub auto_connect: bool,
+    pub dark_mode: bool,
     pub miu_state: miu_state::MiuState,
     pub miu_state_sender: watch::Sender<miu_state::MiuState>,
+    pub sid_message: can::sid::Message,
+    pub miu_emulation: can::miu::Emulation,
+    pub analyser: AnalyserWindow,
+    pub cluster: ClusterWindow,
+    pub inspector: InspectorWindow,
+    pub plot: PlotWindow,
+    pub timing: TimingWindow,
+    pub transmitter: TransmitterWindow,
+    pub preset_bar: PresetBar,
+    pub scenario: ScenarioWindow,
+    pub script: ScriptWindow,
 }
 
 impl eframe::App for Gui {
     fn update(&mut self, context: &eframe::egui::Context, _frame: &mut eframe::Frame) {
+        self.dark_mode = context.style().visuals.dark_mode;
+
+        // The state can be changed through the api, scripts and scenarios as well, so start from
+        // the shared state and only send back the fields that were changed here.
+        self.miu_state = *self.miu_state_sender.borrow();
+        let previous_state = self.miu_state;
+
         egui::TopBottomPanel::top("top-bar").show(context, |ui| {
             self.top_bar(ui);
         });
 
+        egui::TopBottomPanel::top("preset-bar").show(context, |ui| {
+            self.preset_bar.show(ui, &mut self.miu_state);
+        });
+
         egui::CentralPanel::default().show(context, |ui| {
             self.control_grid(ui);
+            ui.separator();
+            self.sid_text(ui);
+            ui.separator();
+            self.miu_emulation(ui);
+            ui.separator();
+            self.fault_injection(ui);
         });
 
-        // This operation fails if there are no receivers, which is the case when there is no
-        // active can bus connection. For the render loop of the gui this is not important so we
-        // can just ignore this case.
-        let _ = self.miu_state_sender.send(self.miu_state);
+        self.analyser.show(context);
+        self.cluster.show(context);
+        let odometer = self.can.odometer().expect("Failed to get odometer");
+        self.inspector
+            .show(context, &self.miu_state, &self.miu_emulation, &odometer);
+        self.plot.show(context);
+        self.timing.show(context, &self.can);
+        self.transmitter.show(context, &self.can);
+        self.scenario.show(context, &self.miu_state_sender);
+        self.script.show(context, &self.can, &self.miu_state_sender);
+
+        if self.miu_state != previous_state {
+            let patch = miu_state::diff(&previous_state, &self.miu_state);
+            if let Err(error) = miu_state::patch(&self.miu_state_sender, patch) {
+                tracing::warn!("unable to apply the changes from the window: {}", error);
+            }
+        }
+    }
+
+    /// The window layout is kept by eframe, everything else goes in the settings file.
+    fn save(&mut self, _storage: &mut dyn eframe::Storage) {
+        if let Err(error) = self.settings().save() {
+            tracing::warn!("unable to save settings: {}", error);
+        }
     }
 }
 
 impl Gui {
+    fn settings(&self) -> Settings {
+        Settings {
+            interface: self.selected_interface.clone(),
+            auto_connect: self.auto_connect,
+            dark_mode: self.dark_mode,
+            emulate_miu: self.miu_emulation.enabled,
+            scheduling: self.timing.scheduling,
+            windows: Windows {
+                analyser: self.analyser.open,
+                cluster: self.cluster.open,
+                inspector: self.inspector.open,
+                plot: self.plot.open,
+                scenario: self.scenario.open,
+                script: self.script.open,
+                timing: self.timing.open,
+                transmitter: self.transmitter.open,
+            },
+            miu_state: self.miu_state,
+        }
+    }
+
     fn top_bar(&mut self, ui: &mut eframe::egui::Ui) {
         ui.horizontal(|ui| {
             egui::widgets::global_dark_light_mode_switch(ui);
@@ -79,15 +172,109 @@ impl Gui {
                     }
                 }
             }
+
+            ui.checkbox(&mut self.auto_connect, "Auto-connect")
+                .on_hover_text("Connect to this interface on startup");
+
+            if connection_state == can::State::Connected {
+                ui.separator();
+                self.bus_load(ui);
+            } else {
+                self.planned_schedule(ui);
+            }
+
+            ui.separator();
+            ui.toggle_value(&mut self.analyser.open, "Analyser");
+            ui.toggle_value(&mut self.cluster.open, "Cluster");
+            ui.toggle_value(&mut self.inspector.open, "Inspector");
+            ui.toggle_value(&mut self.plot.open, "Plot");
+            ui.toggle_value(&mut self.timing.open, "Timing");
+            ui.toggle_value(&mut self.transmitter.open, "Transmitter");
+            ui.toggle_value(&mut self.scenario.open, "Scenario");
+            ui.toggle_value(&mut self.script.open, "Script");
         });
     }
 
+    /// The load of the bus with a minute of history.
+    fn bus_load(&self, ui: &mut egui::Ui) {
+        let load = self.can.bus_load().expect("Failed to get bus load");
+        ui.ctx().request_repaint_after(bus_load::SAMPLE_PERIOD);
+
+        ui.label(format!("Bus {:.0}%", load.load * 100.0))
+            .on_hover_text(format!(
+                "{:.0}% with worst case bit stuffing, at {} kbit/s",
+                load.worst_case * 100.0,
+                load.bitrate / 1000
+            ));
+
+        let history: PlotPoints = load
+            .history
+            .iter()
+            .enumerate()
+            .map(|(index, load)| [index as f64, load * 100.0])
+            .collect();
+        Plot::new("bus-load-history")
+            .width(120.0)
+            .height(ui.spacing().interact_size.y)
+            .show_axes(false)
+            .show_grid(false)
+            .show_x(false)
+            .show_y(false)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_scroll(false)
+            .allow_boxed_zoom(false)
+            .include_x(0.0)
+            .include_x(bus_load::HISTORY as f64)
+            .include_y(0.0)
+            .include_y(100.0)
+            .show(ui, |plot| plot.line(Line::new(history)));
+
+        if load.schedule_too_high() {
+            schedule_warning(ui, load.schedule, load.bitrate);
+        }
+    }
+
+    /// Warns before connecting when the frames that would be sent need too much of the bus. The
+    /// bitrate isn't known until connecting, so the default is assumed.
+    fn planned_schedule(&self, ui: &mut egui::Ui) {
+        let frames = bus_load::schedule(
+            &self.miu_state,
+            &self.transmitter.frames(),
+            &self.miu_emulation,
+        );
+        let schedule = bus_load::schedule_load(&frames, bus_load::DEFAULT_BITRATE);
+        if schedule > bus_load::SCHEDULE_THRESHOLD {
+            ui.separator();
+            schedule_warning(ui, schedule, bus_load::DEFAULT_BITRATE);
+        }
+    }
+
     fn control_grid(&mut self, ui: &mut egui::Ui) {
         egui::Grid::new("control_signal_grid")
             .num_columns(3)
             .spacing([40.0, 10.0])
             .striped(true)
             .show(ui, |ui| {
+                ui.heading("Key position");
+                ui.horizontal(|ui| {
+                    for position in miu_state::KeyPosition::ALL {
+                        ui.selectable_value(
+                            &mut self.miu_state.key_position,
+                            position,
+                            format!("{:?}", position),
+                        );
+                    }
+                });
+                if self.miu_state.key_position == miu_state::KeyPosition::Off {
+                    ui.colored_label(
+                        ui.visuals().warn_fg_color,
+                        "The bus is silent with the key off",
+                    )
+                    .on_hover_text("Only raw frames are sent, turn the key to send the state");
+                }
+                ui.end_row();
+
                 ui.heading("Engine speed");
                 ui.add(
                     egui::DragValue::new(&mut self.miu_state.engine_speed)
@@ -99,6 +286,50 @@ impl Gui {
                 ui.checkbox(&mut self.miu_state.engine_speed_fault, "Fault");
                 ui.end_row();
 
+                ui.heading("Accelerator pedal");
+                ui.add(
+                    egui::DragValue::new(&mut self.miu_state.accelerator_pedal)
+                        .clamp_range(0_u8..=100)
+                        .suffix("%"),
+                );
+                ui.end_row();
+
+                ui.heading("Vehicle speed");
+                ui.add(
+                    egui::DragValue::new(&mut self.miu_state.vehicle_speed)
+                        .clamp_range(0_u16..=300)
+                        .suffix(" km/h"),
+                );
+                ui.checkbox(&mut self.miu_state.vehicle_speed_fault, "Fault");
+                ui.end_row();
+
+                ui.heading("Fuel level")
+                    .on_hover_text("Sent with MIU emulation");
+                ui.add(
+                    egui::DragValue::new(&mut self.miu_state.fuel_level)
+                        .clamp_range(0_u16..=80)
+                        .suffix(" l"),
+                );
+                ui.checkbox(&mut self.miu_state.fuel_level_fault, "Fault");
+                ui.end_row();
+
+                // The distance changes without any input, so keep redrawing to show it live.
+                let odometer = self.can.odometer().expect("Failed to get odometer");
+                ui.ctx()
+                    .request_repaint_after(std::time::Duration::from_millis(200));
+                ui.heading("Distance").on_hover_text(
+                    "Counted from the vehicle speed frames on the bus with MIU emulation, or from \
+                     the vehicle speed above while nothing sends them",
+                );
+                ui.label(format!("{:.3} km", odometer.total_km()));
+                ui.horizontal(|ui| {
+                    ui.label(format!("Trip {:.3} km", odometer.trip_km()));
+                    if ui.button("Reset trip").clicked() {
+                        self.can.reset_trip().expect("Failed to reset trip");
+                    }
+                });
+                ui.end_row();
+
                 ui.heading("Boost");
                 ui.horizontal(|ui| {
                     ui.add(egui::Slider::new(&mut self.miu_state.boost, 0..=255).show_value(false));
@@ -127,104 +358,12 @@ impl Gui {
                 ui.end_row();
 
                 ui.heading("Gear lever");
-                egui::ComboBox::from_id_source("gear-lever")
-                    .selected_text(format!(
-                        "{:?}",
-                        can::tcm::Gear::from(self.miu_state.gear_lever)
-                    ))
-                    .show_ui(ui, |ui| {
-                        ui.selectable_value(
-                            &mut self.miu_state.gear_lever,
-                            can::tcm::Gear::Unknown.into(),
-                            format!("{:?}", can::tcm::Gear::Unknown),
-                        );
-                        ui.selectable_value(
-                            &mut self.miu_state.gear_lever,
-                            can::tcm::Gear::Park.into(),
-                            format!("{:?}", can::tcm::Gear::Park),
-                        );
-                        ui.selectable_value(
-                            &mut self.miu_state.gear_lever,
-                            can::tcm::Gear::Reverse.into(),
-                            format!("{:?}", can::tcm::Gear::Reverse),
-                        );
-                        ui.selectable_value(
-                            &mut self.miu_state.gear_lever,
-                            can::tcm::Gear::Neutral.into(),
-                            format!("{:?}", can::tcm::Gear::Neutral),
-                        );
-                        ui.selectable_value(
-                            &mut self.miu_state.gear_lever,
-                            can::tcm::Gear::Drive.into(),
-                            format!("{:?}", can::tcm::Gear::Drive),
-                        );
-                        ui.selectable_value(
-                            &mut self.miu_state.gear_lever,
-                            can::tcm::Gear::Limit3.into(),
-                            format!("{:?}", can::tcm::Gear::Limit3),
-                        );
-                        ui.selectable_value(
-                            &mut self.miu_state.gear_lever,
-                            can::tcm::Gear::Limit2.into(),
-                            format!("{:?}", can::tcm::Gear::Limit2),
-                        );
-                        ui.selectable_value(
-                            &mut self.miu_state.gear_lever,
-                            can::tcm::Gear::Limit1.into(),
-                            format!("{:?}", can::tcm::Gear::Limit1),
-                        );
-                    });
+                gear_selector(ui, "gear-lever", &mut self.miu_state.gear_lever);
                 ui.checkbox(&mut self.miu_state.gear_lever_fault, "Fault");
                 ui.end_row();
 
                 ui.heading("Actual gear");
-                egui::ComboBox::from_id_source("actual-gear")
-                    .selected_text(format!(
-                        "{:?}",
-                        can::tcm::Gear::from(self.miu_state.actual_gear)
-                    ))
-                    .show_ui(ui, |ui| {
-                        ui.selectable_value(
-                            &mut self.miu_state.actual_gear,
-                            can::tcm::Gear::Unknown.into(),
-                            format!("{:?}", can::tcm::Gear::Unknown),
-                        );
-                        ui.selectable_value(
-                            &mut self.miu_state.actual_gear,
-                            can::tcm::Gear::Park.into(),
-                            format!("{:?}", can::tcm::Gear::Park),
-                        );
-                        ui.selectable_value(
-                            &mut self.miu_state.actual_gear,
-                            can::tcm::Gear::Reverse.into(),
-                            format!("{:?}", can::tcm::Gear::Reverse),
-                        );
-                        ui.selectable_value(
-                            &mut self.miu_state.actual_gear,
-                            can::tcm::Gear::Neutral.into(),
-                            format!("{:?}", can::tcm::Gear::Neutral),
-                        );
-                        ui.selectable_value(
-                            &mut self.miu_state.actual_gear,
-                            can::tcm::Gear::Drive.into(),
-                            format!("{:?}", can::tcm::Gear::Drive),
-                        );
-                        ui.selectable_value(
-                            &mut self.miu_state.actual_gear,
-                            can::tcm::Gear::Limit3.into(),
-                            format!("{:?}", can::tcm::Gear::Limit3),
-                        );
-                        ui.selectable_value(
-                            &mut self.miu_state.actual_gear,
-                            can::tcm::Gear::Limit2.into(),
-                            format!("{:?}", can::tcm::Gear::Limit2),
-                        );
-                        ui.selectable_value(
-                            &mut self.miu_state.actual_gear,
-                            can::tcm::Gear::Limit1.into(),
-                            format!("{:?}", can::tcm::Gear::Limit1),
-                        );
-                    });
+                gear_selector(ui, "actual-gear", &mut self.miu_state.actual_gear);
                 ui.checkbox(&mut self.miu_state.actual_gear_fault, "Fault");
                 ui.end_row();
 
@@ -241,4 +380,236 @@ impl Gui {
                 ui.end_row();
             });
     }
+
+    fn sid_text(&mut self, ui: &mut egui::Ui) {
+        ui.heading("SID text");
+
+        egui::Grid::new("sid_text_grid")
+            .num_columns(2)
+            .spacing([40.0, 10.0])
+            .show(ui, |ui| {
+                for (i, row) in self.sid_message.rows.iter_mut().enumerate() {
+                    ui.label(format!("Row {}", i + 1));
+                    ui.add(
+                        egui::TextEdit::singleline(row)
+                            .char_limit(can::sid::ROW_LENGTH)
+                            .font(egui::TextStyle::Monospace),
+                    );
+                    ui.end_row();
+                }
+
+                ui.label("Priority");
+                ui.add(
+                    egui::DragValue::new(&mut self.sid_message.priority)
+                        .hexadecimal(2, false, true),
+                );
+                ui.end_row();
+
+                ui.label("Ignore arbitration");
+                ui.checkbox(&mut self.sid_message.ignore_arbitration, "");
+                ui.end_row();
+            });
+
+        ui.horizontal(|ui| {
+            if ui.button("Send").clicked() {
+                self.can
+                    .set_sid_message(Some(self.sid_message.clone()))
+                    .expect("Failed to send sid message");
+            }
+
+            if ui.button("Clear").clicked() {
+                self.can
+                    .set_sid_message(None)
+                    .expect("Failed to clear sid message");
+            }
+        });
+    }
+
+    fn miu_emulation(&mut self, ui: &mut egui::Ui) {
+        let previous = self.miu_emulation;
+        let emulation = &mut self.miu_emulation;
+
+        ui.horizontal(|ui| {
+            ui.heading("MIU emulation");
+            ui.checkbox(&mut emulation.enabled, "")
+                .on_hover_text("Send the vehicle speed and fuel level like the MIU");
+        });
+
+        ui.add_enabled_ui(emulation.enabled, |ui| {
+            ui.checkbox(&mut emulation.experimental, "Experimental messages")
+                .on_hover_text(
+                    "Also send the distance and SID buttons. Their ids and layouts are a guess \
+                     that hasn't been checked on a car.",
+                );
+        });
+
+        ui.add_enabled_ui(emulation.enabled && emulation.experimental, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("SID buttons");
+                // The buttons are only held down while the mouse is
+                let buttons = &mut emulation.buttons;
+                buttons.up = ui.button("Up").is_pointer_button_down_on();
+                buttons.down = ui.button("Down").is_pointer_button_down_on();
+                buttons.set = ui.button("Set").is_pointer_button_down_on();
+                buttons.clear = ui.button("Clear").is_pointer_button_down_on();
+                ui.toggle_value(&mut buttons.night_panel, "Night panel");
+            });
+        });
+
+        if self.miu_emulation != previous {
+            self.can
+                .set_miu_emulation(self.miu_emulation)
+                .expect("Failed to set miu emulation");
+        }
+    }
+
+    fn fault_injection(&mut self, ui: &mut egui::Ui) {
+        let nodes_off = can::nodes::NodeId::ALL
+            .iter()
+            .any(|node| !self.miu_state.nodes.get(*node));
+        let title = if self.miu_state.faults.any() || nodes_off {
+            "Nodes and faults (active)"
+        } else {
+            "Nodes and faults"
+        };
+
+        egui::CollapsingHeader::new(title)
+            .id_source("fault-injection")
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("On the bus");
+                    for node in can::nodes::NodeId::ALL {
+                        ui.checkbox(self.miu_state.nodes.get_mut(node), node.name());
+                    }
+                });
+
+                egui::Grid::new("fault_injection_grid")
+                    .num_columns(6)
+                    .spacing([20.0, 10.0])
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong("Message");
+                        ui.strong("Dropout");
+                        ui.strong("Period");
+                        ui.strong("Jitter");
+                        ui.strong("DLC");
+                        ui.strong("Stale");
+                        ui.end_row();
+
+                        for node in &can::nodes::NODES {
+                            let enabled = self.miu_state.nodes.get(node.id);
+                            ui.strong(node.id.name());
+                            ui.end_row();
+
+                            for &id in node.messages {
+                                fault_row(ui, enabled, id, node.period, &mut self.miu_state.faults);
+                            }
+                        }
+
+                        ui.strong("MIU emulation");
+                        ui.end_row();
+                        for message in can::miu::MESSAGES.iter().filter(|m| !m.experimental) {
+                            let enabled = self.miu_emulation.enabled;
+                            fault_row(
+                                ui,
+                                enabled,
+                                message.id,
+                                message.period,
+                                &mut self.miu_state.faults,
+                            );
+                        }
+                    });
+
+                if ui.button("Clear all faults").clicked() {
+                    self.miu_state.faults = Default::default();
+                    self.miu_state.nodes = Default::default();
+                }
+            });
+    }
+}
+
+/// The faults of the message with `id`, which is normally sent every `period`.
+fn fault_row(
+    ui: &mut egui::Ui,
+    enabled: bool,
+    id: u32,
+    period: std::time::Duration,
+    faults: &mut can::faults::FaultInjection,
+) {
+    let name = can::faults::MESSAGES
+        .iter()
+        .find(|(message, _)| *message == id)
+        .map(|(_, name)| *name)
+        .unwrap_or_default();
+    let fault = faults.get_mut(id).expect("all messages have a fault entry");
+    let default_period = period.as_millis() as u64;
+
+    ui.add_enabled(enabled, egui::Label::new(format!("{:03X} {}", id, name)));
+    ui.add_enabled(enabled, egui::Checkbox::without_text(&mut fault.dropout));
+    ui.add_enabled_ui(enabled, |ui| {
+        optional_value(ui, &mut fault.period_ms, default_period, 1..=10_000, " ms");
+    });
+    ui.add_enabled(
+        enabled,
+        egui::DragValue::new(&mut fault.jitter_ms)
+            .clamp_range(0..=1000)
+            .suffix(" ms"),
+    );
+    ui.add_enabled_ui(enabled, |ui| {
+        optional_value(ui, &mut fault.dlc, 8, 0..=8, "");
+    });
+    ui.add_enabled(enabled, egui::Checkbox::without_text(&mut fault.stale));
+    ui.end_row();
+}
+
+/// A checkbox that enables an optional value, with an editor for the value when it is enabled.
+fn optional_value<T: egui::emath::Numeric>(
+    ui: &mut egui::Ui,
+    value: &mut Option<T>,
+    default: T,
+    range: std::ops::RangeInclusive<T>,
+    suffix: &str,
+) {
+    ui.horizontal(|ui| {
+        let mut enabled = value.is_some();
+        if ui.checkbox(&mut enabled, "").changed() {
+            *value = enabled.then_some(default);
+        }
+
+        if let Some(value) = value {
+            ui.add(
+                egui::DragValue::new(value)
+                    .clamp_range(range)
+                    .suffix(suffix),
+            );
+        }
+    });
+}
+
+fn gear_selector(ui: &mut egui::Ui, id_source: &str, gear: &mut can::tcm::Gear) {
+    egui::ComboBox::from_id_source(id_source)
+        .selected_text(format!("{:?}", gear))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(
+                gear,
+                can::tcm::Gear::default(),
+                format!("{:?}", can::tcm::Gear::default()),
+            );
+
+            for option in can::tcm::Gear::iter() {
+                ui.selectable_value(gear, option, format!("{:?}", option));
+            }
+        });
+}
+
+fn schedule_warning(ui: &mut egui::Ui, schedule: f64, bitrate: u32) {
+    ui.colored_label(
+        ui.visuals().warn_fg_color,
+        format!("⚠ Schedule {:.0}%", schedule * 100.0),
+    )
+    .on_hover_text(format!(
+        "The frames sent by miu-com alone need more than {:.0}% of the bus at {} kbit/s",
+        bus_load::SCHEDULE_THRESHOLD * 100.0,
+        bitrate / 1000
+    ));
 }
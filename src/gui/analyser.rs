@@ -0,0 +1,134 @@
+use crate::analyser::{self, Analyser, IdReport};
+use crate::can::ReceivedFrame;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::broadcast;
+
+/// Window for the reverse engineering assistant.
+pub struct AnalyserWindow {
+    pub open: bool,
+    analyser: Arc<Mutex<Analyser>>,
+    label: String,
+    reports: Vec<IdReport>,
+    only_correlated: bool,
+}
+
+impl AnalyserWindow {
+    /// Starts receiving frames in the background, this needs to be called within a tokio runtime.
+    pub fn new(frames: broadcast::Receiver<ReceivedFrame>) -> Self {
+        let analyser = Arc::new(Mutex::new(Analyser::default()));
+        tokio::spawn(analyser::record(Arc::downgrade(&analyser), frames));
+
+        Self {
+            open: false,
+            analyser,
+            label: String::new(),
+            reports: Vec::new(),
+            only_correlated: true,
+        }
+    }
+
+    pub fn show(&mut self, context: &egui::Context) {
+        if !self.open {
+            return;
+        }
+
+        let mut open = self.open;
+        egui::Window::new("Analyser")
+            .open(&mut open)
+            .default_width(600.0)
+            .show(context, |ui| self.contents(ui));
+        self.open = open;
+    }
+
+    fn contents(&mut self, ui: &mut egui::Ui) {
+        let analyser = Arc::clone(&self.analyser);
+        let mut analyser = analyser::lock(&analyser);
+        let recording = analyser.is_recording();
+        if recording {
+            ui.ctx()
+                .request_repaint_after(std::time::Duration::from_millis(200));
+        }
+
+        ui.horizontal(|ui| {
+            if recording {
+                if ui.button("Stop recording").clicked() {
+                    analyser.stop();
+                }
+            } else if ui.button("Start recording").clicked() {
+                analyser.start();
+                self.reports.clear();
+            }
+
+            ui.label(format!("{} frames", analyser.frame_count()));
+            if analyser.is_full() {
+                ui.colored_label(ui.visuals().warn_fg_color, "recording is full");
+            }
+        });
+
+        ui.add_enabled_ui(recording, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Stimulus");
+                let response = ui.text_edit_singleline(&mut self.label);
+                let submitted =
+                    response.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter));
+
+                if (ui.button("Mark").clicked() || submitted) && !self.label.is_empty() {
+                    analyser.mark(std::mem::take(&mut self.label), Instant::now());
+                }
+            });
+        });
+
+        if let Some(first) = analyser.marks().first() {
+            ui.collapsing(format!("{} marks", analyser.marks().len()), |ui| {
+                for mark in analyser.marks() {
+                    ui.label(format!(
+                        "{:>8.3} s  {}",
+                        mark.time.duration_since(first.time).as_secs_f64(),
+                        mark.label
+                    ));
+                }
+            });
+        }
+
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            if ui.button("Analyse").clicked() {
+                self.reports = analyser.analyse();
+            }
+            ui.checkbox(&mut self.only_correlated, "Only correlated messages");
+        });
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for report in &self.reports {
+                if self.only_correlated && !report.correlated() {
+                    continue;
+                }
+
+                let period = report
+                    .period
+                    .map(|period| format!("{} ms", period.as_millis()))
+                    .unwrap_or_else(|| String::from("-"));
+
+                egui::CollapsingHeader::new(format!(
+                    "{:03X}  {} frames  {} bytes  period {}",
+                    report.id, report.frames, report.length, period
+                ))
+                .id_source(report.id)
+                .show(ui, |ui| {
+                    for line in report.summary() {
+                        ui.label(line);
+                    }
+
+                    let mut proposal = report.proposed_struct();
+                    ui.add(
+                        egui::TextEdit::multiline(&mut proposal)
+                            .font(egui::TextStyle::Monospace)
+                            .desired_width(f32::INFINITY),
+                    );
+                });
+            }
+        });
+    }
+}
@@ -0,0 +1,240 @@
+use crate::can::cluster::{Cluster, Signal};
+use crate::can::tcm::Gear;
+use crate::can::ReceivedFrame;
+use egui::{Align2, Color32, FontId, Sense, Stroke, Vec2};
+use std::f32::consts::PI;
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+const GAUGE_SIZE: f32 = 160.0;
+
+/// The needle sweeps from bottom left, through the top, to bottom right.
+const START_ANGLE: f32 = 0.75 * PI;
+const SWEEP: f32 = 1.5 * PI;
+
+/// The speed and fuel level gauges read frames the MIU sends itself, no ECU frame with them is
+/// known.
+const LOOPBACK_HINT: &str = "Read from the frame the MIU sends, so this shows the emulated MIU \
+                             or another MIU on the bus, not a value from an ECU";
+
+/// A software instrument cluster, driven by the frames on the bus instead of the local state.
+pub struct ClusterWindow {
+    pub open: bool,
+    frames: broadcast::Receiver<ReceivedFrame>,
+    cluster: Cluster,
+}
+
+impl ClusterWindow {
+    pub fn new(frames: broadcast::Receiver<ReceivedFrame>) -> Self {
+        Self {
+            open: false,
+            frames,
+            cluster: Cluster::default(),
+        }
+    }
+
+    fn receive(&mut self) {
+        loop {
+            match self.frames.try_recv() {
+                Ok(received) => self.cluster.receive(&received.frame, received.time),
+                Err(broadcast::error::TryRecvError::Lagged(count)) => {
+                    tracing::warn!("cluster missed {} frames", count);
+                }
+                Err(_) => break,
+            }
+        }
+    }
+
+    pub fn show(&mut self, context: &egui::Context) {
+        // Frames have to be drained even when the window is closed, otherwise the channel fills up.
+        self.receive();
+
+        if !self.open {
+            return;
+        }
+        context.request_repaint_after(Duration::from_millis(50));
+
+        let now = Instant::now();
+        let cluster = self.cluster;
+        egui::Window::new("Cluster")
+            .open(&mut self.open)
+            .resizable(false)
+            .show(context, |ui| {
+                ui.horizontal(|ui| {
+                    gauge(
+                        ui,
+                        "km/h (MIU)",
+                        &cluster.vehicle_speed,
+                        now,
+                        0.0..=260.0,
+                        20.0,
+                        |speed| speed,
+                    )
+                    .on_hover_text(LOOPBACK_HINT);
+                    gauge(
+                        ui,
+                        "rpm x1000",
+                        &cluster.engine_speed,
+                        now,
+                        0.0..=7.0,
+                        1.0,
+                        |speed| f32::from(speed) / 1000.0,
+                    );
+                    gauge(ui, "boost", &cluster.boost, now, 0.0..=1.0, 0.25, |boost| {
+                        f32::from(boost) / 255.0
+                    });
+                    gauge(
+                        ui,
+                        "°C",
+                        &cluster.coolant_temperature,
+                        now,
+                        40.0..=130.0,
+                        30.0,
+                        f32::from,
+                    );
+                    gauge(
+                        ui,
+                        "fuel l (MIU)",
+                        &cluster.fuel_level,
+                        now,
+                        0.0..=70.0,
+                        10.0,
+                        |level| level,
+                    )
+                    .on_hover_text(LOOPBACK_HINT);
+                });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    let amber = Color32::from_rgb(255, 170, 0);
+                    lamp(ui, "Check engine", &cluster.check_engine, now, amber);
+                    lamp(ui, "Limp home", &cluster.limp_home, now, amber);
+                    lamp(ui, "Check gearbox", &cluster.check_gearbox, now, amber);
+                    lamp(ui, "Cruise", &cluster.cruise, now, Color32::GREEN);
+
+                    ui.separator();
+                    ui.label(match cluster.gear_lever.get(now) {
+                        Some(gear) => format!("Gear {}", gear_label(gear)),
+                        None => String::from("Gear -"),
+                    });
+                });
+            });
+    }
+}
+
+/// Draws an analogue gauge with ticks every `step`. The needle rests at the start and the label
+/// says so when the signal is missing or faulty.
+fn gauge<T: Copy>(
+    ui: &mut egui::Ui,
+    unit: &str,
+    signal: &Signal<T>,
+    now: Instant,
+    range: RangeInclusive<f32>,
+    step: f32,
+    scale: impl Fn(T) -> f32,
+) -> egui::Response {
+    let (response, painter) = ui.allocate_painter(Vec2::splat(GAUGE_SIZE), Sense::hover());
+    let visuals = ui.visuals();
+    let rect = response.rect;
+    let center = rect.center();
+    let radius = GAUGE_SIZE / 2.0 - 4.0;
+    let point = |fraction: f32, distance: f32| {
+        let angle = START_ANGLE + fraction * SWEEP;
+        center + distance * Vec2::angled(angle)
+    };
+    let fraction =
+        |value: f32| ((value - range.start()) / (range.end() - range.start())).clamp(0.0, 1.0);
+
+    painter.circle(
+        center,
+        radius,
+        visuals.extreme_bg_color,
+        Stroke::new(2.0, visuals.widgets.noninteractive.fg_stroke.color),
+    );
+
+    let tick = Stroke::new(1.5, visuals.text_color());
+    let mut value = *range.start();
+    while value <= *range.end() + f32::EPSILON {
+        let at = fraction(value);
+        painter.line_segment([point(at, radius - 10.0), point(at, radius - 2.0)], tick);
+        painter.text(
+            point(at, radius - 22.0),
+            Align2::CENTER_CENTER,
+            format_tick(value),
+            FontId::proportional(11.0),
+            visuals.text_color(),
+        );
+        value += step;
+    }
+
+    let value = signal.get(now).map(&scale);
+    let status = match value {
+        None => Some("no signal"),
+        Some(_) if signal.fault => Some("fault"),
+        Some(_) => None,
+    };
+
+    let needle = match status {
+        None => Color32::from_rgb(230, 80, 30),
+        Some(_) => visuals.weak_text_color(),
+    };
+    let end = point(fraction(value.unwrap_or(*range.start())), radius - 14.0);
+    painter.line_segment([center, end], Stroke::new(3.0, needle));
+    painter.circle_filled(center, 5.0, needle);
+
+    painter.text(
+        center + Vec2::new(0.0, radius * 0.45),
+        Align2::CENTER_CENTER,
+        unit,
+        FontId::proportional(12.0),
+        visuals.text_color(),
+    );
+    if let Some(status) = status {
+        painter.text(
+            center + Vec2::new(0.0, radius * 0.7),
+            Align2::CENTER_CENTER,
+            status,
+            FontId::proportional(11.0),
+            visuals.warn_fg_color,
+        );
+    }
+
+    response
+}
+
+fn format_tick(value: f32) -> String {
+    if value.fract() == 0.0 {
+        format!("{}", value)
+    } else {
+        format!("{:.2}", value)
+    }
+}
+
+fn lamp(ui: &mut egui::Ui, label: &str, signal: &Signal<bool>, now: Instant, color: Color32) {
+    let (rect, _) = ui.allocate_exact_size(Vec2::splat(14.0), Sense::hover());
+    let fill = match signal.get(now) {
+        Some(true) => color,
+        _ => ui.visuals().faint_bg_color,
+    };
+    ui.painter().circle(
+        rect.center(),
+        6.0,
+        fill,
+        Stroke::new(1.0, ui.visuals().weak_text_color()),
+    );
+    ui.label(label);
+}
+
+fn gear_label(gear: Gear) -> String {
+    match gear {
+        Gear::Park => String::from("P"),
+        Gear::Reverse => String::from("R"),
+        Gear::Neutral => String::from("N"),
+        Gear::Drive => String::from("D"),
+        Gear::Limit1 => String::from("1"),
+        Gear::Limit2 => String::from("2"),
+        Gear::Limit3 => String::from("3"),
+        Gear::Unknown(value) => format!("?{:#x}", value),
+    }
+}
@@ -0,0 +1,227 @@
+use crate::can::faults::MESSAGES;
+use crate::can::layout::{self, Field};
+use crate::can::raw::{format_data, parse_data, parse_id};
+use crate::can::{messages, miu};
+use crate::miu_state::{Ignition, MiuState};
+use crate::odometer::Odometer;
+use deku::DekuError;
+use socketcan::{CanFrame, EmbeddedFrame};
+
+const CELL_SIZE: f32 = 28.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Source {
+    /// A message as it is sent for the current state
+    Message(u32),
+
+    /// A frame typed in by the user
+    Raw,
+}
+
+/// Window that shows which bit of a frame belongs to which field.
+pub struct InspectorWindow {
+    pub open: bool,
+    source: Source,
+    raw_id: String,
+    raw_data: String,
+}
+
+impl Default for InspectorWindow {
+    fn default() -> Self {
+        Self {
+            open: false,
+            source: Source::Message(MESSAGES[0].0),
+            raw_id: String::from("1A0"),
+            raw_data: String::from("00 00 00 00 00 00 00 00"),
+        }
+    }
+}
+
+impl InspectorWindow {
+    pub fn show(
+        &mut self,
+        context: &egui::Context,
+        state: &MiuState,
+        emulation: &miu::Emulation,
+        odometer: &Odometer,
+    ) {
+        let mut open = self.open;
+        egui::Window::new("Inspector")
+            .open(&mut open)
+            .default_width(500.0)
+            .show(context, |ui| self.contents(ui, state, emulation, odometer));
+        self.open = open;
+    }
+
+    fn contents(
+        &mut self,
+        ui: &mut egui::Ui,
+        state: &MiuState,
+        emulation: &miu::Emulation,
+        odometer: &Odometer,
+    ) {
+        ui.horizontal_wrapped(|ui| {
+            for (id, name) in MESSAGES {
+                ui.selectable_value(&mut self.source, Source::Message(id), name);
+            }
+            ui.selectable_value(&mut self.source, Source::Raw, "Raw");
+        });
+
+        let (id, data) = match self.source {
+            Source::Message(id) => match encode(id, state, emulation, odometer) {
+                Some(Ok(frame)) => (id, frame.data().to_vec()),
+                Some(Err(error)) => {
+                    ui.colored_label(ui.visuals().error_fg_color, error.to_string());
+                    return;
+                }
+                None => (id, Vec::new()),
+            },
+            Source::Raw => {
+                egui::Grid::new("inspector-raw").show(ui, |ui| {
+                    ui.label("ID");
+                    ui.text_edit_singleline(&mut self.raw_id);
+                    ui.end_row();
+
+                    ui.label("Data");
+                    ui.text_edit_singleline(&mut self.raw_data);
+                    ui.end_row();
+                });
+
+                match (parse_id(&self.raw_id), parse_data(&self.raw_data)) {
+                    (Some(id), Some(data)) => (id, data),
+                    _ => {
+                        ui.colored_label(
+                            ui.visuals().error_fg_color,
+                            "the ID and data have to be hexadecimal, with at most 8 data bytes",
+                        );
+                        return;
+                    }
+                }
+            }
+        };
+
+        let (name, fields) = layout::find(id).unwrap_or(("Unknown message", &[]));
+        ui.label(format!("{:03X}  {}  [{}]", id, name, format_data(&data)));
+
+        ui.separator();
+        bit_grid(ui, fields, &data);
+        ui.separator();
+        field_table(ui, fields, &data);
+    }
+}
+
+/// The message with `id` as it is sent for `state`, by a node or by the emulated MIU.
+fn encode(
+    id: u32,
+    state: &MiuState,
+    emulation: &miu::Emulation,
+    odometer: &Odometer,
+) -> Option<Result<CanFrame, DekuError>> {
+    let ignition = Ignition::settled(state.key_position);
+    messages::encode(id, state, &ignition).or_else(|| miu::encode(id, state, emulation, odometer))
+}
+
+fn field_color(index: usize) -> egui::Color32 {
+    // Spread the hues so neighbouring fields are easy to tell apart
+    let hue = (index as f32 * 0.381_966) % 1.0;
+    egui::ecolor::Hsva::new(hue, 0.5, 0.7, 1.0).into()
+}
+
+/// Shows the frame as a grid of bytes and bits, coloured by the field each bit belongs to.
+fn bit_grid(ui: &mut egui::Ui, fields: &[Field], data: &[u8]) {
+    egui::Grid::new("inspector-bits")
+        .spacing([2.0, 2.0])
+        .show(ui, |ui| {
+            ui.label("");
+            for bit in (0..8).rev() {
+                ui.label(bit.to_string());
+            }
+            ui.end_row();
+
+            for (byte_index, byte) in data.iter().enumerate() {
+                ui.label(format!("byte {}", byte_index));
+
+                for bit_index in 0..8 {
+                    let bit = byte_index * 8 + bit_index;
+                    let value = (byte >> (7 - bit_index)) & 1;
+                    let field = fields.iter().position(|field| field.contains(bit));
+
+                    let (rect, response) = ui.allocate_exact_size(
+                        egui::vec2(CELL_SIZE, CELL_SIZE),
+                        egui::Sense::hover(),
+                    );
+                    let fill = match field {
+                        Some(index) => field_color(index),
+                        None => ui.visuals().faint_bg_color,
+                    };
+                    ui.painter().rect_filled(rect, 2.0, fill);
+                    ui.painter().text(
+                        rect.center(),
+                        egui::Align2::CENTER_CENTER,
+                        value.to_string(),
+                        egui::TextStyle::Monospace.resolve(ui.style()),
+                        ui.visuals().strong_text_color(),
+                    );
+
+                    response.on_hover_text(match field {
+                        Some(index) => {
+                            let field = &fields[index];
+                            format!(
+                                "{}\n{}\nbit {} of {}",
+                                field.name,
+                                field.symbol,
+                                bit - field.start,
+                                field.bits
+                            )
+                        }
+                        None => String::from("padding"),
+                    });
+                }
+
+                ui.label(format!("{:02X}", byte));
+                ui.end_row();
+            }
+        });
+}
+
+/// Lists the fields with their position and the value in the frame.
+fn field_table(ui: &mut egui::Ui, fields: &[Field], data: &[u8]) {
+    egui::Grid::new("inspector-fields")
+        .num_columns(5)
+        .striped(true)
+        .show(ui, |ui| {
+            ui.strong("Field");
+            ui.strong("Symbol");
+            ui.strong("Bits");
+            ui.strong("Value");
+            ui.strong("Hex");
+            ui.end_row();
+
+            for (index, field) in fields.iter().enumerate() {
+                let value = field.value(data);
+
+                ui.colored_label(field_color(index), field.name);
+                ui.label(field.symbol);
+                ui.label(format!("{}..{}", field.start, field.start + field.bits));
+                ui.label(value.to_string());
+                ui.label(format!("{:X}", value));
+                ui.end_row();
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_message_has_a_frame() {
+        let state = MiuState::default();
+        for (id, name) in MESSAGES {
+            let frame = encode(id, &state, &miu::Emulation::default(), &Odometer::default())
+                .unwrap_or_else(|| panic!("{} has no frame", name))
+                .unwrap();
+            assert!(!frame.data().is_empty(), "{} is empty", name);
+        }
+    }
+}
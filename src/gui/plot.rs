@@ -0,0 +1,197 @@
+use crate::can::ReceivedFrame;
+use crate::miu_state::MiuState;
+use crate::plot::{self, Recorder, CHANNELS, STATE_SAMPLE_PERIOD};
+use egui_plot::{Legend, Line, Plot, PlotPoints};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::{broadcast, watch};
+
+/// Number of plots stacked above each other, each with its own value axis.
+const AXES: usize = 3;
+
+/// Plots signals against time.
+pub struct PlotWindow {
+    pub open: bool,
+    recorder: Arc<Mutex<Recorder>>,
+    axes: [usize; CHANNELS.len()],
+    /// Seconds shown while following the live signals
+    window: f64,
+    reset_zoom: bool,
+    export_path: String,
+    export_result: Option<Result<String, String>>,
+}
+
+impl PlotWindow {
+    /// Starts recording in the background, this needs to be called within a tokio runtime.
+    pub fn new(
+        state: watch::Receiver<MiuState>,
+        frames: broadcast::Receiver<ReceivedFrame>,
+    ) -> Self {
+        let recorder = Arc::new(Mutex::new(Recorder::new(Instant::now())));
+        tokio::spawn(plot::record(Arc::downgrade(&recorder), state, frames));
+
+        Self {
+            open: false,
+            recorder,
+            axes: [0; CHANNELS.len()],
+            window: 30.0,
+            reset_zoom: false,
+            export_path: String::from("signals.csv"),
+            export_result: None,
+        }
+    }
+
+    pub fn show(&mut self, context: &egui::Context) {
+        if !self.open {
+            return;
+        }
+
+        let mut open = self.open;
+        egui::Window::new("Plot")
+            .open(&mut open)
+            .default_size([700.0, 500.0])
+            .show(context, |ui| self.contents(ui));
+        self.open = open;
+    }
+
+    fn contents(&mut self, ui: &mut egui::Ui) {
+        let recorder = Arc::clone(&self.recorder);
+        let mut recorder = plot::lock(&recorder);
+        let mut paused = recorder.is_paused();
+        if !paused {
+            ui.ctx().request_repaint_after(STATE_SAMPLE_PERIOD);
+        }
+
+        ui.horizontal(|ui| {
+            if ui.toggle_value(&mut paused, "Pause").changed() {
+                recorder.set_paused(paused);
+                self.reset_zoom |= !paused;
+            }
+            if ui.button("Clear").clicked() {
+                recorder.clear(Instant::now());
+            }
+
+            ui.add_enabled_ui(!paused, |ui| {
+                ui.label("Last");
+                ui.add(
+                    egui::DragValue::new(&mut self.window)
+                        .clamp_range(1.0..=3600.0)
+                        .suffix(" s"),
+                );
+            });
+            if paused && ui.button("Reset zoom").clicked() {
+                self.reset_zoom = true;
+            }
+
+            ui.separator();
+            ui.text_edit_singleline(&mut self.export_path);
+            if ui.button("Export CSV").clicked() {
+                self.export_result = Some(self.export(&recorder));
+            }
+            match &self.export_result {
+                Some(Ok(message)) => {
+                    ui.label(message);
+                }
+                Some(Err(message)) => {
+                    ui.colored_label(ui.visuals().error_fg_color, message);
+                }
+                None => {}
+            }
+        });
+
+        egui::CollapsingHeader::new("Signals")
+            .default_open(true)
+            .show(ui, |ui| {
+                egui::Grid::new("plot-signals")
+                    .num_columns(5)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (index, channel) in CHANNELS.iter().enumerate() {
+                            ui.checkbox(recorder.recorded_mut(index), channel.name);
+                            ui.label(channel.unit);
+                            ui.label(if channel.is_received() {
+                                "received"
+                            } else {
+                                "state"
+                            });
+                            ui.horizontal(|ui| {
+                                for axis in 0..AXES {
+                                    ui.selectable_value(
+                                        &mut self.axes[index],
+                                        axis,
+                                        format!("Axis {}", axis + 1),
+                                    );
+                                }
+                            });
+                            ui.label(format!("{} samples", recorder.history(index).len()));
+                            ui.end_row();
+                        }
+                    });
+            });
+
+        let used: Vec<usize> = (0..AXES)
+            .filter(|axis| {
+                (0..CHANNELS.len())
+                    .any(|index| recorder.is_recorded(index) && self.axes[index] == *axis)
+            })
+            .collect();
+        if used.is_empty() {
+            ui.label("Select signals to plot");
+            return;
+        }
+
+        let start = recorder.seconds(Instant::now()) - self.window;
+        let height = (ui.available_height() / used.len() as f32).max(80.0);
+        let reset_zoom = std::mem::take(&mut self.reset_zoom);
+
+        for axis in used {
+            let units: Vec<&str> = CHANNELS
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| recorder.is_recorded(*index) && self.axes[*index] == axis)
+                .map(|(_, channel)| channel.unit)
+                .collect();
+
+            let mut plot = Plot::new(("signal-plot", axis))
+                .height(height)
+                .legend(Legend::default())
+                .link_axis("signal-plot", true, false)
+                .link_cursor("signal-plot", true, false)
+                .x_axis_label("s")
+                .y_axis_label(units.join(", "))
+                .allow_zoom(paused)
+                .allow_drag(paused)
+                .allow_scroll(paused);
+            if !paused || reset_zoom {
+                plot = plot.reset();
+            }
+
+            plot.show(ui, |plot_ui| {
+                for (index, channel) in CHANNELS.iter().enumerate() {
+                    if !recorder.is_recorded(index) || self.axes[index] != axis {
+                        continue;
+                    }
+
+                    let history = recorder.history(index);
+                    let points: PlotPoints = if paused {
+                        history.samples().collect()
+                    } else {
+                        history.since(start).collect()
+                    };
+                    plot_ui.line(Line::new(points).name(channel.name));
+                }
+            });
+        }
+    }
+
+    fn export(&self, recorder: &Recorder) -> Result<String, String> {
+        let file = std::fs::File::create(&self.export_path).map_err(|error| error.to_string())?;
+        let mut writer = std::io::BufWriter::new(file);
+        recorder
+            .write_csv(&mut writer)
+            .and_then(|_| writer.flush())
+            .map_err(|error| error.to_string())?;
+        Ok(format!("saved to {}", self.export_path))
+    }
+}
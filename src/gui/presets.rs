@@ -0,0 +1,118 @@
+use crate::miu_state::MiuState;
+use crate::presets::Presets;
+
+/// Bar to apply, save, rename and delete presets.
+pub struct PresetBar {
+    presets: Presets,
+    selected: Option<String>,
+    name: String,
+    error: Option<String>,
+}
+
+impl PresetBar {
+    pub fn new() -> Self {
+        let (presets, error) = match Presets::load() {
+            Ok(presets) => (presets, None),
+            Err(error) => {
+                tracing::warn!("unable to load presets: {}", error);
+                (Presets::default(), Some(error.to_string()))
+            }
+        };
+
+        Self {
+            presets,
+            selected: None,
+            name: String::new(),
+            error,
+        }
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui, state: &mut MiuState) {
+        ui.horizontal(|ui| {
+            ui.label("preset");
+
+            egui::ComboBox::from_id_source("preset-selector")
+                .selected_text(self.selected.as_deref().unwrap_or("None"))
+                .show_ui(ui, |ui| {
+                    for name in self.presets.names() {
+                        if ui
+                            .selectable_label(self.selected.as_deref() == Some(name), name)
+                            .clicked()
+                        {
+                            self.selected = Some(name.to_owned());
+                            self.name = name.to_owned();
+                        }
+                    }
+                });
+
+            let selected = self
+                .selected
+                .as_deref()
+                .and_then(|name| self.presets.get(name));
+            if ui
+                .add_enabled(selected.is_some(), egui::Button::new("Apply"))
+                .clicked()
+            {
+                if let Some(preset) = selected {
+                    *state = *preset;
+                }
+            }
+
+            ui.separator();
+
+            ui.add(egui::TextEdit::singleline(&mut self.name).desired_width(150.0));
+            let name = self.name.trim();
+            let mut changed = false;
+
+            if ui
+                .add_enabled(!name.is_empty(), egui::Button::new("Save"))
+                .on_hover_text("Save the current state under this name")
+                .clicked()
+            {
+                self.presets.insert(name.to_owned(), *state);
+                self.selected = Some(name.to_owned());
+                changed = true;
+            }
+
+            let can_rename = self
+                .selected
+                .as_deref()
+                .is_some_and(|selected| !name.is_empty() && selected != name);
+            if ui
+                .add_enabled(can_rename, egui::Button::new("Rename"))
+                .clicked()
+            {
+                if let Some(selected) = self.selected.take() {
+                    if self.presets.rename(&selected, name.to_owned()) {
+                        self.selected = Some(name.to_owned());
+                        changed = true;
+                    } else {
+                        self.error = Some(format!("a preset named {} already exists", name));
+                        self.selected = Some(selected);
+                    }
+                }
+            }
+
+            if ui
+                .add_enabled(self.selected.is_some(), egui::Button::new("Delete"))
+                .clicked()
+            {
+                if let Some(selected) = self.selected.take() {
+                    self.presets.remove(&selected);
+                    changed = true;
+                }
+            }
+
+            if changed {
+                self.error = self.presets.save().err().map(|error| {
+                    tracing::warn!("unable to save presets: {}", error);
+                    error.to_string()
+                });
+            }
+
+            if let Some(error) = &self.error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+        });
+    }
+}
@@ -0,0 +1,93 @@
+use std::path::Path;
+use tokio::sync::watch;
+
+use crate::miu_state::MiuState;
+use crate::scenario::{Running, Scenario, Status};
+
+/// Window to run and stop scenarios.
+#[derive(Default)]
+pub struct ScenarioWindow {
+    pub open: bool,
+    path: String,
+    scenario: Option<Running>,
+    error: Option<String>,
+}
+
+impl ScenarioWindow {
+    /// Shows a scenario that was started from the command line.
+    pub fn with_scenario(path: String, scenario: Running) -> Self {
+        Self {
+            open: true,
+            path,
+            scenario: Some(scenario),
+            error: None,
+        }
+    }
+
+    pub fn show(&mut self, context: &egui::Context, miu_state: &watch::Sender<MiuState>) {
+        let mut open = self.open;
+        egui::Window::new("Scenario")
+            .open(&mut open)
+            .show(context, |ui| self.contents(ui, miu_state));
+        self.open = open;
+    }
+
+    fn contents(&mut self, ui: &mut egui::Ui, miu_state: &watch::Sender<MiuState>) {
+        ui.horizontal(|ui| {
+            ui.label("Path");
+            ui.text_edit_singleline(&mut self.path);
+
+            if ui.button("Run").clicked() {
+                // Stop the running scenario first so two scenarios don't fight over the state.
+                self.scenario = None;
+
+                match Scenario::load(Path::new(&self.path)) {
+                    Ok(scenario) => {
+                        self.scenario = Some(scenario.start(miu_state.clone()));
+                        self.error = None;
+                    }
+                    Err(error) => self.error = Some(error.to_string()),
+                }
+            }
+
+            let running = self
+                .scenario
+                .as_ref()
+                .is_some_and(|scenario| matches!(scenario.status(), Status::Running { .. }));
+            if ui.add_enabled(running, egui::Button::new("Stop")).clicked() {
+                if let Some(scenario) = &mut self.scenario {
+                    scenario.stop();
+                }
+            }
+        });
+
+        if let Some(error) = &self.error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+
+        if let Some(scenario) = &self.scenario {
+            match scenario.status() {
+                Status::Running { steps_done } => {
+                    ui.label(format!(
+                        "running, {} steps done, {:.1} of {:.1} s",
+                        steps_done,
+                        scenario.elapsed().as_secs_f32(),
+                        scenario.duration().as_secs_f32()
+                    ));
+                    // The progress changes without any input
+                    ui.ctx()
+                        .request_repaint_after(std::time::Duration::from_millis(100));
+                }
+                Status::Finished => {
+                    ui.label("finished");
+                }
+                Status::Stopped => {
+                    ui.label("stopped");
+                }
+                Status::Failed(error) => {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+            }
+        }
+    }
+}
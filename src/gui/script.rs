@@ -0,0 +1,99 @@
+use std::path::Path;
+use tokio::sync::watch;
+
+use crate::can::CanClient;
+use crate::miu_state::MiuState;
+use crate::script::{Script, Status};
+
+/// Window to load and stop scripts.
+#[derive(Default)]
+pub struct ScriptWindow {
+    pub open: bool,
+    path: String,
+    script: Option<Script>,
+    error: Option<String>,
+}
+
+impl ScriptWindow {
+    /// Shows a script that was loaded from the command line.
+    pub fn with_script(path: String, script: Script) -> Self {
+        Self {
+            open: true,
+            path,
+            script: Some(script),
+            error: None,
+        }
+    }
+
+    pub fn show(
+        &mut self,
+        context: &egui::Context,
+        can: &CanClient,
+        miu_state: &watch::Sender<MiuState>,
+    ) {
+        let mut open = self.open;
+        egui::Window::new("Script")
+            .open(&mut open)
+            .show(context, |ui| self.contents(ui, can, miu_state));
+        self.open = open;
+    }
+
+    fn contents(
+        &mut self,
+        ui: &mut egui::Ui,
+        can: &CanClient,
+        miu_state: &watch::Sender<MiuState>,
+    ) {
+        ui.horizontal(|ui| {
+            ui.label("Path");
+            ui.text_edit_singleline(&mut self.path);
+
+            if ui.button("Load").clicked() {
+                // Stop the running script first so two scripts don't fight over the state.
+                self.script = None;
+
+                match Script::load(Path::new(&self.path), can.clone(), miu_state.clone()) {
+                    Ok(script) => {
+                        self.script = Some(script);
+                        self.error = None;
+                    }
+                    Err(error) => self.error = Some(error.to_string()),
+                }
+            }
+
+            let running = self
+                .script
+                .as_ref()
+                .is_some_and(|script| script.status() == Status::Running);
+            if ui.add_enabled(running, egui::Button::new("Stop")).clicked() {
+                if let Some(script) = &mut self.script {
+                    script.stop();
+                }
+            }
+        });
+
+        if let Some(error) = &self.error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+
+        if let Some(script) = &self.script {
+            match script.status() {
+                Status::Running => {
+                    ui.label("running");
+                    // The status changes without any input
+                    ui.ctx()
+                        .request_repaint_after(std::time::Duration::from_millis(500));
+                }
+                Status::Finished => {
+                    ui.label("finished");
+                }
+                Status::Stopped => {
+                    ui.label("stopped");
+                }
+                Status::Failed(error) => {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+            }
+        }
+    }
+}
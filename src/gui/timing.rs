@@ -0,0 +1,105 @@
+use crate::can::nodes::NODES;
+use crate::can::timing::{MessageTiming, Scheduling, REPORT_PERIOD};
+use crate::can::CanClient;
+use std::time::Duration;
+
+/// Window that shows how well the frames that are sent keep to their periods.
+#[derive(Default)]
+pub struct TimingWindow {
+    pub open: bool,
+    pub scheduling: Scheduling,
+}
+
+impl TimingWindow {
+    pub fn show(&mut self, context: &egui::Context, can: &CanClient) {
+        let mut open = self.open;
+        egui::Window::new("Timing")
+            .open(&mut open)
+            .default_width(500.0)
+            .show(context, |ui| self.contents(ui, can));
+        self.open = open;
+    }
+
+    fn contents(&mut self, ui: &mut egui::Ui, can: &CanClient) {
+        ui.horizontal(|ui| {
+            let previous = self.scheduling;
+            ui.label("Scheduling");
+            ui.selectable_value(&mut self.scheduling, Scheduling::Normal, "Normal");
+            ui.selectable_value(&mut self.scheduling, Scheduling::Realtime, "Realtime")
+                .on_hover_text(
+                    "Send from a thread of its own with a real-time priority and a high \
+                     resolution timer, which uses more cpu but keeps the periods under load",
+                );
+            if self.scheduling != previous {
+                can.set_scheduling(self.scheduling)
+                    .expect("Failed to set scheduling");
+            }
+            ui.label("applies from the next connection");
+        });
+        ui.separator();
+
+        ui.ctx().request_repaint_after(REPORT_PERIOD);
+        let report = can
+            .transmit_timing()
+            .expect("Failed to get transmit timing");
+
+        if report.is_empty() {
+            ui.label("Nothing was sent yet");
+            return;
+        }
+
+        egui::Grid::new("timing-grid")
+            .num_columns(8)
+            .striped(true)
+            .show(ui, |ui| {
+                for heading in [
+                    "ID",
+                    "Node",
+                    "Period",
+                    "Frames",
+                    "Mean",
+                    "Max",
+                    "p99 jitter",
+                    "Missed",
+                ] {
+                    ui.strong(heading);
+                }
+                ui.end_row();
+
+                for timing in &report {
+                    row(ui, timing);
+                }
+            });
+    }
+}
+
+fn row(ui: &mut egui::Ui, timing: &MessageTiming) {
+    let node = NODES
+        .iter()
+        .find(|node| node.messages.contains(&timing.id))
+        .map(|node| node.id.name())
+        .unwrap_or_default();
+
+    ui.monospace(format!("{:03X}", timing.id));
+    ui.label(node);
+    ui.label(timing.period.map(milliseconds).unwrap_or_default());
+    ui.label(timing.frames.to_string());
+    ui.label(milliseconds(timing.mean_period));
+    ui.label(milliseconds(timing.max_period));
+    ui.label(milliseconds(timing.p99_jitter));
+    if timing.missed_deadlines > 0 {
+        ui.colored_label(
+            ui.visuals().warn_fg_color,
+            timing.missed_deadlines.to_string(),
+        );
+    } else if timing.period.is_some() {
+        ui.label("0");
+    } else {
+        ui.label("");
+    }
+    ui.end_row();
+}
+
+fn milliseconds(duration: Duration) -> String {
+    format!("{:.2} ms", duration.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,228 @@
+use crate::can::raw::{self, format_data, parse_data, parse_id, PeriodicFrame, PeriodicFrames};
+use crate::can::CanClient;
+use crate::config;
+
+/// A periodic frame with the text that is being edited.
+struct Entry {
+    frame: PeriodicFrame,
+    id: String,
+    data: String,
+}
+
+impl Entry {
+    fn new(frame: PeriodicFrame) -> Self {
+        Self {
+            id: format!("{:03X}", frame.id),
+            data: format_data(&frame.data),
+            frame,
+        }
+    }
+}
+
+/// Window to send frames typed in by the user, once or periodically.
+///
+/// The periodic frames are stored in the config dir, so they are still there in the next session.
+pub struct TransmitterWindow {
+    pub open: bool,
+    id: String,
+    data: String,
+    period_ms: u64,
+    entries: Vec<Entry>,
+    error: Option<String>,
+}
+
+impl TransmitterWindow {
+    /// Loads the periodic frames from the config dir and hands them to the can task.
+    pub fn new(can: &CanClient) -> Self {
+        let (frames, error) = match config::load::<PeriodicFrames>(PeriodicFrames::CONFIG_NAME) {
+            Ok(frames) => (frames.frames, None),
+            Err(error) => {
+                tracing::warn!("unable to load periodic frames: {}", error);
+                (Vec::new(), Some(error.to_string()))
+            }
+        };
+
+        let window = Self {
+            open: false,
+            id: String::new(),
+            data: String::new(),
+            period_ms: PeriodicFrame::default().period_ms,
+            entries: frames.into_iter().map(Entry::new).collect(),
+            error,
+        };
+        window.apply(can);
+
+        window
+    }
+
+    pub fn show(&mut self, context: &egui::Context, can: &CanClient) {
+        let mut open = self.open;
+        egui::Window::new("Transmitter")
+            .open(&mut open)
+            .default_width(500.0)
+            .show(context, |ui| self.contents(ui, can));
+        self.open = open;
+    }
+
+    fn contents(&mut self, ui: &mut egui::Ui, can: &CanClient) {
+        let mut changed = false;
+        // Typing and dragging change the frames on every frame drawn, they are only saved once
+        // the edit is done.
+        let mut save = false;
+
+        egui::Grid::new("transmitter-new-frame")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("ID");
+                ui.text_edit_singleline(&mut self.id);
+                ui.end_row();
+
+                ui.label("Data");
+                ui.text_edit_singleline(&mut self.data);
+                ui.end_row();
+
+                ui.label("Period");
+                ui.add(
+                    egui::DragValue::new(&mut self.period_ms)
+                        .clamp_range(1_u64..=10_000)
+                        .suffix(" ms"),
+                );
+                ui.end_row();
+            });
+
+        let parsed = parse_id(&self.id).zip(parse_data(&self.data));
+        ui.horizontal(|ui| {
+            ui.add_enabled_ui(parsed.is_some(), |ui| {
+                if ui.button("Send once").clicked() {
+                    if let Some(frame) =
+                        parsed.as_ref().and_then(|(id, data)| raw::frame(*id, data))
+                    {
+                        can.send_frame(frame).expect("Failed to send frame");
+                    }
+                }
+
+                if ui.button("Add to list").clicked() {
+                    if let Some((id, data)) = parsed.clone() {
+                        self.entries.push(Entry::new(PeriodicFrame {
+                            enabled: false,
+                            id,
+                            data,
+                            period_ms: self.period_ms,
+                        }));
+                        changed = true;
+                        save = true;
+                    }
+                }
+            });
+
+            if parsed.is_none() {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    "the ID and data have to be hexadecimal, with at most 8 data bytes",
+                );
+            }
+        });
+
+        ui.separator();
+
+        let mut removed = None;
+        egui::Grid::new("transmitter-periodic-frames")
+            .num_columns(5)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("Send");
+                ui.strong("ID");
+                ui.strong("Data");
+                ui.strong("Period");
+                ui.end_row();
+
+                for (index, entry) in self.entries.iter_mut().enumerate() {
+                    if ui.checkbox(&mut entry.frame.enabled, "").changed() {
+                        changed = true;
+                        save = true;
+                    }
+
+                    let id = ui.add(egui::TextEdit::singleline(&mut entry.id).desired_width(40.0));
+                    if id.changed() {
+                        if let Some(parsed) = parse_id(&entry.id) {
+                            entry.frame.id = parsed;
+                            changed = true;
+                        }
+                    }
+                    if id.lost_focus() {
+                        entry.id = format!("{:03X}", entry.frame.id);
+                        save = true;
+                    }
+
+                    let data =
+                        ui.add(egui::TextEdit::singleline(&mut entry.data).desired_width(180.0));
+                    if data.changed() {
+                        if let Some(parsed) = parse_data(&entry.data) {
+                            entry.frame.data = parsed;
+                            changed = true;
+                        }
+                    }
+                    if data.lost_focus() {
+                        entry.data = format_data(&entry.frame.data);
+                        save = true;
+                    }
+
+                    let period = ui.add(
+                        egui::DragValue::new(&mut entry.frame.period_ms)
+                            .clamp_range(1_u64..=10_000)
+                            .suffix(" ms"),
+                    );
+                    changed |= period.changed();
+                    save |= period.drag_stopped() || period.lost_focus();
+
+                    if ui.button("Delete").clicked() {
+                        removed = Some(index);
+                    }
+                    ui.end_row();
+                }
+            });
+
+        if let Some(index) = removed {
+            self.entries.remove(index);
+            changed = true;
+            save = true;
+        }
+
+        if changed {
+            self.apply(can);
+        }
+        if save {
+            self.save();
+        }
+
+        if let Some(error) = &self.error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+    }
+
+    /// The periodic frames as they are edited.
+    pub fn frames(&self) -> Vec<PeriodicFrame> {
+        self.entries
+            .iter()
+            .map(|entry| entry.frame.clone())
+            .collect()
+    }
+
+    fn apply(&self, can: &CanClient) {
+        can.set_periodic_frames(self.frames())
+            .expect("Failed to set periodic frames");
+    }
+
+    fn save(&mut self) {
+        let frames = PeriodicFrames {
+            frames: self.frames(),
+        };
+
+        self.error = config::save(PeriodicFrames::CONFIG_NAME, &frames)
+            .err()
+            .map(|error| {
+                tracing::warn!("unable to save periodic frames: {}", error);
+                error.to_string()
+            });
+    }
+}
@@ -0,0 +1,39 @@
+//! Broadcasts the state without showing a window, for setups without a display.
+use tokio::sync::watch;
+
+use crate::can::CanClient;
+use crate::miu_state::{KeyPosition, MiuState};
+use crate::presets::Presets;
+
+/// The state to start with: the preset with `name`, or the default state with the key on, because
+/// nothing is sent with the key off.
+pub fn initial_state(preset: Option<&str>) -> Result<MiuState, Box<dyn std::error::Error>> {
+    match preset {
+        Some(name) => Ok(*Presets::load()?
+            .get(name)
+            .ok_or_else(|| format!("there is no preset named {}", name))?),
+        None => Ok(MiuState {
+            key_position: KeyPosition::On,
+            ..Default::default()
+        }),
+    }
+}
+
+/// Broadcasts the state until ctrl-c is pressed. The state can still be changed through
+/// `miu_state`, by the api or a script for example.
+pub fn run(
+    runtime: &tokio::runtime::Handle,
+    can: CanClient,
+    miu_state: watch::Sender<MiuState>,
+    interface: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    tracing::info!("broadcasting {:?} on {}", *miu_state.borrow(), interface);
+    can.connect(interface, miu_state.subscribe())
+        .map_err(|_| "the can task stopped")?;
+
+    runtime.block_on(tokio::signal::ctrl_c())?;
+
+    can.disconnect().map_err(|_| "the can task stopped")?;
+
+    Ok(())
+}
@@ -1,17 +1,171 @@
+use clap::{Parser, Subcommand};
 use tokio::runtime::Runtime;
 use tokio::sync::watch;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
+mod analyser;
+mod api;
 mod can;
+mod config;
+mod export;
+mod gamepad;
 mod gui;
+mod headless;
+mod metrics;
 mod miu_state;
+mod odometer;
+mod pipe;
+mod plot;
+mod presets;
+mod recording;
+mod scenario;
+mod script;
+mod settings;
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    mode: Option<Mode>,
+
+    /// Serve the http api on this address, localhost only unless another address is given
+    #[arg(long, global = true, value_name = "ADDRESS", num_args = 0..=1, default_missing_value = api::DEFAULT_ADDRESS)]
+    api: Option<std::net::SocketAddr>,
+
+    /// Run this scenario, see `src/scenario.rs` for the format
+    #[arg(long, global = true, value_name = "PATH")]
+    scenario: Option<std::path::PathBuf>,
+
+    /// Run this Rhai script, see `src/script.rs` for what scripts can do
+    #[arg(long, global = true, value_name = "PATH")]
+    script: Option<std::path::PathBuf>,
+
+    /// Control the state with a gamepad, the bindings are read from `gamepad.toml` in the config
+    /// directory
+    #[arg(long, global = true)]
+    gamepad: bool,
+
+    /// Act as the MIU and send the vehicle speed and fuel level, for benches without a cluster
+    #[arg(long, global = true)]
+    emulate_miu: bool,
+
+    /// Also send the distance and SID button messages when emulating the MIU. Their ids and
+    /// layouts are a guess that hasn't been checked on a car.
+    #[arg(long, global = true, requires = "emulate_miu")]
+    experimental_miu_messages: bool,
+
+    /// Send the frames from a thread of its own with a real-time priority and a high resolution
+    /// timer, which uses more cpu but keeps the periods under load
+    #[arg(long, global = true)]
+    realtime: bool,
+}
+
+#[derive(Subcommand)]
+enum Mode {
+    /// Control the MIU from a window, this is the default
+    Gui,
+
+    /// Broadcast the state without a window until ctrl-c is pressed
+    Headless {
+        /// The can interface to broadcast on
+        #[arg(short, long)]
+        interface: String,
+
+        /// The name of the preset to broadcast, the default state with the key on is used when this
+        /// is left out
+        #[arg(short, long)]
+        preset: Option<String>,
+    },
+
+    /// Read JSON commands from stdin and write events to stdout as JSON lines
+    Pipe,
+
+    /// Decode the known signals in a candump log or a live session into a CSV or Parquet table
+    #[command(group(clap::ArgGroup::new("source").required(true).args(["input", "interface"])))]
+    Export {
+        /// The recording to read: a candump log, ASC, BLF or TRC
+        #[arg(short, long)]
+        input: Option<std::path::PathBuf>,
+
+        /// The format of the input, taken from its extension when left out
+        #[arg(long)]
+        input_format: Option<recording::Format>,
+
+        /// Record from this can interface until ctrl-c is pressed instead of reading a log
+        #[arg(long)]
+        interface: Option<String>,
+
+        /// Also keep the frames recorded from the interface in this candump log
+        #[arg(long, value_name = "PATH", requires = "interface")]
+        save_log: Option<std::path::PathBuf>,
+
+        /// The file to write
+        #[arg(short, long)]
+        output: std::path::PathBuf,
+
+        /// The output format, taken from the extension of the output when left out
+        #[arg(short, long)]
+        format: Option<export::Format>,
+
+        /// The signals to export, all known signals when left out
+        #[arg(short, long, value_delimiter = ',')]
+        signals: Vec<String>,
+
+        /// Write a row every this many milliseconds instead of a row per frame
+        #[arg(long, value_name = "MS", value_parser = clap::value_parser!(u64).range(1..))]
+        resample: Option<u64>,
+
+        /// How the values within a resampling period are combined
+        #[arg(long, default_value = "last")]
+        aggregation: export::Aggregation,
+    },
+
+    /// Convert a recording between the candump, ASC, BLF and TRC formats
+    Convert {
+        #[arg(short, long)]
+        input: std::path::PathBuf,
+
+        /// The format of the input, taken from its extension when left out
+        #[arg(long)]
+        input_format: Option<recording::Format>,
+
+        #[arg(short, long)]
+        output: std::path::PathBuf,
+
+        /// The format of the output, taken from its extension when left out
+        #[arg(long)]
+        output_format: Option<recording::Format>,
+    },
+
+    /// Send the frames in a recording on an interface, with the timing they were recorded with
+    Replay {
+        /// The recording to send: a candump log, ASC, BLF or TRC
+        #[arg(short, long)]
+        input: std::path::PathBuf,
+
+        /// The format of the input, taken from its extension when left out
+        #[arg(long)]
+        input_format: Option<recording::Format>,
+
+        /// The can interface to send on
+        #[arg(long)]
+        interface: String,
+
+        /// Start over at the end until ctrl-c is pressed
+        #[arg(long)]
+        repeat: bool,
+    },
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+
     let env_filter = tracing_subscriber::EnvFilter::builder()
         .with_default_directive(tracing_subscriber::filter::LevelFilter::INFO.into())
         .from_env_lossy();
-    let fmt = tracing_subscriber::fmt::layer();
+    // Logs go to stderr so they don't get mixed up with the events in pipe mode
+    let fmt = tracing_subscriber::fmt::layer().with_writer(std::io::stderr);
     tracing_subscriber::registry()
         .with(env_filter)
         .with(fmt)
@@ -22,12 +176,71 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Keeping this guard around is needed for `tokio::spawn` to work.
     let _guard = runtime.enter();
 
-    let (interfaces_client, interfaces_task) = can::interfaces::task();
-    let (can_client, mut can_task) = can::task(runtime.handle().clone());
+    match cli.mode {
+        Some(Mode::Export {
+            input,
+            input_format,
+            interface,
+            save_log,
+            output,
+            format,
+            signals,
+            resample,
+            aggregation,
+        }) => {
+            let source = match (input, interface) {
+                (Some(path), _) => export::Source::Log(path, input_format),
+                (None, interface) => {
+                    export::Source::Interface(interface.unwrap_or_default(), save_log)
+                }
+            };
+            let resampling = export::Resampling {
+                period: resample.map(std::time::Duration::from_millis),
+                aggregation,
+            };
+            export::run(
+                runtime.handle(),
+                source,
+                &output,
+                format,
+                &signals,
+                resampling,
+            )?;
+            return Ok(());
+        }
+        Some(Mode::Convert {
+            input,
+            input_format,
+            output,
+            output_format,
+        }) => {
+            let frames = recording::read(&input, input_format)?;
+            recording::write(&output, output_format, &frames)?;
+            tracing::info!("converted {} frames to {}", frames.len(), output.display());
+            return Ok(());
+        }
+        Some(Mode::Replay {
+            input,
+            input_format,
+            interface,
+            repeat,
+        }) => {
+            let frames = recording::read(&input, input_format)?;
+            tracing::info!("replaying {} frames on {}", frames.len(), interface);
+            runtime.block_on(async {
+                tokio::select! {
+                    result = recording::replay(&interface, &frames, repeat) => result,
+                    _ = tokio::signal::ctrl_c() => Ok(()),
+                }
+            })?;
+            return Ok(());
+        }
+        _ => {}
+    }
 
-    // We don't need the receiver right now and we can just create more receivers from the sender,
-    // so we can just drop it here.
-    let (miu_state_sender, _) = watch::channel(miu_state::MiuState::default());
+    let (interfaces_client, interfaces_task) = can::interfaces::task();
+    let handle = runtime.handle().clone();
+    let (can_client, mut can_task) = can::task(handle.clone());
 
     std::thread::spawn(move || {
         runtime.block_on(async {
@@ -45,19 +258,148 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         })
     });
 
+    // We don't need the receiver right now and we can just create more receivers from the sender,
+    // so we can just drop it here.
+    let (miu_state_sender, _) = watch::channel(miu_state::MiuState::default());
+
+    if let Some(address) = cli.api {
+        let api = api::Api {
+            can: can_client.clone(),
+            miu_state: miu_state_sender.clone(),
+        };
+        handle.spawn(async move {
+            if let Err(error) = api::serve(address, api).await {
+                tracing::error!("api stopped: {}", error);
+            }
+        });
+    }
+
+    let settings = settings::Settings::load();
+    miu_state_sender.send_replace(match &cli.mode {
+        Some(Mode::Headless { preset, .. }) => headless::initial_state(preset.as_deref())?,
+        Some(Mode::Pipe | Mode::Export { .. } | Mode::Convert { .. } | Mode::Replay { .. }) => {
+            miu_state::MiuState::default()
+        }
+        Some(Mode::Gui) | None => settings.miu_state,
+    });
+
+    if cli.gamepad {
+        gamepad::spawn(gamepad::Bindings::load()?, miu_state_sender.clone())?;
+    }
+
+    let miu_emulation = can::miu::Emulation {
+        enabled: cli.emulate_miu
+            || (matches!(cli.mode, Some(Mode::Gui) | None) && settings.emulate_miu),
+        experimental: cli.experimental_miu_messages,
+        ..Default::default()
+    };
+    can_client
+        .set_miu_emulation(miu_emulation)
+        .map_err(|_| "the can task stopped")?;
+    let scheduling = if cli.realtime {
+        can::timing::Scheduling::Realtime
+    } else if matches!(cli.mode, Some(Mode::Gui) | None) {
+        settings.scheduling
+    } else {
+        can::timing::Scheduling::Normal
+    };
+    can_client
+        .set_scheduling(scheduling)
+        .map_err(|_| "the can task stopped")?;
+
+    // The script stops when it goes out of scope, so keep it around until the end.
+    let script = match &cli.script {
+        Some(path) => Some(script::Script::load(
+            path,
+            can_client.clone(),
+            miu_state_sender.clone(),
+        )?),
+        None => None,
+    };
+
+    // The same goes for the scenario.
+    let scenario = match &cli.scenario {
+        Some(path) => Some(scenario::Scenario::load(path)?.start(miu_state_sender.clone())),
+        None => None,
+    };
+
+    match cli.mode {
+        Some(Mode::Headless { interface, .. }) => {
+            return headless::run(&handle, can_client, miu_state_sender, interface);
+        }
+        Some(Mode::Pipe) => return pipe::run(&handle, can_client, miu_state_sender),
+        Some(Mode::Export { .. } | Mode::Convert { .. } | Mode::Replay { .. }) => {
+            unreachable!("recordings are handled before the can task starts")
+        }
+        Some(Mode::Gui) | None => {}
+    }
+
+    if let Some(interface) = settings.interface.clone().filter(|_| settings.auto_connect) {
+        tracing::info!("auto-connecting to {}", interface);
+        can_client
+            .connect(interface, miu_state_sender.subscribe())
+            .expect("Failed to connect");
+    }
+
+    let mut analyser = gui::AnalyserWindow::new(can_client.received_frames());
+    analyser.open = settings.windows.analyser;
+    let mut cluster = gui::ClusterWindow::new(can_client.received_frames());
+    cluster.open = settings.windows.cluster;
+    let mut inspector = gui::InspectorWindow::default();
+    inspector.open = settings.windows.inspector;
+    let mut plot = gui::PlotWindow::new(miu_state_sender.subscribe(), can_client.received_frames());
+    plot.open = settings.windows.plot;
+    let mut timing = gui::TimingWindow::default();
+    timing.open = settings.windows.timing;
+    timing.scheduling = scheduling;
+    let mut transmitter = gui::TransmitterWindow::new(&can_client);
+    transmitter.open = settings.windows.transmitter;
+    // Windows showing what was started from the command line are open anyway
+    let mut scenario_window = match (cli.scenario, scenario) {
+        (Some(path), Some(scenario)) => {
+            gui::ScenarioWindow::with_scenario(path.display().to_string(), scenario)
+        }
+        _ => gui::ScenarioWindow::default(),
+    };
+    scenario_window.open |= settings.windows.scenario;
+    let mut script_window = match (cli.script, script) {
+        (Some(path), Some(script)) => {
+            gui::ScriptWindow::with_script(path.display().to_string(), script)
+        }
+        _ => gui::ScriptWindow::default(),
+    };
+    script_window.open |= settings.windows.script;
+
     let gui = Box::new(gui::Gui {
         can: can_client,
         interfaces: interfaces_client,
-        selected_interface: None,
-        miu_state: Default::default(),
+        selected_interface: settings.interface,
+        auto_connect: settings.auto_connect,
+        dark_mode: settings.dark_mode,
+        miu_state: settings.miu_state,
         miu_state_sender,
+        sid_message: Default::default(),
+        miu_emulation,
+        analyser,
+        cluster,
+        inspector,
+        plot,
+        timing,
+        transmitter,
+        preset_bar: gui::PresetBar::new(),
+        scenario: scenario_window,
+        script: script_window,
     });
 
     eframe::run_native(
         "miu",
         eframe::NativeOptions::default(),
-        Box::new(|cc| {
-            cc.egui_ctx.set_visuals(egui::Visuals::dark());
+        Box::new(move |cc| {
+            cc.egui_ctx.set_visuals(if settings.dark_mode {
+                egui::Visuals::dark()
+            } else {
+                egui::Visuals::light()
+            });
             gui
         }),
     )
@@ -0,0 +1,247 @@
+//! Metrics in the OpenMetrics text format, so a bench session can be recorded by Prometheus. They
+//! are served by the api at `/metrics`.
+use serde_json::Value;
+
+use crate::can::counters::Totals;
+use crate::miu_state::MiuState;
+
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// The states of a can controller, as reported by the kernel, in the order of their raw values.
+const BUS_STATES: [&str; 6] = [
+    "error_active",
+    "error_warning",
+    "error_passive",
+    "bus_off",
+    "stopped",
+    "sleeping",
+];
+
+/// What the metrics are made of.
+pub struct Snapshot<'a> {
+    pub totals: &'a Totals,
+    pub connected: bool,
+
+    /// The state of the can controller, see [`bus_state`]
+    pub bus_state: Option<&'static str>,
+
+    pub miu_state: &'a MiuState,
+}
+
+/// The state of the controller of `interface`, like `error_active` or `bus_off`, if it has one.
+/// Virtual interfaces don't.
+pub fn bus_state(interface: &str) -> Option<&'static str> {
+    let state = socketcan::CanInterface::open(interface)
+        .ok()?
+        .state()
+        .ok()??;
+
+    // The type of the state isn't public, but its raw value is
+    BUS_STATES.get(state as usize).copied()
+}
+
+pub fn render(snapshot: &Snapshot) -> String {
+    let mut metrics = Metrics::default();
+    let totals = snapshot.totals;
+
+    metrics.family(
+        "miu_can_connected",
+        "gauge",
+        "Whether the can bus is connected",
+    );
+    metrics.sample("miu_can_connected", "", u8::from(snapshot.connected));
+
+    metrics.family(
+        "miu_can_bus_state",
+        "gauge",
+        "State of the can controller, virtual interfaces have none",
+    );
+    if let Some(bus_state) = snapshot.bus_state {
+        for state in BUS_STATES {
+            let labels = label("state", state);
+            metrics.sample("miu_can_bus_state", &labels, u8::from(state == bus_state));
+        }
+    }
+
+    metrics.family(
+        "miu_can_reconnects",
+        "counter",
+        "Connections made after the first one",
+    );
+    metrics.sample("miu_can_reconnects_total", "", totals.reconnects() as f64);
+
+    metrics.family(
+        "miu_can_errors",
+        "counter",
+        "Connections that ended with an error, by kind",
+    );
+    for (kind, count) in &totals.errors {
+        metrics.sample("miu_can_errors_total", &label("kind", kind), *count as f64);
+    }
+
+    metrics.family("miu_can_frames_sent", "counter", "Frames sent by id");
+    for (id, count) in &totals.sent {
+        let labels = label("id", &format!("{:03X}", id));
+        metrics.sample("miu_can_frames_sent_total", &labels, *count as f64);
+    }
+
+    metrics.family(
+        "miu_can_frames_received",
+        "counter",
+        "Frames received by id, including the ones that were sent",
+    );
+    for (id, count) in &totals.received {
+        let labels = label("id", &format!("{:03X}", id));
+        metrics.sample("miu_can_frames_received_total", &labels, *count as f64);
+    }
+
+    miu_state(&mut metrics, snapshot.miu_state);
+
+    metrics.text.push_str("# EOF\n");
+    metrics.text
+}
+
+/// A gauge for every field of the state. Text values, like the key position, get a label with
+/// the value, and objects a label with the key of every value. Gears are raw values, so gears that
+/// aren't known can be told apart.
+fn miu_state(metrics: &mut Metrics, state: &MiuState) {
+    let Value::Object(fields) = serde_json::to_value(state).expect("the state can be serialized")
+    else {
+        return;
+    };
+
+    for (field, value) in fields {
+        let name = format!("miu_state_{}", field);
+        match value {
+            // Gears are raw values and the faults are summed up, see below
+            _ if matches!(field.as_str(), "gear_lever" | "actual_gear" | "faults") => {}
+            Value::Bool(_) | Value::Number(_) => {
+                metrics.family(&name, "gauge", &format!("The {} of the state", field));
+                metrics.sample(&name, "", number(&value).unwrap_or_default());
+            }
+            Value::String(text) => {
+                metrics.family(&name, "gauge", &format!("The {} of the state", field));
+                metrics.sample(&name, &label("value", &text), 1);
+            }
+            Value::Object(values) => {
+                metrics.family(&name, "gauge", &format!("The {} of the state", field));
+                for (key, value) in values {
+                    if let Some(value) = number(&value) {
+                        metrics.sample(&name, &label("key", &key), value);
+                    }
+                }
+            }
+            Value::Array(_) | Value::Null => {}
+        }
+    }
+
+    for (name, gear) in [
+        ("gear_lever", state.gear_lever),
+        ("actual_gear", state.actual_gear),
+    ] {
+        let name = format!("miu_state_{}", name);
+        metrics.family(
+            &name,
+            "gauge",
+            "The raw gear of the state, as the TCM sends it",
+        );
+        metrics.sample(&name, "", u8::from(gear));
+    }
+
+    metrics.family(
+        "miu_state_faults_injected",
+        "gauge",
+        "Whether any fault is injected",
+    );
+    metrics.sample(
+        "miu_state_faults_injected",
+        "",
+        u8::from(state.faults.any()),
+    );
+}
+
+fn label(name: &str, value: &str) -> String {
+    let value = value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n");
+    format!("{{{}=\"{}\"}}", name, value)
+}
+
+#[derive(Default)]
+struct Metrics {
+    text: String,
+}
+
+impl Metrics {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        self.text.push_str(&format!("# TYPE {} {}\n", name, kind));
+        self.text.push_str(&format!("# HELP {} {}.\n", name, help));
+    }
+
+    fn sample(&mut self, name: &str, labels: &str, value: impl Into<f64>) {
+        self.text
+            .push_str(&format!("{}{} {}\n", name, labels, value.into()));
+    }
+}
+
+/// The value of a field of the state as a number, if it has one.
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Bool(value) => Some(f64::from(u8::from(*value))),
+        Value::Number(value) => value.as_f64(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::miu_state::KeyPosition;
+
+    #[test]
+    fn it_renders_the_metrics() {
+        let mut totals = Totals::default();
+        totals.sent.insert(0x1a0, 12);
+        totals.received.insert(0x5c0, 3);
+        totals.errors.insert("io", 1);
+        totals.connections = 3;
+        let miu_state = MiuState {
+            key_position: KeyPosition::On,
+            vehicle_speed: 88,
+            ..Default::default()
+        };
+
+        let text = render(&Snapshot {
+            totals: &totals,
+            connected: true,
+            bus_state: Some("error_passive"),
+            miu_state: &miu_state,
+        });
+
+        for line in [
+            "miu_can_connected 1",
+            "miu_can_bus_state{state=\"error_active\"} 0",
+            "miu_can_bus_state{state=\"error_passive\"} 1",
+            "miu_can_reconnects_total 2",
+            "miu_can_errors_total{kind=\"io\"} 1",
+            "miu_can_frames_sent_total{id=\"1A0\"} 12",
+            "miu_can_frames_received_total{id=\"5C0\"} 3",
+            "miu_state_vehicle_speed 88",
+            "miu_state_key_position{value=\"On\"} 1",
+            "miu_state_cruise 0",
+            "miu_state_nodes{key=\"tcm\"} 1",
+            "miu_state_gear_lever 0",
+            "miu_state_faults_injected 0",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {}", line);
+        }
+        assert!(!text.contains("miu_state_faults "));
+        assert!(text.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn it_escapes_labels() {
+        assert_eq!(label("kind", "a\"b\\"), r#"{kind="a\"b\\"}"#);
+    }
+}
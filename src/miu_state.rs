@@ -1,9 +1,158 @@
+use crate::can::faults::{FaultInjection, MessageFault};
+use crate::can::nodes::Nodes;
+use crate::can::tcm::Gear;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::watch;
+
+/// Time it takes for all nodes on the bus to start sending after the ignition is switched on.
+///
+/// This and the wake-up delays of the nodes are an approximation of the timing, not taken from a
+/// car. The nodes just start sending their normal messages, the traffic of a real wake-up, like
+/// network management frames, isn't simulated.
+pub const WAKE_UP_DURATION: Duration = Duration::from_millis(300);
+
+/// Time the starter has to crank before the engine runs.
+pub const CRANKING_DURATION: Duration = Duration::from_millis(1200);
+
+/// Engine speed reported while cranking.
+pub const CRANKING_ENGINE_SPEED: u16 = 250;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub enum KeyPosition {
+    #[default]
+    Off,
+    Accessory,
+    On,
+    Start,
+}
+
+impl KeyPosition {
+    pub const ALL: [KeyPosition; 4] = [
+        KeyPosition::Off,
+        KeyPosition::Accessory,
+        KeyPosition::On,
+        KeyPosition::Start,
+    ];
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PowerMode {
+    #[default]
+    Off,
+    Accessory,
+    On,
+    Cranking,
+    Running,
+}
+
+/// Follows the key position over time to decide what the car is doing.
+///
+/// The key position is what the user selects, but the car needs time to react to it: the nodes on
+/// the bus need time to wake up and the engine has to crank before it runs. Turning the key back
+/// from start to on keeps the engine running, turning it to accessory or off stops it.
+#[derive(Debug, Default)]
+pub struct Ignition {
+    mode: PowerMode,
+    woke_at: Option<Instant>,
+    cranking_since: Option<Instant>,
+}
+
+impl Ignition {
+    /// The state the ignition ends up in when `key` is held long enough.
+    pub fn settled(key: KeyPosition) -> Self {
+        let mode = match key {
+            KeyPosition::Off => PowerMode::Off,
+            KeyPosition::Accessory => PowerMode::Accessory,
+            KeyPosition::On => PowerMode::On,
+            KeyPosition::Start => PowerMode::Running,
+        };
+
+        Self {
+            mode,
+            ..Default::default()
+        }
+    }
+
+    pub fn mode(&self) -> PowerMode {
+        self.mode
+    }
+
+    pub fn update(&mut self, key: KeyPosition, now: Instant) {
+        match key {
+            KeyPosition::Off | KeyPosition::Accessory => {
+                self.mode = if key == KeyPosition::Off {
+                    PowerMode::Off
+                } else {
+                    PowerMode::Accessory
+                };
+                self.woke_at = None;
+                self.cranking_since = None;
+            }
+            KeyPosition::On | KeyPosition::Start => {
+                let woke_at = *self.woke_at.get_or_insert(now);
+
+                if matches!(self.mode, PowerMode::Off | PowerMode::Accessory) {
+                    self.mode = PowerMode::On;
+                }
+
+                match (key, self.mode) {
+                    // The starter only engages once the ECU is awake
+                    (KeyPosition::Start, PowerMode::On)
+                        if now.duration_since(woke_at) >= WAKE_UP_DURATION =>
+                    {
+                        self.mode = PowerMode::Cranking;
+                        self.cranking_since = Some(now);
+                    }
+                    // Releasing the key before the engine runs stops cranking
+                    (KeyPosition::On, PowerMode::Cranking) => {
+                        self.mode = PowerMode::On;
+                        self.cranking_since = None;
+                    }
+                    _ => {}
+                }
+
+                if let Some(cranking_since) = self.cranking_since {
+                    if now.duration_since(cranking_since) >= CRANKING_DURATION {
+                        self.mode = PowerMode::Running;
+                        self.cranking_since = None;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Time since the bus woke up, or `None` when the ignition is not on.
+    pub fn awake_for(&self, now: Instant) -> Option<Duration> {
+        self.woke_at.map(|woke_at| now.duration_since(woke_at))
+    }
+
+    pub fn engine_started(&self) -> bool {
+        self.mode == PowerMode::Running
+    }
+
+    /// The engine speed that should be reported, which only follows the state when the engine runs.
+    pub fn engine_speed(&self, state: &MiuState) -> u16 {
+        match self.mode {
+            PowerMode::Running => state.engine_speed,
+            PowerMode::Cranking => CRANKING_ENGINE_SPEED,
+            _ => 0,
+        }
+    }
+}
+
 /// A representation of the Main Instrument Unit state.
 #[allow(dead_code)]
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 pub struct MiuState {
+    pub key_position: KeyPosition,
     pub engine_speed: u16,
     pub engine_speed_fault: bool,
+    /// In percent
+    pub accelerator_pedal: u8,
     pub vehicle_speed: u16,
     pub vehicle_speed_fault: bool,
     pub boost: u8,
@@ -13,13 +162,15 @@ pub struct MiuState {
     pub fuel_level_fault: bool,
     pub check_engine: bool,
     pub cruise: bool,
-    pub gear_lever: u8,
+    pub gear_lever: Gear,
     pub gear_lever_fault: bool,
-    pub actual_gear: u8,
+    pub actual_gear: Gear,
     pub actual_gear_fault: bool,
     pub sport: bool,
     pub winter: bool,
     pub check_gearbox: bool,
+    pub faults: FaultInjection,
+    pub nodes: Nodes,
 }
 
 impl MiuState {
@@ -36,11 +187,118 @@ impl MiuState {
 
         self.boost = (255.0 * percentage).floor() as u8;
     }
+
+    /// Changes the fields that are in `patch`. Objects are merged, everything else is replaced.
+    /// Nothing changes when the patch doesn't fit the state.
+    pub fn patch(&mut self, patch: Value) -> Result<(), PatchError> {
+        let mut value = serde_json::to_value(*self).expect("the state can always be serialized");
+        merge(&mut value, patch, "")?;
+
+        *self = serde_json::from_value(value)?;
+        Ok(())
+    }
+}
+
+/// Patches the shared state. Receivers are only notified when the patch changes something.
+pub fn patch(miu_state: &watch::Sender<MiuState>, patch: Value) -> Result<(), PatchError> {
+    let mut result = Ok(());
+    miu_state.send_if_modified(|state| {
+        let previous = *state;
+        result = state.patch(patch);
+        *state != previous
+    });
+    result
+}
+
+/// The patch that turns `from` into `to`. It only has the fields that differ, so applying it to a
+/// state that was changed elsewhere in the meantime keeps those changes.
+pub fn diff(from: &MiuState, to: &MiuState) -> Value {
+    let from = serde_json::to_value(from).expect("the state can always be serialized");
+    let to = serde_json::to_value(to).expect("the state can always be serialized");
+    diff_values(&from, to).unwrap_or_else(|| Value::Object(Default::default()))
+}
+
+fn diff_values(from: &Value, to: Value) -> Option<Value> {
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => {
+            let changed: serde_json::Map<String, Value> = to
+                .into_iter()
+                .filter_map(|(key, value)| {
+                    let value = match from.get(&key) {
+                        Some(previous) => diff_values(previous, value)?,
+                        None => value,
+                    };
+                    Some((key, value))
+                })
+                .collect();
+            (!changed.is_empty()).then_some(Value::Object(changed))
+        }
+        (from, to) => (*from != to).then_some(to),
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PatchError {
+    #[error("unknown field {0}")]
+    UnknownField(String),
+    #[error("{0}")]
+    Invalid(#[from] serde_json::Error),
+}
+
+fn merge(target: &mut Value, patch: Value, path: &str) -> Result<(), PatchError> {
+    match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => {
+            for (key, value) in patch {
+                // Faults are keyed by the id in upper case hex, but any case is accepted
+                let key = match u32::from_str_radix(&key, 16) {
+                    Ok(id) if path == "/faults" => format!("{:03X}", id),
+                    _ => key,
+                };
+                let path = format!("{}/{}", path, key);
+                let field = target
+                    .get_mut(&key)
+                    .ok_or_else(|| PatchError::UnknownField(path.clone()))?;
+                let mut value = value;
+                if path.starts_with("/faults/") {
+                    reset_fault(field, &mut value)?;
+                }
+                merge(field, value, &path)?;
+            }
+            Ok(())
+        }
+        (target, patch) => {
+            *target = patch;
+            Ok(())
+        }
+    }
+}
+
+/// Clears `fault` when `patch` has `"reset": true`, so options like the period can be switched off
+/// from formats without null, like the TOML of scenarios. The rest of the patch applies afterwards.
+fn reset_fault(fault: &mut Value, patch: &mut Value) -> Result<(), PatchError> {
+    let Some(reset) = patch
+        .as_object_mut()
+        .and_then(|patch| patch.remove("reset"))
+    else {
+        return Ok(());
+    };
+
+    match reset {
+        Value::Bool(true) => {
+            *fault = serde_json::to_value(MessageFault::default())
+                .expect("a fault can always be serialized");
+            Ok(())
+        }
+        Value::Bool(false) => Ok(()),
+        _ => Err(PatchError::Invalid(serde::de::Error::custom(
+            "reset has to be true or false",
+        ))),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::MiuState;
+    use super::*;
     use assert_approx_eq::assert_approx_eq;
 
     #[test]
@@ -73,4 +331,178 @@ mod tests {
         state.set_boost_percentage(0.5);
         assert_eq!(state.boost, 127);
     }
+
+    #[test]
+    fn it_stays_silent_with_the_key_off() {
+        let mut ignition = Ignition::default();
+        let now = Instant::now();
+
+        ignition.update(KeyPosition::Off, now);
+        assert_eq!(ignition.mode(), PowerMode::Off);
+        assert_eq!(ignition.awake_for(now), None);
+
+        ignition.update(KeyPosition::Accessory, now);
+        assert_eq!(ignition.mode(), PowerMode::Accessory);
+        assert_eq!(ignition.awake_for(now), None);
+    }
+
+    #[test]
+    fn it_cranks_before_the_engine_starts() {
+        let mut ignition = Ignition::default();
+        let state = MiuState {
+            engine_speed: 800,
+            ..Default::default()
+        };
+        let now = Instant::now();
+
+        ignition.update(KeyPosition::Start, now);
+        assert_eq!(ignition.mode(), PowerMode::On);
+        assert_eq!(ignition.engine_speed(&state), 0);
+
+        let cranking = now + WAKE_UP_DURATION;
+        ignition.update(KeyPosition::Start, cranking);
+        assert_eq!(ignition.mode(), PowerMode::Cranking);
+        assert_eq!(ignition.engine_speed(&state), CRANKING_ENGINE_SPEED);
+        assert!(!ignition.engine_started());
+
+        ignition.update(KeyPosition::Start, cranking + CRANKING_DURATION);
+        assert_eq!(ignition.mode(), PowerMode::Running);
+        assert_eq!(ignition.engine_speed(&state), 800);
+        assert!(ignition.engine_started());
+
+        // Letting go of the key keeps the engine running
+        ignition.update(KeyPosition::On, cranking + CRANKING_DURATION);
+        assert!(ignition.engine_started());
+
+        ignition.update(KeyPosition::Off, cranking + CRANKING_DURATION);
+        assert!(!ignition.engine_started());
+    }
+
+    #[test]
+    fn it_stops_cranking_when_the_key_is_released() {
+        let mut ignition = Ignition::default();
+        let now = Instant::now();
+
+        ignition.update(KeyPosition::On, now);
+        ignition.update(KeyPosition::Start, now + WAKE_UP_DURATION);
+        ignition.update(KeyPosition::On, now + WAKE_UP_DURATION);
+        ignition.update(KeyPosition::On, now + WAKE_UP_DURATION + CRANKING_DURATION);
+
+        assert_eq!(ignition.mode(), PowerMode::On);
+        assert_eq!(
+            ignition.awake_for(now + WAKE_UP_DURATION),
+            Some(WAKE_UP_DURATION)
+        );
+    }
+
+    #[test]
+    fn it_patches_fields() {
+        let mut state = MiuState::default();
+
+        state
+            .patch(serde_json::json!({
+                "engine_speed": 3000,
+                "key_position": "On",
+                "gear_lever": "Drive",
+            }))
+            .unwrap();
+
+        assert_eq!(state.engine_speed, 3000);
+        assert_eq!(state.key_position, KeyPosition::On);
+        assert_eq!(state.gear_lever, Gear::Drive);
+        assert_eq!(state.vehicle_speed, 0);
+    }
+
+    #[test]
+    fn it_patches_the_fault_of_one_message() {
+        let mut state = MiuState::default();
+
+        state
+            .patch(serde_json::json!({ "faults": { "280": { "dropout": true } } }))
+            .unwrap();
+
+        assert!(state.faults.get(0x280).dropout);
+        assert!(!state.faults.get(0x1a0).dropout);
+    }
+
+    #[test]
+    fn it_diffs_only_the_changed_fields() {
+        let previous = MiuState::default();
+        let mut changed = previous;
+        changed.engine_speed = 3000;
+        changed.faults.get_mut(0x280).unwrap().period_ms = Some(200);
+
+        let patch = diff(&previous, &changed);
+        assert_eq!(
+            patch,
+            serde_json::json!({ "engine_speed": 3000, "faults": { "280": { "period_ms": 200 } } })
+        );
+        assert_eq!(diff(&changed, &changed), serde_json::json!({}));
+
+        // A change made elsewhere in the meantime survives
+        let mut shared = previous;
+        shared.faults.get_mut(0x3e0).unwrap().dropout = true;
+        shared.patch(patch).unwrap();
+        assert_eq!(shared.engine_speed, 3000);
+        assert!(shared.faults.get(0x3e0).dropout);
+
+        let cleared = diff(&changed, &previous);
+        shared.patch(cleared).unwrap();
+        assert_eq!(shared.faults.get(0x280).period_ms, None);
+    }
+
+    #[test]
+    fn it_resets_a_fault() {
+        let mut state = MiuState::default();
+        state
+            .patch(serde_json::json!({ "faults": { "3E0": { "period_ms": 300, "dlc": 2 } } }))
+            .unwrap();
+
+        state
+            .patch(serde_json::json!({ "faults": { "3E0": { "reset": true, "jitter_ms": 5 } } }))
+            .unwrap();
+        let fault = state.faults.get(0x3e0);
+        assert_eq!(fault.period_ms, None);
+        assert_eq!(fault.dlc, None);
+        assert_eq!(fault.jitter_ms, 5);
+
+        assert!(state
+            .patch(serde_json::json!({ "faults": { "3E0": { "reset": 1 } } }))
+            .is_err());
+    }
+
+    #[test]
+    fn it_accepts_fault_ids_in_any_case() {
+        let mut state = MiuState::default();
+
+        state
+            .patch(serde_json::json!({ "faults": { "2f0": { "dropout": true }, "5c0": { "stale": true } } }))
+            .unwrap();
+
+        assert!(state.faults.get(0x2f0).dropout);
+        assert!(state.faults.get(0x5c0).stale);
+        assert!(state
+            .patch(serde_json::json!({ "faults": { "123": { "dropout": true } } }))
+            .is_err());
+    }
+
+    #[test]
+    fn it_rejects_bad_patches() {
+        let mut state = MiuState::default();
+
+        assert!(state.patch(serde_json::json!({ "speed": 1 })).is_err());
+        assert!(state.patch(serde_json::json!({ "boost": 300 })).is_err());
+        assert!(state
+            .patch(serde_json::json!({ "engine_speed": 1000, "key_position": "Sideways" }))
+            .is_err());
+
+        assert_eq!(state, MiuState::default());
+    }
+
+    #[test]
+    fn it_settles_on_the_key_position() {
+        assert_eq!(Ignition::settled(KeyPosition::Off).mode(), PowerMode::Off);
+        assert_eq!(Ignition::settled(KeyPosition::On).mode(), PowerMode::On);
+        assert!(Ignition::settled(KeyPosition::Start).engine_started());
+    }
 }
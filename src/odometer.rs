@@ -0,0 +1,206 @@
+use socketcan::{CanFrame, EmbeddedFrame, Frame};
+use std::time::{Duration, Instant};
+
+use crate::can::{miu, Message};
+use miu_protocol::signals;
+
+/// A gap between two vehicle speed frames longer than this is taken as a lost signal, and isn't
+/// counted. How long the cluster keeps counting with the last speed isn't known.
+pub const SPEED_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Distance travelled according to the vehicle speed.
+///
+/// The cluster counts distance from the speed signal, so running a known distance here and
+/// comparing it with the cluster shows whether the cluster counts correctly. When vehicle speed
+/// frames are on the bus, only those count, including their faults: a dropout, a lost signal or a
+/// frame that can't be decoded stops the count, like it would for the cluster. Without them, the
+/// speed of the state is counted instead, see [`Odometer::simulate`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Odometer {
+    total_m: f64,
+    trip_m: f64,
+
+    /// The last valid speed in km/h and when it was received
+    last_speed: Option<(f64, Instant)>,
+
+    /// When the last vehicle speed frame was received, valid or not
+    last_frame: Option<Instant>,
+}
+
+impl Odometer {
+    /// Counts the distance since the previous vehicle speed frame at its speed, and takes the speed
+    /// of `frame` from `time` on. Returns whether `frame` was a vehicle speed frame.
+    pub fn record(&mut self, frame: &CanFrame, time: Instant) -> bool {
+        if frame.raw_id() != miu::VehicleSpeed::CAN_ID {
+            return false;
+        }
+
+        if let Some((speed_kmh, last)) = self.last_speed {
+            let elapsed = time.saturating_duration_since(last);
+            if elapsed <= SPEED_TIMEOUT {
+                self.integrate(speed_kmh, elapsed);
+            }
+        }
+
+        self.last_frame = Some(time);
+        self.last_speed = miu::VehicleSpeed::decode(frame.data())
+            .ok()
+            .filter(|message| message.vehicle_speed_fault == 0)
+            .map(|message| {
+                (
+                    f64::from(signals::vehicle_speed(message.vehicle_speed)),
+                    time,
+                )
+            });
+        true
+    }
+
+    /// Counts `speed_kmh` from the state during `elapsed`, unless vehicle speed frames were
+    /// received lately, which count instead. This keeps the distance running when nothing sends the
+    /// speed, like without MIU emulation. Returns whether the distance changed.
+    pub fn simulate(&mut self, speed_kmh: u16, elapsed: Duration, now: Instant) -> bool {
+        let frames_on_bus = self
+            .last_frame
+            .is_some_and(|last| now.saturating_duration_since(last) <= SPEED_TIMEOUT);
+        if frames_on_bus || speed_kmh == 0 {
+            return false;
+        }
+
+        self.integrate(f64::from(speed_kmh), elapsed);
+        true
+    }
+
+    /// Adds the distance travelled at `speed_kmh` during `elapsed`.
+    fn integrate(&mut self, speed_kmh: f64, elapsed: Duration) {
+        let distance_m = speed_kmh / 3.6 * elapsed.as_secs_f64();
+
+        self.total_m += distance_m;
+        self.trip_m += distance_m;
+    }
+
+    pub fn reset_trip(&mut self) {
+        self.trip_m = 0.0;
+    }
+
+    pub fn total_km(&self) -> f64 {
+        self.total_m / 1000.0
+    }
+
+    pub fn trip_km(&self) -> f64 {
+        self.trip_m / 1000.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::can::faults::MessageFault;
+    use crate::miu_state::MiuState;
+    use assert_approx_eq::assert_approx_eq;
+
+    const PERIOD: Duration = Duration::from_millis(100);
+
+    fn speed_frame(speed_kmh: u16, fault: &MessageFault) -> CanFrame {
+        let state = MiuState {
+            vehicle_speed: speed_kmh,
+            ..Default::default()
+        };
+        let frame = crate::can::frame(&miu::vehicle_speed(&state)).unwrap();
+        crate::can::faults::Scheduler::default().apply(frame, fault)
+    }
+
+    /// Sends `frames` speed frames a period apart, starting at `start`, and returns the time after
+    /// the last one.
+    fn drive(odometer: &mut Odometer, frame: &CanFrame, frames: u32, start: Instant) -> Instant {
+        for n in 0..frames {
+            odometer.record(frame, start + PERIOD * n);
+        }
+        start + PERIOD * frames
+    }
+
+    #[test]
+    fn it_integrates_the_speed_that_was_sent() {
+        let mut odometer = Odometer::default();
+        let frame = speed_frame(36, &MessageFault::default());
+
+        // 100 s at 36 km/h
+        let end = drive(&mut odometer, &frame, 1001, Instant::now());
+        assert_approx_eq!(odometer.total_km(), 1.0);
+        assert_approx_eq!(odometer.trip_km(), 1.0);
+
+        // The first frame counts the time since the last one
+        drive(&mut odometer, &frame, 1000, end);
+        assert_approx_eq!(odometer.total_km(), 2.0);
+    }
+
+    #[test]
+    fn it_only_counts_while_the_speed_is_on_the_bus() {
+        let mut odometer = Odometer::default();
+        let frame = speed_frame(36, &MessageFault::default());
+
+        // 1 s at 36 km/h
+        let end = drive(&mut odometer, &frame, 11, Instant::now());
+        assert_approx_eq!(odometer.total_km(), 0.01);
+
+        // A dropout longer than the timeout isn't counted
+        let end = drive(&mut odometer, &frame, 11, end + SPEED_TIMEOUT * 2);
+        assert_approx_eq!(odometer.total_km(), 0.02);
+
+        // Neither are frames that are cut short or flag the speed as faulty, only the time until
+        // the first of them
+        let short = speed_frame(
+            36,
+            &MessageFault {
+                dlc: Some(2),
+                ..Default::default()
+            },
+        );
+        let end = drive(&mut odometer, &short, 11, end);
+        let faulty = crate::can::frame(&miu::vehicle_speed(&MiuState {
+            vehicle_speed: 36,
+            vehicle_speed_fault: true,
+            ..Default::default()
+        }))
+        .unwrap();
+        let end = drive(&mut odometer, &faulty, 11, end);
+        assert_approx_eq!(odometer.total_km(), 0.021);
+
+        assert!(!odometer.record(&CanFrame::from_raw_id(0x123, &[0; 8]).unwrap(), end));
+    }
+
+    #[test]
+    fn it_simulates_the_speed_without_frames() {
+        let mut odometer = Odometer::default();
+        let start = Instant::now();
+
+        assert!(odometer.simulate(36, Duration::from_secs(100), start));
+        assert_approx_eq!(odometer.total_km(), 1.0);
+        assert!(!odometer.simulate(0, Duration::from_secs(100), start));
+
+        // Frames on the bus take over, also when they can't be used
+        let short = speed_frame(
+            36,
+            &MessageFault {
+                dlc: Some(2),
+                ..Default::default()
+            },
+        );
+        odometer.record(&short, start);
+        assert!(!odometer.simulate(36, Duration::from_secs(100), start + PERIOD));
+        assert!(odometer.simulate(36, Duration::from_secs(100), start + SPEED_TIMEOUT * 2));
+        assert_approx_eq!(odometer.total_km(), 2.0);
+    }
+
+    #[test]
+    fn it_resets_the_trip_only() {
+        let mut odometer = Odometer::default();
+        let frame = speed_frame(100, &MessageFault::default());
+
+        let end = drive(&mut odometer, &frame, 361, Instant::now());
+        odometer.reset_trip();
+        drive(&mut odometer, &frame, 360, end);
+
+        assert_approx_eq!(odometer.total_km(), 2.0);
+        assert_approx_eq!(odometer.trip_km(), 1.0);
+    }
+}
@@ -0,0 +1,365 @@
+//! Control through JSON lines on stdin, with events as JSON lines on stdout.
+//!
+//! This is a lighter alternative to the api for programs that start miu-com as a subprocess.
+//! Every line on stdin is a command, for example:
+//!
+//! ```text
+//! {"command": "connect", "interface": "vcan0"}
+//! {"command": "set", "field": "engine_speed", "value": 3000}
+//! {"command": "send", "id": 792, "data": [0, 0, 0, 0, 0, 0, 0, 0]}
+//! {"command": "load_preset", "name": "cold start"}
+//! {"command": "load_scenario", "path": "scenarios/tcm_timeout.toml"}
+//! {"command": "stop_scenario"}
+//! {"command": "disconnect"}
+//! ```
+//!
+//! A scenario runs in the background until its last step, see `src/scenario.rs`. Loading another
+//! one stops the one that is running. The application ends when stdin is closed.
+use serde::{Deserialize, Serialize};
+use socketcan::{EmbeddedFrame, Frame};
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+use tokio::sync::{broadcast, mpsc, watch};
+
+use crate::can::{self, layout, raw, CanClient, ReceivedFrame};
+use crate::miu_state::{self, MiuState};
+use crate::presets::Presets;
+use crate::scenario::{self, Scenario};
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum Command {
+    Set {
+        field: String,
+        value: serde_json::Value,
+    },
+    Connect {
+        interface: String,
+    },
+    Disconnect,
+    Send {
+        id: u32,
+        data: Vec<u8>,
+    },
+    LoadPreset {
+        name: String,
+    },
+    LoadScenario {
+        path: PathBuf,
+    },
+    StopScenario,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event {
+    State(Box<MiuState>),
+    Connection {
+        connected: bool,
+    },
+    Frame {
+        /// Seconds since the application started
+        time: f64,
+        id: u32,
+        data: Vec<u8>,
+        /// The name of the message, if it is known
+        #[serde(skip_serializing_if = "Option::is_none")]
+        name: Option<&'static str>,
+        #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+        fields: BTreeMap<&'static str, u64>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+impl Event {
+    fn frame(time: f64, frame: &socketcan::CanFrame) -> Self {
+        let data = frame.data().to_vec();
+        let (name, fields) = match layout::find(frame.raw_id()) {
+            Some((name, fields)) => (
+                Some(name),
+                fields
+                    .iter()
+                    .map(|field| (field.name, field.value(&data)))
+                    .collect(),
+            ),
+            None => (None, BTreeMap::new()),
+        };
+
+        Self::Frame {
+            time,
+            id: frame.raw_id(),
+            data,
+            name,
+            fields,
+        }
+    }
+}
+
+/// Reads commands from stdin until it is closed.
+pub fn run(
+    runtime: &tokio::runtime::Handle,
+    can: CanClient,
+    miu_state: watch::Sender<MiuState>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (errors, error_receiver) = mpsc::unbounded_channel();
+    let writer = runtime.spawn(write_events(
+        error_receiver,
+        miu_state.subscribe(),
+        can.received_frames(),
+        can.connection_state(),
+    ));
+
+    let mut scenario = None;
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Err(message) = handle(&line, &can, &miu_state, &mut scenario) {
+            // The writer only stops when this sender is dropped
+            let _ = errors.send(message);
+        }
+    }
+
+    tracing::info!("stdin closed, stopping");
+    drop(errors);
+    let _ = can.disconnect();
+    runtime.block_on(writer)?;
+
+    Ok(())
+}
+
+/// Applies one command. A scenario that is started is kept in `scenario`, so it runs until the next
+/// one is loaded or it is stopped.
+fn handle(
+    line: &str,
+    can: &CanClient,
+    miu_state: &watch::Sender<MiuState>,
+    scenario: &mut Option<scenario::Running>,
+) -> Result<(), String> {
+    let command: Command = serde_json::from_str(line).map_err(|error| error.to_string())?;
+    tracing::debug!("received command: {:?}", command);
+
+    match command {
+        Command::Set { field, value } => {
+            miu_state::patch(miu_state, serde_json::json!({ field: value }))
+                .map_err(|error| error.to_string())
+        }
+        Command::Connect { interface } => can
+            .connect(interface, miu_state.subscribe())
+            .map_err(|_| String::from("the can task stopped")),
+        Command::Disconnect => can
+            .disconnect()
+            .map_err(|_| String::from("the can task stopped")),
+        Command::Send { id, data } => {
+            let frame = raw::frame(id, &data)
+                .ok_or_else(|| String::from("the id has to fit 11 bits and data 8 bytes"))?;
+            if can
+                .state()
+                .map_err(|_| String::from("the can task stopped"))?
+                != can::State::Connected
+            {
+                return Err(String::from("not connected"));
+            }
+            can.send_frame(frame)
+                .map_err(|_| String::from("the can task stopped"))
+        }
+        Command::LoadPreset { name } => {
+            let presets = Presets::load().map_err(|error| error.to_string())?;
+            let preset = presets
+                .get(&name)
+                .ok_or_else(|| format!("there is no preset named {}", name))?;
+            miu_state.send_replace(*preset);
+            Ok(())
+        }
+        Command::LoadScenario { path } => {
+            // Stop the running scenario first so two scenarios don't fight over the state.
+            *scenario = None;
+            let loaded = Scenario::load(&path).map_err(|error| error.to_string())?;
+            *scenario = Some(loaded.start(miu_state.clone()));
+            Ok(())
+        }
+        Command::StopScenario => {
+            scenario
+                .as_mut()
+                .ok_or_else(|| String::from("no scenario was loaded"))?
+                .stop();
+            Ok(())
+        }
+    }
+}
+
+async fn write_events(
+    mut errors: mpsc::UnboundedReceiver<String>,
+    mut miu_state: watch::Receiver<MiuState>,
+    mut frames: broadcast::Receiver<ReceivedFrame>,
+    mut connection_state: can::StateReceiver,
+) {
+    let started = Instant::now();
+    let mut event = Some(Event::State(Box::new(*miu_state.borrow_and_update())));
+
+    loop {
+        if let Some(event) = event.take() {
+            let line = serde_json::to_string(&event).expect("events can always be serialized");
+            let mut stdout = std::io::stdout().lock();
+            if writeln!(stdout, "{}", line)
+                .and_then(|_| stdout.flush())
+                .is_err()
+            {
+                tracing::info!("stdout closed, no longer writing events");
+                return;
+            }
+        }
+
+        event = tokio::select! {
+            message = errors.recv() => match message {
+                Some(message) => Some(Event::Error { message }),
+                None => return,
+            },
+            Ok(()) = miu_state.changed() => Some(Event::State(Box::new(*miu_state.borrow_and_update()))),
+            Ok(()) = connection_state.changed() => Some(Event::Connection {
+                connected: *connection_state.borrow_and_update() == can::State::Connected,
+            }),
+            result = frames.recv() => match result {
+                Ok(received) => Some(Event::frame(
+                    received.time.saturating_duration_since(started).as_secs_f64(),
+                    &received.frame,
+                )),
+                Err(broadcast::error::RecvError::Lagged(count)) => Some(Event::Error {
+                    message: format!("missed {} received frames", count),
+                }),
+                Err(broadcast::error::RecvError::Closed) => None,
+            },
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::miu_state::KeyPosition;
+
+    #[test]
+    fn it_parses_commands() {
+        let parse = |line| serde_json::from_str::<Command>(line).unwrap();
+
+        assert_eq!(
+            parse(r#"{"command": "set", "field": "engine_speed", "value": 3000}"#),
+            Command::Set {
+                field: String::from("engine_speed"),
+                value: serde_json::json!(3000),
+            }
+        );
+        assert_eq!(
+            parse(r#"{"command": "send", "id": 792, "data": [1, 2]}"#),
+            Command::Send {
+                id: 0x318,
+                data: vec![1, 2],
+            }
+        );
+        assert_eq!(parse(r#"{"command": "disconnect"}"#), Command::Disconnect);
+        assert_eq!(
+            parse(r#"{"command": "load_scenario", "path": "timeout.toml"}"#),
+            Command::LoadScenario {
+                path: PathBuf::from("timeout.toml"),
+            }
+        );
+        assert!(serde_json::from_str::<Command>(r#"{"command": "explode"}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn it_applies_commands() {
+        let (can, _task) = can::task(tokio::runtime::Handle::current());
+        let miu_state = watch::Sender::new(MiuState::default());
+
+        // The client blocks, which is not allowed on the test runtime
+        tokio::task::spawn_blocking(move || {
+            let mut scenario = None;
+            handle(
+                r#"{"command": "set", "field": "boost", "value": 128}"#,
+                &can,
+                &miu_state,
+                &mut scenario,
+            )
+            .unwrap();
+            assert_eq!(miu_state.borrow().boost, 128);
+
+            let error = handle(
+                r#"{"command": "set", "field": "boost", "value": -1}"#,
+                &can,
+                &miu_state,
+                &mut scenario,
+            )
+            .unwrap_err();
+            assert!(error.contains("invalid value"), "{}", error);
+
+            let error = handle(
+                r#"{"command": "send", "id": 2048, "data": []}"#,
+                &can,
+                &miu_state,
+                &mut scenario,
+            )
+            .unwrap_err();
+            assert!(error.contains("11 bits"), "{}", error);
+
+            handle(
+                r#"{"command": "load_scenario", "path": "scenarios/tcm_timeout.toml"}"#,
+                &can,
+                &miu_state,
+                &mut scenario,
+            )
+            .unwrap();
+            let started = Instant::now();
+            while miu_state.borrow().key_position != KeyPosition::On {
+                assert!(
+                    started.elapsed().as_secs() < 5,
+                    "the first step did not run"
+                );
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            handle(
+                r#"{"command": "stop_scenario"}"#,
+                &can,
+                &miu_state,
+                &mut scenario,
+            )
+            .unwrap();
+            assert_eq!(
+                scenario.as_ref().map(|scenario| scenario.status()),
+                Some(scenario::Status::Stopped)
+            );
+
+            let error = handle(
+                r#"{"command": "load_scenario", "path": "missing.toml"}"#,
+                &can,
+                &miu_state,
+                &mut scenario,
+            )
+            .unwrap_err();
+            assert!(error.contains("unable to read scenario"), "{}", error);
+            assert!(scenario.is_none());
+        })
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn it_decodes_known_frames() {
+        let frame = raw::frame(0x1a0, &[0, 0x03, 0x52, 0, 0, 0, 0, 0]).unwrap();
+
+        let event = serde_json::to_value(Event::frame(1.5, &frame)).unwrap();
+        assert_eq!(event["event"], "frame");
+        assert_eq!(event["id"], 0x1a0);
+        assert_eq!(event["name"], "Engine speed and throttle");
+        assert_eq!(event["fields"]["speed"], 850);
+
+        let unknown = raw::frame(0x123, &[1]).unwrap();
+        let event = serde_json::to_value(Event::frame(1.5, &unknown)).unwrap();
+        assert!(event.get("name").is_none());
+    }
+}
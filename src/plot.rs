@@ -0,0 +1,462 @@
+//! Records signals over time, to see how they change.
+//!
+//! Signals come either from the local state, which is what gets sent, or from the received frames,
+//! which is what the bus actually carries. Each signal keeps a bounded history, so recording can
+//! run for as long as the application does. Recording happens in the background, see [`record`],
+//! so the samples don't depend on how often the window is drawn.
+use socketcan::{CanFrame, Frame};
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::sync::{Mutex, PoisonError, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, watch};
+use tokio::time;
+
+use crate::can::cluster::Cluster;
+use crate::can::{miu, t7, Message, ReceivedFrame};
+use crate::miu_state::MiuState;
+
+/// Number of samples kept per signal, about 8 minutes of a message sent every 50 ms.
+pub const HISTORY_CAPACITY: usize = 10_000;
+
+/// The state only changes through user input, so it doesn't have to be sampled as often as frames
+/// are received.
+pub const STATE_SAMPLE_PERIOD: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Copy, Debug)]
+enum Source {
+    State(fn(&MiuState) -> f64),
+
+    /// Sampled when a frame with `id` is received
+    Received {
+        id: u32,
+        value: fn(&Cluster, Instant) -> Option<f64>,
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Channel {
+    pub name: &'static str,
+    pub unit: &'static str,
+    source: Source,
+}
+
+impl Channel {
+    pub fn is_received(&self) -> bool {
+        matches!(self.source, Source::Received { .. })
+    }
+}
+
+pub const CHANNELS: [Channel; 9] = [
+    Channel {
+        name: "Engine speed",
+        unit: "rpm",
+        source: Source::State(|state| f64::from(state.engine_speed)),
+    },
+    Channel {
+        name: "Vehicle speed",
+        unit: "km/h",
+        source: Source::State(|state| f64::from(state.vehicle_speed)),
+    },
+    Channel {
+        name: "Boost",
+        unit: "%",
+        source: Source::State(|state| f64::from(state.get_boost_percentage()) * 100.0),
+    },
+    Channel {
+        name: "Coolant temperature",
+        unit: "°C",
+        source: Source::State(|state| f64::from(state.coolant_temperature)),
+    },
+    Channel {
+        name: "RX engine speed",
+        unit: "rpm",
+        source: Source::Received {
+            id: t7::EngineSpeedAndThrottle::CAN_ID,
+            value: |cluster, now| cluster.engine_speed.get(now).map(f64::from),
+        },
+    },
+    Channel {
+        name: "RX vehicle speed",
+        unit: "km/h",
+        source: Source::Received {
+            id: miu::VehicleSpeed::CAN_ID,
+            value: |cluster, now| cluster.vehicle_speed.get(now).map(f64::from),
+        },
+    },
+    Channel {
+        name: "RX boost",
+        unit: "%",
+        source: Source::Received {
+            id: t7::FuelConsumptionAndBoost::CAN_ID,
+            value: |cluster, now| {
+                cluster
+                    .boost
+                    .get(now)
+                    .map(|boost| f64::from(boost) / f64::from(u8::MAX) * 100.0)
+            },
+        },
+    },
+    Channel {
+        name: "RX coolant temperature",
+        unit: "°C",
+        source: Source::Received {
+            id: t7::AirAndCoolant::CAN_ID,
+            value: |cluster, now| cluster.coolant_temperature.get(now).map(f64::from),
+        },
+    },
+    Channel {
+        name: "RX fuel level",
+        unit: "l",
+        source: Source::Received {
+            id: miu::FuelLevel::CAN_ID,
+            value: |cluster, now| cluster.fuel_level.get(now).map(f64::from),
+        },
+    },
+];
+
+/// The last samples of a signal, as seconds since the recording started and the value.
+#[derive(Clone, Debug)]
+pub struct History {
+    samples: VecDeque<[f64; 2]>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Adds a sample, dropping the oldest one when the history is full.
+    pub fn push(&mut self, time: f64, value: f64) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back([time, value]);
+    }
+
+    pub fn samples(&self) -> impl Iterator<Item = [f64; 2]> + '_ {
+        self.samples.iter().copied()
+    }
+
+    /// The samples at or after `start`.
+    pub fn since(&self, start: f64) -> impl Iterator<Item = [f64; 2]> + '_ {
+        let first = self.samples.partition_point(|[time, _]| *time < start);
+        self.samples.range(first..).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+}
+
+/// Records the selected channels.
+pub struct Recorder {
+    start: Instant,
+    cluster: Cluster,
+    recorded: [bool; CHANNELS.len()],
+    histories: Vec<History>,
+    last_state_sample: Option<Instant>,
+    paused: bool,
+}
+
+impl Recorder {
+    pub fn new(start: Instant) -> Self {
+        Self {
+            start,
+            cluster: Cluster::default(),
+            recorded: [false; CHANNELS.len()],
+            histories: vec![History::new(HISTORY_CAPACITY); CHANNELS.len()],
+            last_state_sample: None,
+            paused: false,
+        }
+    }
+
+    /// Starts over at `start`, keeping the selected channels.
+    pub fn clear(&mut self, start: Instant) {
+        *self = Self {
+            recorded: self.recorded,
+            paused: self.paused,
+            ..Self::new(start)
+        };
+    }
+
+    /// Nothing is recorded while paused.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Seconds since the recording started.
+    pub fn seconds(&self, time: Instant) -> f64 {
+        time.saturating_duration_since(self.start).as_secs_f64()
+    }
+
+    pub fn recorded_mut(&mut self, channel: usize) -> &mut bool {
+        &mut self.recorded[channel]
+    }
+
+    pub fn is_recorded(&self, channel: usize) -> bool {
+        self.recorded[channel]
+    }
+
+    pub fn history(&self, channel: usize) -> &History {
+        &self.histories[channel]
+    }
+
+    pub fn sample_state(&mut self, state: &MiuState, now: Instant) {
+        let due = self
+            .last_state_sample
+            .is_none_or(|last| now.saturating_duration_since(last) >= STATE_SAMPLE_PERIOD);
+        if !due || self.paused {
+            return;
+        }
+        self.last_state_sample = Some(now);
+
+        let time = self.seconds(now);
+        for (index, channel) in CHANNELS.iter().enumerate() {
+            if let (true, Source::State(value)) = (self.recorded[index], channel.source) {
+                self.histories[index].push(time, value(state));
+            }
+        }
+    }
+
+    pub fn receive(&mut self, frame: &CanFrame, time: Instant) {
+        if self.paused {
+            return;
+        }
+        self.cluster.receive(frame, time);
+
+        let seconds = self.seconds(time);
+        for (index, channel) in CHANNELS.iter().enumerate() {
+            if let (true, Source::Received { id, value }) = (self.recorded[index], channel.source) {
+                if id != frame.raw_id() {
+                    continue;
+                }
+                if let Some(value) = value(&self.cluster, time) {
+                    self.histories[index].push(seconds, value);
+                }
+            }
+        }
+    }
+
+    /// Writes every recorded sample as a row of time, signal and value.
+    pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "time_s,signal,unit,value")?;
+
+        let mut rows: Vec<(f64, &Channel, f64)> = CHANNELS
+            .iter()
+            .zip(&self.histories)
+            .flat_map(|(channel, history)| {
+                history
+                    .samples()
+                    .map(move |[time, value]| (time, channel, value))
+            })
+            .collect();
+        rows.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        for (time, channel, value) in rows {
+            writeln!(
+                writer,
+                "{:.6},{},{},{}",
+                time, channel.name, channel.unit, value
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Samples the state every [`STATE_SAMPLE_PERIOD`] and records the frames as they are received,
+/// until the recorder is dropped.
+pub async fn record(
+    recorder: Weak<Mutex<Recorder>>,
+    mut state: watch::Receiver<MiuState>,
+    mut frames: broadcast::Receiver<ReceivedFrame>,
+) {
+    let mut interval = time::interval(STATE_SAMPLE_PERIOD);
+    interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+
+    loop {
+        tokio::select! {
+            result = frames.recv() => {
+                let received = match result {
+                    Ok(received) => received,
+                    Err(broadcast::error::RecvError::Lagged(count)) => {
+                        tracing::warn!("plot missed {} frames", count);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                let Some(recorder) = recorder.upgrade() else {
+                    break;
+                };
+                lock(&recorder).receive(&received.frame, received.time);
+            }
+
+            _ = interval.tick() => {
+                let Some(recorder) = recorder.upgrade() else {
+                    break;
+                };
+                let state = *state.borrow_and_update();
+                lock(&recorder).sample_state(&state, Instant::now());
+            }
+        }
+    }
+
+    tracing::debug!("plot recording ended");
+}
+
+/// Locks the recorder, which is always valid even when another thread panicked while holding it.
+pub fn lock(recorder: &Mutex<Recorder>) -> std::sync::MutexGuard<'_, Recorder> {
+    recorder.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::can::messages;
+    use crate::miu_state::{Ignition, KeyPosition};
+    use std::sync::Arc;
+
+    fn channel(name: &str) -> usize {
+        CHANNELS
+            .iter()
+            .position(|channel| channel.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn history_drops_the_oldest_samples() {
+        let mut history = History::new(3);
+        for time in 0..5 {
+            history.push(f64::from(time), 0.0);
+        }
+
+        let times: Vec<f64> = history.samples().map(|[time, _]| time).collect();
+        assert_eq!(times, [2.0, 3.0, 4.0]);
+
+        let recent: Vec<f64> = history.since(3.0).map(|[time, _]| time).collect();
+        assert_eq!(recent, [3.0, 4.0]);
+    }
+
+    #[test]
+    fn it_only_records_selected_channels() {
+        let start = Instant::now();
+        let mut recorder = Recorder::new(start);
+        *recorder.recorded_mut(channel("Engine speed")) = true;
+
+        let state = MiuState {
+            engine_speed: 850,
+            vehicle_speed: 30,
+            ..Default::default()
+        };
+        recorder.sample_state(&state, start);
+        // Too soon after the previous sample
+        recorder.sample_state(&state, start + Duration::from_millis(10));
+        recorder.sample_state(&state, start + STATE_SAMPLE_PERIOD);
+
+        let samples: Vec<[f64; 2]> = recorder
+            .history(channel("Engine speed"))
+            .samples()
+            .collect();
+        assert_eq!(samples, [[0.0, 850.0], [0.05, 850.0]]);
+        assert_eq!(recorder.history(channel("Vehicle speed")).len(), 0);
+    }
+
+    #[test]
+    fn it_records_received_signals_when_their_frame_arrives() {
+        let start = Instant::now();
+        let mut recorder = Recorder::new(start);
+        *recorder.recorded_mut(channel("RX engine speed")) = true;
+        *recorder.recorded_mut(channel("RX coolant temperature")) = true;
+
+        let state = MiuState {
+            engine_speed: 3000,
+            coolant_temperature: 90,
+            ..Default::default()
+        };
+        let ignition = Ignition::settled(KeyPosition::Start);
+        let frame = messages::encode(t7::EngineSpeedAndThrottle::CAN_ID, &state, &ignition)
+            .unwrap()
+            .unwrap();
+        recorder.receive(&frame, start + Duration::from_secs(1));
+
+        let samples: Vec<[f64; 2]> = recorder
+            .history(channel("RX engine speed"))
+            .samples()
+            .collect();
+        assert_eq!(samples, [[1.0, 3000.0]]);
+        assert_eq!(recorder.history(channel("RX coolant temperature")).len(), 0);
+    }
+
+    #[test]
+    fn it_records_nothing_while_paused() {
+        let start = Instant::now();
+        let mut recorder = Recorder::new(start);
+        *recorder.recorded_mut(channel("Engine speed")) = true;
+
+        recorder.set_paused(true);
+        recorder.sample_state(&MiuState::default(), start);
+        assert_eq!(recorder.history(channel("Engine speed")).len(), 0);
+
+        recorder.set_paused(false);
+        recorder.sample_state(&MiuState::default(), start);
+        assert_eq!(recorder.history(channel("Engine speed")).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn it_samples_the_state_in_the_background() {
+        let recorder = Arc::new(Mutex::new(Recorder::new(Instant::now())));
+        *lock(&recorder).recorded_mut(channel("Engine speed")) = true;
+        let (_state_sender, state) = watch::channel(MiuState::default());
+        let (frame_sender, frames) = broadcast::channel(1);
+        let task = tokio::spawn(record(Arc::downgrade(&recorder), state, frames));
+
+        time::sleep(STATE_SAMPLE_PERIOD * 5).await;
+        assert!(lock(&recorder).history(channel("Engine speed")).len() >= 2);
+
+        drop(frame_sender);
+        task.await.unwrap();
+    }
+
+    #[test]
+    fn it_exports_samples_in_time_order() {
+        let start = Instant::now();
+        let mut recorder = Recorder::new(start);
+        *recorder.recorded_mut(channel("Engine speed")) = true;
+        *recorder.recorded_mut(channel("Vehicle speed")) = true;
+        recorder.sample_state(
+            &MiuState {
+                engine_speed: 850,
+                vehicle_speed: 0,
+                ..Default::default()
+            },
+            start,
+        );
+        recorder.sample_state(
+            &MiuState {
+                engine_speed: 900,
+                vehicle_speed: 5,
+                ..Default::default()
+            },
+            start + Duration::from_millis(500),
+        );
+
+        let mut csv = Vec::new();
+        recorder.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "time_s,signal,unit,value\n\
+             0.000000,Engine speed,rpm,850\n\
+             0.000000,Vehicle speed,km/h,0\n\
+             0.500000,Engine speed,rpm,900\n\
+             0.500000,Vehicle speed,km/h,5\n"
+        );
+    }
+}
@@ -0,0 +1,121 @@
+//! Named snapshots of the MIU state, so the same situation can be set up again quickly.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::config::{self, ConfigError};
+use crate::miu_state::MiuState;
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Presets {
+    #[serde(default)]
+    presets: BTreeMap<String, MiuState>,
+}
+
+impl Presets {
+    pub const CONFIG_NAME: &'static str = "presets";
+
+    pub fn load() -> Result<Self, ConfigError> {
+        config::load(Self::CONFIG_NAME)
+    }
+
+    pub fn save(&self) -> Result<(), ConfigError> {
+        config::save(Self::CONFIG_NAME, self)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.presets.keys().map(String::as_str)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&MiuState> {
+        self.presets.get(name)
+    }
+
+    /// Stores `state` under `name`, replacing the preset that had that name.
+    pub fn insert(&mut self, name: String, state: MiuState) {
+        self.presets.insert(name, state);
+    }
+
+    /// Renames a preset. Returns false when there is no preset `from` or `to` is already taken.
+    pub fn rename(&mut self, from: &str, to: String) -> bool {
+        if self.presets.contains_key(&to) {
+            return false;
+        }
+
+        match self.presets.remove(from) {
+            Some(state) => {
+                self.presets.insert(to, state);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<MiuState> {
+        self.presets.remove(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::can::tcm::Gear;
+    use crate::miu_state::KeyPosition;
+
+    fn redline_in_third() -> MiuState {
+        let mut state = MiuState {
+            key_position: KeyPosition::Start,
+            engine_speed: 6500,
+            vehicle_speed: 120,
+            gear_lever: Gear::Limit3,
+            actual_gear: Gear::Limit3,
+            ..Default::default()
+        };
+        let fault = state.faults.get_mut(0x1a0).unwrap();
+        fault.period_ms = Some(200);
+        fault.dlc = Some(4);
+
+        state
+    }
+
+    #[test]
+    fn it_survives_a_round_trip_through_toml() {
+        let mut presets = Presets::default();
+        presets.insert(String::from("cold start"), MiuState::default());
+        presets.insert(String::from("redline in 3rd"), redline_in_third());
+
+        let text = toml::to_string_pretty(&presets).unwrap();
+        assert_eq!(toml::from_str::<Presets>(&text).unwrap(), presets);
+    }
+
+    #[test]
+    fn it_fills_in_missing_fields() {
+        let presets: Presets = toml::from_str(
+            r#"
+            [presets.idle]
+            key_position = "On"
+            engine_speed = 850
+            "#,
+        )
+        .unwrap();
+
+        let idle = presets.get("idle").unwrap();
+        assert_eq!(idle.key_position, KeyPosition::On);
+        assert_eq!(idle.engine_speed, 850);
+        assert_eq!(idle.boost, 0);
+    }
+
+    #[test]
+    fn it_renames_presets() {
+        let mut presets = Presets::default();
+        presets.insert(String::from("a"), MiuState::default());
+        presets.insert(String::from("b"), redline_in_third());
+
+        assert!(!presets.rename("a", String::from("b")));
+        assert!(!presets.rename("c", String::from("d")));
+        assert!(presets.rename("b", String::from("c")));
+
+        assert_eq!(presets.names().collect::<Vec<_>>(), ["a", "c"]);
+        assert_eq!(presets.get("c"), Some(&redline_in_third()));
+        assert_eq!(presets.remove("a"), Some(MiuState::default()));
+    }
+}
@@ -0,0 +1,310 @@
+//! Frames recorded from the bus, and the log files they're kept in.
+//!
+//! Besides candump logs, the formats of Vector CANalyzer (ASC and BLF) and PEAK (TRC) can be read
+//! and written, so captures made with those tools can be replayed on the bench.
+use clap::ValueEnum;
+use futures::StreamExt;
+use socketcan::tokio::CanSocket;
+use socketcan::{CanFrame, EmbeddedFrame, ExtendedId, Id, StandardId};
+use std::future::Future;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+pub mod asc;
+pub mod blf;
+pub mod candump;
+pub mod trc;
+
+#[derive(Clone, Debug)]
+pub struct RecordedFrame {
+    /// Time since the unix epoch, or since the start of the recording when the format doesn't say
+    /// when it started
+    pub time: Duration,
+    pub frame: CanFrame,
+}
+
+#[derive(Debug, Error)]
+pub enum RecordingError {
+    #[error("unable to read or write recording")]
+    IO(#[from] std::io::Error),
+    #[error("line {line}: {message}")]
+    Parse { line: usize, message: String },
+    #[error("invalid recording: {0}")]
+    Invalid(String),
+    #[error("unknown recording format for {0}, give the format")]
+    UnknownFormat(String),
+    #[error("can error")]
+    SocketCan(#[from] socketcan::Error),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Format {
+    /// `candump -l`, usually `.log`
+    Candump,
+    /// Vector ASCII logging
+    Asc,
+    /// Vector binary logging
+    Blf,
+    /// PEAK trace
+    Trc,
+}
+
+impl Format {
+    /// The format that goes with the extension of `path`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "log" | "candump" => Some(Self::Candump),
+            "asc" => Some(Self::Asc),
+            "blf" => Some(Self::Blf),
+            "trc" => Some(Self::Trc),
+            _ => None,
+        }
+    }
+
+    fn resolve(format: Option<Self>, path: &Path) -> Result<Self, RecordingError> {
+        format
+            .or_else(|| Self::from_path(path))
+            .ok_or_else(|| RecordingError::UnknownFormat(path.display().to_string()))
+    }
+}
+
+/// Reads the recording at `path`, in `format` or the format that goes with the extension.
+pub fn read(path: &Path, format: Option<Format>) -> Result<Vec<RecordedFrame>, RecordingError> {
+    let format = Format::resolve(format, path)?;
+    let reader = BufReader::new(std::fs::File::open(path)?);
+
+    match format {
+        Format::Candump => candump::read(reader),
+        Format::Asc => asc::read(reader),
+        Format::Blf => blf::read(reader),
+        Format::Trc => trc::read(reader),
+    }
+}
+
+/// Writes `frames` to `path`, in `format` or the format that goes with the extension.
+pub fn write(
+    path: &Path,
+    format: Option<Format>,
+    frames: &[RecordedFrame],
+) -> Result<(), RecordingError> {
+    let format = Format::resolve(format, path)?;
+    let mut writer = BufWriter::new(std::fs::File::create(path)?);
+
+    match format {
+        Format::Candump => candump::write(&mut writer, frames, "can0")?,
+        Format::Asc => asc::write(&mut writer, frames)?,
+        Format::Blf => blf::write(&mut writer, frames)?,
+        Format::Trc => trc::write(&mut writer, frames)?,
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Records the frames on `interface` until `stop` completes, without sending anything.
+pub async fn record(
+    interface: &str,
+    stop: impl Future<Output = ()>,
+) -> Result<Vec<RecordedFrame>, RecordingError> {
+    let mut socket = CanSocket::open(interface)?;
+    let mut frames = Vec::new();
+    tokio::pin!(stop);
+
+    loop {
+        tokio::select! {
+            _ = &mut stop => return Ok(frames),
+            frame = socket.next() => match frame {
+                Some(frame) => frames.push(RecordedFrame {
+                    time: SystemTime::now()
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .unwrap_or_default(),
+                    frame: frame?,
+                }),
+                None => return Ok(frames),
+            },
+        }
+    }
+}
+
+/// Sends `frames` on `interface` with the time between them as recorded, over and over when
+/// `repeat` is set.
+pub async fn replay(
+    interface: &str,
+    frames: &[RecordedFrame],
+    repeat: bool,
+) -> Result<(), RecordingError> {
+    let socket = CanSocket::open(interface)?;
+    let Some(first) = frames.first() else {
+        return Ok(());
+    };
+
+    loop {
+        let start = tokio::time::Instant::now();
+        for recorded in frames {
+            let offset = recorded.time.saturating_sub(first.time);
+            tokio::time::sleep_until(start + offset).await;
+            socket.write_frame(recorded.frame)?.await?;
+        }
+
+        if !repeat {
+            return Ok(());
+        }
+    }
+}
+
+/// Builds a frame from the parts the log formats keep. The data of remote frames is ignored.
+fn build_frame(id: u32, extended: bool, remote: bool, dlc: usize, data: &[u8]) -> Option<CanFrame> {
+    let id: Id = if extended {
+        ExtendedId::new(id)?.into()
+    } else {
+        StandardId::new(u16::try_from(id).ok()?)?.into()
+    };
+
+    if remote {
+        CanFrame::new_remote(id, dlc)
+    } else {
+        CanFrame::new(id, data)
+    }
+}
+
+/// Parses seconds with an optional fraction, like `1436509052.249713`, without rounding errors.
+fn parse_seconds(text: &str) -> Option<Duration> {
+    let (seconds, fraction) = text.split_once('.').unwrap_or((text, ""));
+    if !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let nanos = format!("{:0<9}", fraction).get(..9)?.parse().ok()?;
+    Some(Duration::new(seconds.parse().ok()?, nanos))
+}
+
+/// The time since the unix epoch of a UTC calendar date and time.
+fn unix_time(
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    nanos: u32,
+) -> Option<Duration> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Days since 1970-01-01, from http://howardhinnant.github.io/date_algorithms.html
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    let seconds = days * 86_400 + i64::from(hour * 3600 + minute * 60 + second);
+    Some(Duration::new(u64::try_from(seconds).ok()?, nanos))
+}
+
+/// The UTC calendar date and time of a time since the unix epoch: year, month, day, hour, minute,
+/// second.
+fn calendar(time: Duration) -> (i64, u32, u32, u32, u32, u32) {
+    let seconds = time.as_secs();
+    let days = (seconds / 86_400) as i64 + 719_468;
+    let of_day = (seconds % 86_400) as u32;
+
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (
+        year,
+        month,
+        day,
+        of_day / 3600,
+        of_day / 60 % 60,
+        of_day % 60,
+    )
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use socketcan::{EmbeddedFrame, ExtendedId, Frame, StandardId};
+
+    /// Frames that every format has to survive: standard, extended, remote and empty.
+    pub fn frames() -> Vec<RecordedFrame> {
+        let standard = StandardId::new(0x1a0).unwrap();
+        let extended = ExtendedId::new(0x1234_5678).unwrap();
+        vec![
+            RecordedFrame {
+                time: Duration::new(1_700_000_000, 0),
+                frame: CanFrame::new(standard, &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap(),
+            },
+            RecordedFrame {
+                time: Duration::new(1_700_000_000, 12_345_000),
+                frame: CanFrame::new(extended, &[0xde, 0xad]).unwrap(),
+            },
+            RecordedFrame {
+                time: Duration::new(1_700_000_001, 500_000_000),
+                frame: CanFrame::new_remote(standard, 2).unwrap(),
+            },
+            RecordedFrame {
+                time: Duration::new(1_700_000_002, 0),
+                frame: CanFrame::new(StandardId::new(0x5c0).unwrap(), &[]).unwrap(),
+            },
+        ]
+    }
+
+    /// Checks that `read` gives back `expected`, with times relative to the first frame when the
+    /// format doesn't keep the absolute time.
+    pub fn assert_same_frames(actual: &[RecordedFrame], expected: &[RecordedFrame]) {
+        assert_eq!(actual.len(), expected.len());
+        for (read, written) in actual.iter().zip(expected) {
+            assert_eq!(read.time - actual[0].time, written.time - expected[0].time);
+            assert_eq!(read.frame.raw_id(), written.frame.raw_id());
+            assert_eq!(read.frame.is_extended(), written.frame.is_extended());
+            assert_eq!(
+                read.frame.is_remote_frame(),
+                written.frame.is_remote_frame()
+            );
+            assert_eq!(read.frame.dlc(), written.frame.dlc());
+            if !written.frame.is_remote_frame() {
+                assert_eq!(read.frame.data(), written.frame.data());
+            }
+        }
+    }
+
+    #[test]
+    fn it_converts_calendar_times() {
+        let time = unix_time(2023, 11, 14, 22, 13, 20, 0).unwrap();
+        assert_eq!(time, Duration::from_secs(1_700_000_000));
+        assert_eq!(calendar(time), (2023, 11, 14, 22, 13, 20));
+
+        assert_eq!(unix_time(1970, 1, 1, 0, 0, 0, 0), Some(Duration::ZERO));
+        assert_eq!(
+            calendar(unix_time(2024, 2, 29, 12, 0, 0, 0).unwrap()),
+            (2024, 2, 29, 12, 0, 0)
+        );
+        assert_eq!(unix_time(2024, 13, 1, 0, 0, 0, 0), None);
+    }
+
+    #[test]
+    fn it_knows_formats_by_extension() {
+        assert_eq!(Format::from_path(Path::new("drive.ASC")), Some(Format::Asc));
+        assert_eq!(
+            Format::from_path(Path::new("drive.log")),
+            Some(Format::Candump)
+        );
+        assert_eq!(Format::from_path(Path::new("drive.txt")), None);
+    }
+}
@@ -0,0 +1,290 @@
+//! The ASCII logging format of Vector CANalyzer and CANoe.
+//!
+//! ```text
+//! date Tue Nov 14 10:13:20.000 pm 2023
+//! base hex  timestamps absolute
+//! Begin Triggerblock Tue Nov 14 10:13:20.000 pm 2023
+//!    0.012345 1  1A0             Rx   d 8 01 02 03 04 05 06 07 08
+//!    0.020000 1  12345678x       Rx   d 2 DE AD
+//! End TriggerBlock
+//! ```
+//!
+//! Timestamps are seconds since the date in the header. With `base dec` the ids and data bytes are
+//! decimal. Lines with other events, like error frames and CAN FD frames, are skipped.
+use socketcan::{EmbeddedFrame, Frame};
+use std::io::{BufRead, Write};
+use std::time::Duration;
+
+use super::{build_frame, calendar, parse_seconds, unix_time, RecordedFrame, RecordingError};
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+
+/// Reads every CAN frame in `reader`. Times are since the unix epoch when the date in the header
+/// can be read, otherwise since the start of the measurement.
+pub fn read(reader: impl BufRead) -> Result<Vec<RecordedFrame>, RecordingError> {
+    let mut frames = Vec::new();
+    let mut start = Duration::ZERO;
+    let mut hexadecimal = true;
+    let mut relative = false;
+    let mut previous = Duration::ZERO;
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        let error = |message: String| RecordingError::Parse {
+            line: index + 1,
+            message,
+        };
+
+        if let Some(date) = line.strip_prefix("date ") {
+            start = parse_date(date).unwrap_or_default();
+            continue;
+        }
+        if let Some(base) = line.strip_prefix("base ") {
+            hexadecimal = !base.starts_with("dec");
+            relative = base.contains("timestamps relative");
+            continue;
+        }
+
+        let Some(frame) = parse_frame(line, hexadecimal).map_err(error)? else {
+            continue;
+        };
+        let (offset, frame) = frame;
+        let offset = if relative { previous + offset } else { offset };
+        previous = offset;
+        frames.push(RecordedFrame {
+            time: start + offset,
+            frame,
+        });
+    }
+    Ok(frames)
+}
+
+/// Parses a line with a CAN frame, or returns `None` for other lines.
+fn parse_frame(
+    line: &str,
+    hexadecimal: bool,
+) -> Result<Option<(Duration, socketcan::CanFrame)>, String> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let [time, channel, id, direction, kind, rest @ ..] = tokens.as_slice() else {
+        return Ok(None);
+    };
+    let Some(time) = parse_seconds(time) else {
+        return Ok(None);
+    };
+    let is_frame = channel.parse::<u8>().is_ok()
+        && matches!(*direction, "Rx" | "Tx")
+        && matches!(*kind, "d" | "r");
+    if !is_frame {
+        return Ok(None);
+    }
+
+    let (id, extended) = match id.strip_suffix('x') {
+        Some(id) => (id, true),
+        None => (*id, false),
+    };
+    let radix = if hexadecimal { 16 } else { 10 };
+    let id = u32::from_str_radix(id, radix).map_err(|_| format!("invalid id {}", id))?;
+
+    let remote = *kind == "r";
+    let dlc = match rest.first() {
+        Some(dlc) => usize::from_str_radix(dlc, 16).map_err(|_| format!("invalid dlc {}", dlc))?,
+        None if remote => 0,
+        None => return Err(String::from("missing dlc")),
+    };
+    let data = if remote {
+        Vec::new()
+    } else {
+        rest.iter()
+            .skip(1)
+            .take(dlc)
+            .map(|byte| {
+                u8::from_str_radix(byte, radix).map_err(|_| format!("invalid byte {}", byte))
+            })
+            .collect::<Result<Vec<u8>, String>>()?
+    };
+    if !remote && data.len() != dlc {
+        return Err(format!("expected {} bytes", dlc));
+    }
+
+    let frame = build_frame(id, extended, remote, dlc, &data)
+        .ok_or_else(|| format!("invalid frame {}", line))?;
+    Ok(Some((time, frame)))
+}
+
+/// Parses a date like `Tue Nov 14 10:13:20.000 pm 2023` as UTC. Localised dates aren't supported.
+fn parse_date(text: &str) -> Option<Duration> {
+    let tokens: Vec<&str> = text.split_whitespace().collect();
+    let (month, day, time, meridiem, year) = match tokens.as_slice() {
+        [_, month, day, time, meridiem, year] => (month, day, time, Some(*meridiem), year),
+        [_, month, day, time, year] => (month, day, time, None, year),
+        _ => return None,
+    };
+
+    let month = MONTHS.iter().position(|name| name == month)? as u32 + 1;
+    let mut parts = time.split(':');
+    let mut hour: u32 = parts.next()?.parse().ok()?;
+    let minute = parts.next()?.parse().ok()?;
+    let seconds = parse_seconds(parts.next()?)?;
+    match meridiem {
+        Some("am") if hour == 12 => hour = 0,
+        Some("pm") if hour < 12 => hour += 12,
+        Some("am" | "pm") | None => {}
+        Some(_) => return None,
+    }
+
+    unix_time(
+        year.parse().ok()?,
+        month,
+        day.parse().ok()?,
+        hour,
+        minute,
+        seconds.as_secs() as u32,
+        seconds.subsec_nanos(),
+    )
+}
+
+fn format_date(time: Duration) -> String {
+    let (year, month, day, hour, minute, second) = calendar(time);
+    let weekday = WEEKDAYS[(time.as_secs() / 86_400 % 7) as usize];
+    let (hour, meridiem) = match hour {
+        0 => (12, "am"),
+        1..=11 => (hour, "am"),
+        12 => (12, "pm"),
+        _ => (hour - 12, "pm"),
+    };
+    format!(
+        "{} {} {:02} {:02}:{:02}:{:02}.{:03} {} {}",
+        weekday,
+        MONTHS[month as usize - 1],
+        day,
+        hour,
+        minute,
+        second,
+        time.subsec_millis(),
+        meridiem,
+        year
+    )
+}
+
+/// Writes the frames with timestamps since the first one, which is the date in the header.
+pub fn write(mut writer: impl Write, frames: &[RecordedFrame]) -> std::io::Result<()> {
+    // The date only has milliseconds, so start at the millisecond of the first frame.
+    let first = frames.first().map(|frame| frame.time).unwrap_or_default();
+    let start = Duration::from_millis(first.as_millis() as u64);
+    let date = format_date(start);
+
+    writeln!(writer, "date {}", date)?;
+    writeln!(writer, "base hex  timestamps absolute")?;
+    writeln!(writer, "no internal events logged")?;
+    writeln!(writer, "// version 9.0.0")?;
+    writeln!(writer, "Begin Triggerblock {}", date)?;
+    writeln!(writer, "{:>11.6} Start of measurement", 0.0)?;
+
+    for recorded in frames {
+        let frame = &recorded.frame;
+        let offset = recorded.time.saturating_sub(start);
+        let id = if frame.is_extended() {
+            format!("{:X}x", frame.raw_id())
+        } else {
+            format!("{:X}", frame.raw_id())
+        };
+        let data = if frame.is_remote_frame() {
+            format!("r {:x}", frame.dlc())
+        } else {
+            let bytes: Vec<String> = frame.data().iter().map(|b| format!("{:02X}", b)).collect();
+            format!("d {:x} {}", frame.dlc(), bytes.join(" "))
+        };
+
+        writeln!(
+            writer,
+            "{:>4}.{:06} 1  {:<15} Rx   {}",
+            offset.as_secs(),
+            offset.subsec_micros(),
+            id,
+            data.trim_end()
+        )?;
+    }
+
+    writeln!(writer, "End TriggerBlock")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::tests::{assert_same_frames, frames};
+
+    const LOG: &str = "\
+date Tue Nov 14 10:13:20.000 pm 2023
+base hex  timestamps absolute
+internal events logged
+// version 13.0.0
+Begin Triggerblock Tue Nov 14 10:13:20.000 pm 2023
+   0.000000 Start of measurement
+   0.012345 1  1A0             Rx   d 8 01 02 03 04 05 06 07 08  Length = 0 BitCount = 0 ID = 416
+   0.020000 2  12345678x       Tx   d 2 DE AD
+   0.030000 1  ErrorFrame
+   0.040000 CANFD   1 Rx        1A0                                   0 0 8  8 01 02 03 04 05 06 07 08
+   1.500000 1  280             Rx   r 4
+End TriggerBlock
+";
+
+    #[test]
+    fn it_reads_a_log() {
+        let frames = read(LOG.as_bytes()).unwrap();
+
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].time, Duration::new(1_700_000_000, 12_345_000));
+        assert_eq!(frames[0].frame.data(), [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(frames[1].frame.is_extended());
+        assert_eq!(frames[1].frame.raw_id(), 0x1234_5678);
+        assert!(frames[2].frame.is_remote_frame());
+        assert_eq!(frames[2].frame.dlc(), 4);
+    }
+
+    #[test]
+    fn it_reads_relative_decimal_timestamps() {
+        let log = "base dec  timestamps relative\n\
+                   0.100000 1  416 Rx d 1 01\n\
+                   0.100000 1  416 Rx d 3 10 128 255\n";
+        let frames = read(log.as_bytes()).unwrap();
+
+        assert_eq!(frames[1].time, Duration::from_millis(200));
+        assert_eq!(frames[1].frame.raw_id(), 0x1a0);
+        assert_eq!(frames[1].frame.data(), [10, 128, 255]);
+    }
+
+    #[test]
+    fn it_reports_broken_frames() {
+        let log = "   0.100000 1  1A0 Rx d 8 01 02\n";
+        assert!(matches!(
+            read(log.as_bytes()),
+            Err(RecordingError::Parse { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn it_writes_what_it_reads() {
+        let mut log = Vec::new();
+        write(&mut log, &frames()).unwrap();
+
+        let read = read(log.as_slice()).unwrap();
+        assert_same_frames(&read, &frames());
+        assert_eq!(read[0].time, frames()[0].time);
+    }
+
+    #[test]
+    fn it_formats_dates() {
+        let time = Duration::new(1_700_000_000, 0);
+        assert_eq!(format_date(time), "Tue Nov 14 10:13:20.000 pm 2023");
+        assert_eq!(parse_date(&format_date(time)), Some(time));
+        assert_eq!(
+            parse_date("Tue Nov 14 00:13:20.5 2023"),
+            Some(Duration::new(1_699_920_800, 500_000_000))
+        );
+    }
+}
@@ -0,0 +1,345 @@
+//! The binary logging format of Vector CANalyzer and CANoe.
+//!
+//! A file starts with a header that has the start time, followed by objects. Most objects are
+//! containers with zlib compressed objects in them, which can be split over several containers.
+//! Only CAN messages are read, other objects are skipped.
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use socketcan::{EmbeddedFrame, Frame};
+use std::io::{BufRead, Read, Write};
+use std::time::Duration;
+
+use super::{build_frame, calendar, unix_time, RecordedFrame, RecordingError};
+
+const FILE_SIGNATURE: &[u8; 4] = b"LOGG";
+const OBJECT_SIGNATURE: &[u8; 4] = b"LOBJ";
+const FILE_HEADER_SIZE: usize = 144;
+const OBJECT_HEADER_BASE_SIZE: usize = 16;
+const OBJECT_HEADER_V1_SIZE: usize = 16;
+const CONTAINER_HEADER_SIZE: usize = 16;
+const CAN_MESSAGE_SIZE: usize = 16;
+
+const CAN_MESSAGE: u32 = 1;
+const LOG_CONTAINER: u32 = 10;
+const CAN_MESSAGE2: u32 = 86;
+
+const NO_COMPRESSION: u16 = 0;
+const ZLIB_DEFLATE: u16 = 2;
+
+const TIME_TEN_MICROSECONDS: u32 = 1;
+const TIME_ONE_NANOSECOND: u32 = 2;
+
+const REMOTE_FLAG: u8 = 0x80;
+const EXTENDED_FLAG: u32 = 0x8000_0000;
+
+/// Uncompressed data per container, like CANalyzer writes it.
+const CONTAINER_SIZE: usize = 128 * 1024;
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+fn invalid(message: &str) -> RecordingError {
+    RecordingError::Invalid(String::from(message))
+}
+
+/// Reads every CAN message in `reader`, with times since the unix epoch.
+pub fn read(mut reader: impl BufRead) -> Result<Vec<RecordedFrame>, RecordingError> {
+    let mut file = Vec::new();
+    reader.read_to_end(&mut file)?;
+
+    if file.get(..4) != Some(FILE_SIGNATURE) {
+        return Err(invalid("not a BLF file"));
+    }
+    let header_size = u32_at(&file, 4).ok_or_else(|| invalid("truncated file header"))? as usize;
+    let start = read_system_time(&file, 40).ok_or_else(|| invalid("invalid start time"))?;
+
+    let mut frames = Vec::new();
+    // Objects in containers can continue in the next container.
+    let mut unpacked = Vec::new();
+    let mut position = header_size;
+    while let Some(object) = next_object(&file, &mut position)? {
+        if object.kind != LOG_CONTAINER {
+            read_object(&object, start, &mut frames)?;
+            continue;
+        }
+
+        let method = u16_at(object.data, 0).ok_or_else(|| invalid("truncated container"))?;
+        let data = object.data.get(CONTAINER_HEADER_SIZE..).unwrap_or_default();
+        match method {
+            NO_COMPRESSION => unpacked.extend_from_slice(data),
+            ZLIB_DEFLATE => {
+                ZlibDecoder::new(data).read_to_end(&mut unpacked)?;
+            }
+            _ => return Err(invalid("unknown compression method")),
+        }
+
+        let mut inner = 0;
+        while let Some(object) = next_object(&unpacked, &mut inner)? {
+            read_object(&object, start, &mut frames)?;
+        }
+        unpacked.drain(..inner.min(unpacked.len()));
+    }
+    Ok(frames)
+}
+
+struct Object<'a> {
+    kind: u32,
+    header_version: u16,
+    /// Everything after the base header
+    header: &'a [u8],
+    /// Everything after the full header
+    data: &'a [u8],
+}
+
+/// The object at `position`, moving `position` to the next one. Returns `None` when there isn't a
+/// complete object left.
+fn next_object<'a>(
+    data: &'a [u8],
+    position: &mut usize,
+) -> Result<Option<Object<'a>>, RecordingError> {
+    let Some(base) = data.get(*position..*position + OBJECT_HEADER_BASE_SIZE) else {
+        return Ok(None);
+    };
+    if &base[..4] != OBJECT_SIGNATURE {
+        return Err(invalid("missing object signature"));
+    }
+    let header_size = usize::from(u16_at(base, 4).unwrap_or_default());
+    let header_version = u16_at(base, 6).unwrap_or_default();
+    let size = u32_at(base, 8).unwrap_or_default() as usize;
+    let kind = u32_at(base, 12).unwrap_or_default();
+    if size < OBJECT_HEADER_BASE_SIZE || header_size > size {
+        return Err(invalid("invalid object size"));
+    }
+
+    let Some(object) = data.get(*position..*position + size) else {
+        return Ok(None);
+    };
+    *position += size + size % 4;
+
+    Ok(Some(Object {
+        kind,
+        header_version,
+        header: &object[OBJECT_HEADER_BASE_SIZE..header_size.max(OBJECT_HEADER_BASE_SIZE)],
+        data: &object[header_size.max(OBJECT_HEADER_BASE_SIZE)..],
+    }))
+}
+
+fn read_object(
+    object: &Object,
+    start: Duration,
+    frames: &mut Vec<RecordedFrame>,
+) -> Result<(), RecordingError> {
+    if !matches!(object.kind, CAN_MESSAGE | CAN_MESSAGE2) {
+        return Ok(());
+    }
+
+    let flags = u32_at(object.header, 0).ok_or_else(|| invalid("truncated object header"))?;
+    // Version 2 headers have a status byte and a reserved byte before the object version
+    let timestamp = match object.header_version {
+        1 | 2 => u64_at(object.header, 8),
+        _ => None,
+    }
+    .ok_or_else(|| invalid("unknown object header"))?;
+    let time = match flags {
+        TIME_TEN_MICROSECONDS => Duration::from_micros(timestamp * 10),
+        TIME_ONE_NANOSECOND => Duration::from_nanos(timestamp),
+        _ => return Err(invalid("unknown timestamp unit")),
+    };
+
+    let message = object
+        .data
+        .get(..CAN_MESSAGE_SIZE)
+        .ok_or_else(|| invalid("truncated CAN message"))?;
+    let remote = message[2] & REMOTE_FLAG != 0;
+    let dlc = usize::from(message[3]).min(8);
+    let id = u32_at(message, 4).unwrap_or_default();
+
+    let frame = build_frame(
+        id & !EXTENDED_FLAG,
+        id & EXTENDED_FLAG != 0,
+        remote,
+        dlc,
+        &message[8..8 + dlc],
+    )
+    .ok_or_else(|| invalid("invalid CAN message"))?;
+    frames.push(RecordedFrame {
+        time: start + time,
+        frame,
+    });
+    Ok(())
+}
+
+/// Reads a Windows `SYSTEMTIME`, which is UTC in the files written here.
+fn read_system_time(data: &[u8], offset: usize) -> Option<Duration> {
+    let field = |index: usize| u16_at(data, offset + index * 2).map(u32::from);
+    let time = unix_time(
+        i64::from(field(0)?),
+        field(1)?,
+        field(3)?,
+        field(4)?,
+        field(5)?,
+        field(6)?,
+        0,
+    )?;
+    Some(time + Duration::from_millis(u64::from(field(7)?)))
+}
+
+fn write_system_time(buffer: &mut Vec<u8>, time: Duration) {
+    let (year, month, day, hour, minute, second) = calendar(time);
+    let weekday = (time.as_secs() / 86_400 + 4) % 7;
+    for field in [
+        year as u16,
+        month as u16,
+        weekday as u16,
+        day as u16,
+        hour as u16,
+        minute as u16,
+        second as u16,
+        time.subsec_millis() as u16,
+    ] {
+        buffer.extend_from_slice(&field.to_le_bytes());
+    }
+}
+
+fn write_object_base(buffer: &mut Vec<u8>, header_size: usize, size: usize, kind: u32) {
+    buffer.extend_from_slice(OBJECT_SIGNATURE);
+    buffer.extend_from_slice(&(header_size as u16).to_le_bytes());
+    buffer.extend_from_slice(&1_u16.to_le_bytes());
+    buffer.extend_from_slice(&(size as u32).to_le_bytes());
+    buffer.extend_from_slice(&kind.to_le_bytes());
+}
+
+/// Writes the frames as CAN messages in compressed containers, with nanosecond timestamps since the
+/// first frame.
+pub fn write(mut writer: impl Write, frames: &[RecordedFrame]) -> std::io::Result<()> {
+    // The start time only has milliseconds, so start at the millisecond of the first frame.
+    let first = frames.first().map(|frame| frame.time).unwrap_or_default();
+    let start = Duration::from_millis(first.as_millis() as u64);
+    let stop = frames.last().map(|frame| frame.time).unwrap_or_default();
+
+    let mut messages = Vec::new();
+    for recorded in frames {
+        let frame = &recorded.frame;
+        let size = OBJECT_HEADER_BASE_SIZE + OBJECT_HEADER_V1_SIZE + CAN_MESSAGE_SIZE;
+        write_object_base(
+            &mut messages,
+            OBJECT_HEADER_BASE_SIZE + OBJECT_HEADER_V1_SIZE,
+            size,
+            CAN_MESSAGE,
+        );
+        messages.extend_from_slice(&TIME_ONE_NANOSECOND.to_le_bytes());
+        // Client index and object version
+        messages.extend_from_slice(&[0; 4]);
+        let timestamp = recorded.time.saturating_sub(start).as_nanos() as u64;
+        messages.extend_from_slice(&timestamp.to_le_bytes());
+
+        let id = if frame.is_extended() {
+            frame.raw_id() | EXTENDED_FLAG
+        } else {
+            frame.raw_id()
+        };
+        let mut data = [0; 8];
+        if !frame.is_remote_frame() {
+            data[..frame.data().len()].copy_from_slice(frame.data());
+        }
+        // Channel 1
+        messages.extend_from_slice(&1_u16.to_le_bytes());
+        messages.push(if frame.is_remote_frame() {
+            REMOTE_FLAG
+        } else {
+            0
+        });
+        messages.push(frame.dlc() as u8);
+        messages.extend_from_slice(&id.to_le_bytes());
+        messages.extend_from_slice(&data);
+    }
+
+    let mut objects = Vec::new();
+    for chunk in messages.chunks(CONTAINER_SIZE) {
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(chunk)?;
+        let compressed = encoder.finish()?;
+
+        let size = OBJECT_HEADER_BASE_SIZE + CONTAINER_HEADER_SIZE + compressed.len();
+        write_object_base(&mut objects, OBJECT_HEADER_BASE_SIZE, size, LOG_CONTAINER);
+        objects.extend_from_slice(&ZLIB_DEFLATE.to_le_bytes());
+        objects.extend_from_slice(&[0; 6]);
+        objects.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+        objects.extend_from_slice(&[0; 4]);
+        objects.extend_from_slice(&compressed);
+        objects.resize(objects.len() + size % 4, 0);
+    }
+
+    let mut header = Vec::with_capacity(FILE_HEADER_SIZE);
+    header.extend_from_slice(FILE_SIGNATURE);
+    header.extend_from_slice(&(FILE_HEADER_SIZE as u32).to_le_bytes());
+    // Application id and version, and the version of the format
+    header.extend_from_slice(&[0, 0, 0, 0, 4, 1, 0, 0]);
+    let file_size = (FILE_HEADER_SIZE + objects.len()) as u64;
+    header.extend_from_slice(&file_size.to_le_bytes());
+    let uncompressed_size = (FILE_HEADER_SIZE + messages.len()) as u64;
+    header.extend_from_slice(&uncompressed_size.to_le_bytes());
+    header.extend_from_slice(&(frames.len() as u32).to_le_bytes());
+    header.extend_from_slice(&0_u32.to_le_bytes());
+    write_system_time(&mut header, start);
+    write_system_time(&mut header, stop);
+    header.resize(FILE_HEADER_SIZE, 0);
+
+    writer.write_all(&header)?;
+    writer.write_all(&objects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::tests::{assert_same_frames, frames};
+
+    #[test]
+    fn it_writes_what_it_reads() {
+        let mut log = Vec::new();
+        write(&mut log, &frames()).unwrap();
+
+        let read = read(log.as_slice()).unwrap();
+        assert_same_frames(&read, &frames());
+        assert_eq!(read[0].time, frames()[0].time);
+    }
+
+    #[test]
+    fn it_reads_objects_split_over_containers() {
+        let frames: Vec<RecordedFrame> = (0..10_000_u64)
+            .map(|index| RecordedFrame {
+                time: Duration::from_millis(index),
+                ..frames()[0].clone()
+            })
+            .collect();
+        let mut log = Vec::new();
+        write(&mut log, &frames).unwrap();
+
+        // 48 byte messages don't fit a 128 KiB container exactly
+        assert_eq!(CONTAINER_SIZE % 48, 32);
+        assert_same_frames(&read(log.as_slice()).unwrap(), &frames);
+    }
+
+    #[test]
+    fn it_rejects_other_files() {
+        assert!(matches!(
+            read("date Tue Nov 14".as_bytes()),
+            Err(RecordingError::Invalid(_))
+        ));
+    }
+}
@@ -0,0 +1,161 @@
+//! The log format of `candump -l`, which `canplayer` replays: `(1436509052.249713) can0 1A0#0102`.
+use socketcan::{CanFrame, EmbeddedFrame, ExtendedId, Frame, Id, StandardId};
+use std::io::{BufRead, Write};
+
+use super::{parse_seconds, RecordedFrame, RecordingError};
+
+/// Reads every frame in `reader`, skipping empty lines.
+pub fn read(reader: impl BufRead) -> Result<Vec<RecordedFrame>, RecordingError> {
+    let mut frames = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        frames.push(parse_line(&line).map_err(|message| RecordingError::Parse {
+            line: index + 1,
+            message,
+        })?);
+    }
+    Ok(frames)
+}
+
+pub fn parse_line(line: &str) -> Result<RecordedFrame, String> {
+    let mut parts = line.split_whitespace();
+    let time = parts
+        .next()
+        .and_then(|time| time.strip_prefix('('))
+        .and_then(|time| time.strip_suffix(')'))
+        .ok_or("missing timestamp")?;
+    let _interface = parts.next().ok_or("missing interface")?;
+    let frame = parts.next().ok_or("missing frame")?;
+
+    Ok(RecordedFrame {
+        time: parse_seconds(time).ok_or_else(|| format!("invalid timestamp {}", time))?,
+        frame: parse_frame(frame)?,
+    })
+}
+
+fn parse_frame(text: &str) -> Result<CanFrame, String> {
+    let (id, data) = text.split_once('#').ok_or("missing # in frame")?;
+    if data.starts_with('#') {
+        return Err(String::from("CAN FD frames are not supported"));
+    }
+
+    let raw = u32::from_str_radix(id, 16).map_err(|_| format!("invalid id {}", id))?;
+    let id: Id = match id.len() {
+        3 => StandardId::new(raw as u16).map(Id::from),
+        8 => ExtendedId::new(raw).map(Id::from),
+        _ => None,
+    }
+    .ok_or_else(|| format!("invalid id {}", id))?;
+
+    let frame = match data.strip_prefix('R') {
+        // candump leaves out the length of empty remote frames
+        Some("") => CanFrame::new_remote(id, 0),
+        Some(dlc) => {
+            let dlc = dlc.parse().map_err(|_| format!("invalid length {}", dlc))?;
+            CanFrame::new_remote(id, dlc)
+        }
+        None => {
+            let bytes = (0..data.len())
+                .step_by(2)
+                .map(|i| {
+                    data.get(i..i + 2)
+                        .and_then(|b| u8::from_str_radix(b, 16).ok())
+                })
+                .collect::<Option<Vec<u8>>>()
+                .ok_or_else(|| format!("invalid data {}", data))?;
+            CanFrame::new(id, &bytes)
+        }
+    };
+    frame.ok_or_else(|| format!("invalid frame {}", text))
+}
+
+/// Writes a line for every frame, as if it was received on `interface`.
+pub fn write(
+    mut writer: impl Write,
+    frames: &[RecordedFrame],
+    interface: &str,
+) -> std::io::Result<()> {
+    for recorded in frames {
+        let frame = &recorded.frame;
+        let id = if frame.is_extended() {
+            format!("{:08X}", frame.raw_id())
+        } else {
+            format!("{:03X}", frame.raw_id())
+        };
+        let data = if frame.is_remote_frame() {
+            format!("R{}", frame.dlc())
+        } else {
+            frame.data().iter().map(|b| format!("{:02X}", b)).collect()
+        };
+
+        writeln!(
+            writer,
+            "({}.{:06}) {} {}#{}",
+            recorded.time.as_secs(),
+            recorded.time.subsec_micros(),
+            interface,
+            id,
+            data
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const LOG: &str = "\
+(1436509052.249713) vcan0 1A0#0102030405060708
+(1436509052.250001) vcan0 12345678#DEAD
+
+(1436509052.3) vcan0 280#R
+(1436509052.400000) vcan0 5C0#
+";
+
+    #[test]
+    fn it_reads_a_log() {
+        let frames = read(LOG.as_bytes()).unwrap();
+
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0].time, Duration::new(1436509052, 249_713_000));
+        assert_eq!(frames[0].frame.raw_id(), 0x1a0);
+        assert_eq!(frames[0].frame.data(), [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(frames[1].frame.is_extended());
+        assert_eq!(frames[1].frame.raw_id(), 0x1234_5678);
+        assert_eq!(frames[2].time, Duration::new(1436509052, 300_000_000));
+        assert!(frames[2].frame.is_remote_frame());
+        assert!(frames[3].frame.data().is_empty());
+    }
+
+    #[test]
+    fn it_writes_what_it_reads() {
+        let frames = read(LOG.as_bytes()).unwrap();
+
+        let mut log = Vec::new();
+        write(&mut log, &frames, "vcan0").unwrap();
+        assert_eq!(
+            String::from_utf8(log).unwrap(),
+            "(1436509052.249713) vcan0 1A0#0102030405060708\n\
+             (1436509052.250001) vcan0 12345678#DEAD\n\
+             (1436509052.300000) vcan0 280#R0\n\
+             (1436509052.400000) vcan0 5C0#\n"
+        );
+    }
+
+    #[test]
+    fn it_reports_the_line_of_an_error() {
+        let error = read("(1.0) vcan0 1A0#01\n(2.0) vcan0 1A0#0\n".as_bytes()).unwrap_err();
+        assert!(matches!(error, RecordingError::Parse { line: 2, .. }));
+
+        assert!(parse_line("(1.0) vcan0 1A0##1").is_err());
+        assert!(parse_line("(1.0) vcan0 1A0#Rx").is_err());
+        assert!(parse_line("(1.0) vcan0 1A0#R9").is_err());
+        assert!(parse_line("(1.0) vcan0 1A0#R").is_ok());
+        assert!(parse_line("1.0 vcan0 1A0#01").is_err());
+    }
+}
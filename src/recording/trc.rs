@@ -0,0 +1,338 @@
+//! The trace format of PEAK's PCAN-View and PCAN-Basic.
+//!
+//! Version 1.x has a fixed layout, where 1.2 adds the bus and 1.3 a reserved column after the id.
+//! Version 2.x lists its columns in the header:
+//!
+//! ```text
+//! ;$FILEVERSION=2.1
+//! ;$STARTTIME=45244.9259259259
+//! ;$COLUMNS=N,O,T,I,d,l,D
+//!       1         0.000 DT     01A0 Rx 8  01 02 03 04 05 06 07 08
+//!       2        12.345 DT 12345678 Rx 2  DE AD
+//! ```
+//!
+//! The offsets are milliseconds since the start time, which is in days since 1899-12-30. PEAK
+//! writes the local time there, which is read as UTC.
+use socketcan::{EmbeddedFrame, Frame};
+use std::io::{BufRead, Write};
+use std::time::Duration;
+
+use super::{build_frame, parse_seconds, RecordedFrame, RecordingError};
+
+/// Days between 1899-12-30, where the start time counts from, and the unix epoch.
+const UNIX_EPOCH_DAYS: f64 = 25_569.0;
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+/// The columns of version 2.0, which doesn't list them.
+const VERSION_2_0_COLUMNS: &str = "N,O,T,I,d,l,D";
+
+pub fn read(reader: impl BufRead) -> Result<Vec<RecordedFrame>, RecordingError> {
+    let mut frames = Vec::new();
+    let mut start = Duration::ZERO;
+    let mut columns: Option<Vec<char>> = None;
+    let mut minor_version = 1;
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        let error = |message: String| RecordingError::Parse {
+            line: index + 1,
+            message,
+        };
+
+        if let Some(header) = line.strip_prefix(';') {
+            if let Some(version) = header.strip_prefix("$FILEVERSION=") {
+                let (major, minor) = version.trim().split_once('.').unwrap_or((version, "0"));
+                match major {
+                    "1" => {
+                        minor_version = minor
+                            .parse()
+                            .map_err(|_| error(format!("invalid version {}", version)))?;
+                    }
+                    "2" => {
+                        columns.get_or_insert_with(|| {
+                            VERSION_2_0_COLUMNS.split(',').map(column).collect()
+                        });
+                    }
+                    _ => return Err(error(format!("unsupported version {}", version))),
+                }
+            } else if let Some(time) = header.strip_prefix("$STARTTIME=") {
+                let days: f64 = time
+                    .parse()
+                    .map_err(|_| error(format!("invalid start time {}", time)))?;
+                // Days only have about 10 µs of precision, so round to the millisecond it was
+                // written with.
+                let milliseconds = (days - UNIX_EPOCH_DAYS) * SECONDS_PER_DAY * 1000.0;
+                start = Duration::from_millis(milliseconds.round().max(0.0) as u64);
+            } else if let Some(names) = header.strip_prefix("$COLUMNS=") {
+                columns = Some(names.split(',').map(column).collect());
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        let frame = match &columns {
+            Some(columns) => parse_version_2(line, columns),
+            None => parse_version_1(line, minor_version),
+        }
+        .map_err(error)?;
+        if let Some((offset, frame)) = frame {
+            frames.push(RecordedFrame {
+                time: start + offset,
+                frame,
+            });
+        }
+    }
+    Ok(frames)
+}
+
+fn column(name: &str) -> char {
+    name.trim().chars().next().unwrap_or(' ')
+}
+
+/// Parses a frame like `1)  1841.0  Rx  0001  8  00 00`, where 1.0 leaves out the direction, 1.2
+/// adds the bus before the direction and 1.3 a reserved column before the length, as in
+/// `1)  1841.0 1  Rx  0001 -  8  00 00`.
+fn parse_version_1(
+    line: &str,
+    minor_version: u32,
+) -> Result<Option<(Duration, socketcan::CanFrame)>, String> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let (offset, rest) = match tokens.as_slice() {
+        [number, offset, rest @ ..] if number.ends_with(')') => (*offset, rest),
+        _ => return Err(String::from("expected a message number")),
+    };
+    let rest = match rest {
+        [_bus, rest @ ..] if minor_version >= 2 => rest,
+        rest => rest,
+    };
+    let rest = match rest {
+        [direction, rest @ ..] if matches!(*direction, "Rx" | "Tx") => rest,
+        // Errors and warnings
+        [kind, ..] if !kind.chars().all(|c| c.is_ascii_hexdigit()) => return Ok(None),
+        rest => rest,
+    };
+    let (id, rest) = rest
+        .split_first()
+        .ok_or_else(|| String::from("expected an id and length"))?;
+    let rest = match rest {
+        [_reserved, rest @ ..] if minor_version >= 3 => rest,
+        rest => rest,
+    };
+    let [dlc, data @ ..] = rest else {
+        return Err(String::from("expected an id and length"));
+    };
+
+    let remote = data.first() == Some(&"RTR");
+    let frame = frame(id, dlc, if remote { &[] } else { data }, remote)?;
+    Ok(Some((parse_milliseconds(offset)?, frame)))
+}
+
+/// Parses a frame with the columns from the header.
+fn parse_version_2(
+    line: &str,
+    columns: &[char],
+) -> Result<Option<(Duration, socketcan::CanFrame)>, String> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let field = |name: char| {
+        columns
+            .iter()
+            .position(|column| *column == name)
+            .and_then(|index| tokens.get(index).copied())
+    };
+
+    let remote = match field('T') {
+        Some("DT") => false,
+        Some("RR") => true,
+        // Errors, status and CAN FD frames
+        Some(_) => return Ok(None),
+        None => return Err(String::from("missing type")),
+    };
+    let offset = field('O').ok_or("missing offset")?;
+    let id = field('I').ok_or("missing id")?;
+    let dlc = field('l').or_else(|| field('L')).ok_or("missing length")?;
+    let data = match columns.iter().position(|column| *column == 'D') {
+        Some(index) if !remote => tokens.get(index..).unwrap_or_default(),
+        _ => &[],
+    };
+
+    let frame = frame(id, dlc, data, remote)?;
+    Ok(Some((parse_milliseconds(offset)?, frame)))
+}
+
+fn frame(id: &str, dlc: &str, data: &[&str], remote: bool) -> Result<socketcan::CanFrame, String> {
+    let raw = u32::from_str_radix(id, 16).map_err(|_| format!("invalid id {}", id))?;
+    let dlc: usize = dlc.parse().map_err(|_| format!("invalid length {}", dlc))?;
+    let data = data
+        .iter()
+        .take(dlc)
+        .map(|byte| u8::from_str_radix(byte, 16).map_err(|_| format!("invalid byte {}", byte)))
+        .collect::<Result<Vec<u8>, String>>()?;
+    if !remote && data.len() != dlc {
+        return Err(format!("expected {} bytes", dlc));
+    }
+
+    // Extended ids are written with 8 digits, standard ids with 4 or less
+    build_frame(raw, id.len() > 4, remote, dlc, &data)
+        .ok_or_else(|| format!("invalid frame {}", id))
+}
+
+fn parse_milliseconds(text: &str) -> Result<Duration, String> {
+    let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+    let milliseconds: u64 = whole
+        .parse()
+        .map_err(|_| format!("invalid offset {}", text))?;
+    let fraction = parse_seconds(&format!("0.{}", fraction))
+        .ok_or_else(|| format!("invalid offset {}", text))?;
+    Ok(Duration::from_millis(milliseconds) + fraction / 1000)
+}
+
+/// Writes version 2.1, with offsets since the millisecond of the first frame.
+pub fn write(mut writer: impl Write, frames: &[RecordedFrame]) -> std::io::Result<()> {
+    let first = frames.first().map(|frame| frame.time).unwrap_or_default();
+    let start = Duration::from_millis(first.as_millis() as u64);
+    let days = start.as_secs_f64() / SECONDS_PER_DAY + UNIX_EPOCH_DAYS;
+
+    writeln!(writer, ";$FILEVERSION=2.1")?;
+    writeln!(writer, ";$STARTTIME={:.10}", days)?;
+    writeln!(writer, ";$COLUMNS={}", VERSION_2_0_COLUMNS)?;
+    writeln!(writer, ";   Generated by {}", env!("CARGO_PKG_NAME"))?;
+    writeln!(
+        writer,
+        ";---+-- ------+------ +- --+----- +- +- +- -- -- -- -- -- -- --"
+    )?;
+
+    for (index, recorded) in frames.iter().enumerate() {
+        let frame = &recorded.frame;
+        let offset = recorded.time.saturating_sub(start);
+        let id = if frame.is_extended() {
+            format!("{:08X}", frame.raw_id())
+        } else {
+            format!("{:04X}", frame.raw_id())
+        };
+        let (kind, data) = if frame.is_remote_frame() {
+            ("RR", String::new())
+        } else {
+            let bytes: Vec<String> = frame.data().iter().map(|b| format!("{:02X}", b)).collect();
+            ("DT", bytes.join(" "))
+        };
+
+        writeln!(
+            writer,
+            "{:>7} {:>9}.{:03} {} {:>8} Rx {:<2} {}",
+            index + 1,
+            offset.as_millis(),
+            offset.subsec_micros() % 1000,
+            kind,
+            id,
+            frame.dlc(),
+            data
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::tests::{assert_same_frames, frames};
+
+    #[test]
+    fn it_reads_version_1_1() {
+        let log = ";$FILEVERSION=1.1
+;$STARTTIME=45244.9259259259
+;---+--   ----+----  --+--  ----+---  +  -+ -- -- -- -- -- -- --
+     1)      1841.5  Rx         01A0  8  01 02 03 04 05 06 07 08
+     2)      1842.0  Error      0000  4  00 00 08 00
+     3)      1850.0  Tx     12345678  2  DE AD
+     4)      1900.0  Rx         0280  4  RTR
+";
+        let frames = read(log.as_bytes()).unwrap();
+
+        assert_eq!(frames.len(), 3);
+        assert_eq!(
+            frames[1].time - frames[0].time,
+            Duration::from_micros(8_500)
+        );
+        assert_eq!(frames[0].frame.data(), [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(frames[1].frame.is_extended());
+        assert!(frames[2].frame.is_remote_frame());
+        // 45244.9259259259 days after 1899-12-30 is 2023-11-14 22:13:20
+        assert_eq!(frames[0].time, Duration::new(1_700_000_001, 841_500_000));
+    }
+
+    #[test]
+    fn it_reads_the_bus_of_version_1_2() {
+        let log = ";$FILEVERSION=1.2
+;$STARTTIME=45244.9259259259
+;---+-- ------+------  +- --+-- ----+--- +  -+ -- -- -- -- -- -- --
+     1)      1841.0 1  Rx        01A0  8    01 02 03 04 05 06 07 08
+     2)      1842.5 2  Error     0000  4    00 00 08 00
+     3)      1850.0 1  Tx    12345678  2    DE AD
+";
+        let frames = read(log.as_bytes()).unwrap();
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].frame.raw_id(), 0x1a0);
+        assert_eq!(frames[0].frame.data(), [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(frames[1].frame.raw_id(), 0x1234_5678);
+        assert_eq!(frames[1].frame.data(), [0xde, 0xad]);
+        assert_eq!(frames[1].time - frames[0].time, Duration::from_millis(9));
+    }
+
+    #[test]
+    fn it_reads_the_reserved_column_of_version_1_3() {
+        let log = ";$FILEVERSION=1.3
+;$STARTTIME=45244.9259259259
+;---+-- ------+------  +- --+-- ----+--- +- -+-- -+ -- -- -- -- -- -- --
+     1)      1841.0 1  Rx        0001 -  8    00 00 00 00 00 00 00 00
+     2)      1841.1 1  Rx        01A0 -  2    01 02
+     3)      1900.0 1  Rx        0280 -  4    RTR
+";
+        let frames = read(log.as_bytes()).unwrap();
+
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].frame.raw_id(), 0x001);
+        assert_eq!(frames[0].frame.data(), [0; 8]);
+        assert_eq!(frames[1].frame.data(), [1, 2]);
+        assert_eq!(frames[1].time - frames[0].time, Duration::from_micros(100));
+        assert!(frames[2].frame.is_remote_frame());
+        assert_eq!(frames[2].frame.dlc(), 4);
+    }
+
+    #[test]
+    fn it_reads_version_1_0() {
+        let frames = read("   1)      1841  01A0  2  01 02\n".as_bytes()).unwrap();
+        assert_eq!(frames[0].time, Duration::from_millis(1841));
+        assert_eq!(frames[0].frame.data(), [1, 2]);
+    }
+
+    #[test]
+    fn it_reads_the_columns_of_version_2() {
+        let log = ";$FILEVERSION=2.1
+;$COLUMNS=N,O,T,B,I,d,R,L,D
+      1      1059.900 DT 1     01A0 Rx - 2    01 02
+      2      1060.000 ST 1 Rx 00000004
+      3      1060.100 DT 1 12345678 Tx - 1    FF
+";
+        let frames = read(log.as_bytes()).unwrap();
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].time, Duration::from_micros(1_059_900));
+        assert_eq!(frames[1].frame.raw_id(), 0x1234_5678);
+        assert_eq!(frames[1].frame.data(), [0xff]);
+    }
+
+    #[test]
+    fn it_writes_what_it_reads() {
+        let mut log = Vec::new();
+        write(&mut log, &frames()).unwrap();
+
+        let read = read(log.as_slice()).unwrap();
+        assert_same_frames(&read, &frames());
+        assert_eq!(read[0].time, frames()[0].time);
+    }
+}
@@ -0,0 +1,283 @@
+//! Scenarios: changes to the state at fixed times, like taking a message off the bus for a while to
+//! see whether the cluster notices the timeout.
+//!
+//! A scenario is a TOML file with steps. Every step patches the state, the same way the api does,
+//! at a time in milliseconds since the scenario started. Faults are keyed by the id in hex:
+//!
+//! ```toml
+//! [[steps]]
+//! at_ms = 0
+//! patch = { key_position = "On", engine_speed = 850 }
+//!
+//! [[steps]]
+//! at_ms = 5000
+//! patch = { faults = { "3E0" = { dropout = true } } }
+//!
+//! [[steps]]
+//! at_ms = 15000
+//! patch = { faults = { "3E0" = { dropout = false } } }
+//! ```
+//!
+//! The steps are checked against the state when the scenario is loaded, so a typo doesn't show up
+//! halfway through a run. TOML has no null, so to switch off the `period_ms` or `dlc` of a fault,
+//! reset it: `faults = { "3E0" = { reset = true } }` clears the whole fault before the other fields
+//! in the step apply.
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::Path;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Instant};
+
+use crate::miu_state::{self, MiuState, PatchError};
+
+#[derive(Debug, Error)]
+pub enum ScenarioError {
+    #[error("unable to read scenario")]
+    IO(#[from] std::io::Error),
+    #[error("unable to parse scenario: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("step at {at_ms} ms: {error}")]
+    Step { at_ms: u64, error: PatchError },
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Step {
+    /// Time since the start of the scenario
+    pub at_ms: u64,
+
+    /// The fields of the state to change
+    pub patch: Value,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct Scenario {
+    /// The steps, in the order they happen
+    #[serde(default)]
+    pub steps: Vec<Step>,
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> Result<Self, ScenarioError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ScenarioError> {
+        let mut scenario: Self = toml::from_str(text)?;
+        // Steps at the same time keep the order of the file
+        scenario.steps.sort_by_key(|step| step.at_ms);
+
+        let mut state = MiuState::default();
+        for step in &scenario.steps {
+            state
+                .patch(step.patch.clone())
+                .map_err(|error| ScenarioError::Step {
+                    at_ms: step.at_ms,
+                    error,
+                })?;
+        }
+
+        Ok(scenario)
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.steps.last().map_or(0, |step| step.at_ms))
+    }
+
+    /// Runs the steps on the tokio runtime this is called from.
+    pub fn start(self, miu_state: watch::Sender<MiuState>) -> Running {
+        let duration = self.duration();
+        let (status_sender, status) = watch::channel(Status::Running { steps_done: 0 });
+        let task = tokio::spawn(async move {
+            let status = run(self.steps, &miu_state, &status_sender).await;
+            tracing::info!("scenario ended: {:?}", status);
+            status_sender.send_replace(status);
+        });
+
+        Running {
+            task,
+            status,
+            stopped: false,
+            started: std::time::Instant::now(),
+            duration,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Status {
+    Running { steps_done: usize },
+    Finished,
+    Stopped,
+    Failed(String),
+}
+
+/// A scenario that is running. It stops when this is dropped.
+pub struct Running {
+    task: JoinHandle<()>,
+    status: watch::Receiver<Status>,
+    stopped: bool,
+    started: std::time::Instant,
+    duration: Duration,
+}
+
+impl Running {
+    pub fn status(&self) -> Status {
+        if self.stopped {
+            return Status::Stopped;
+        }
+        self.status.borrow().clone()
+    }
+
+    /// The time since the start, at most the time of the last step.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed().min(self.duration)
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Stops the scenario, the state keeps the changes of the steps that were done.
+    pub fn stop(&mut self) {
+        if matches!(self.status(), Status::Running { .. }) {
+            self.task.abort();
+            self.stopped = true;
+            tracing::info!("scenario stopped");
+        }
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn run(
+    steps: Vec<Step>,
+    miu_state: &watch::Sender<MiuState>,
+    status: &watch::Sender<Status>,
+) -> Status {
+    let started = Instant::now();
+    for (index, step) in steps.into_iter().enumerate() {
+        time::sleep_until(started + Duration::from_millis(step.at_ms)).await;
+        tracing::debug!("scenario step at {} ms: {}", step.at_ms, step.patch);
+
+        if let Err(error) = miu_state::patch(miu_state, step.patch) {
+            tracing::warn!("scenario step at {} ms failed: {}", step.at_ms, error);
+            return Status::Failed(error.to_string());
+        }
+        status.send_replace(Status::Running {
+            steps_done: index + 1,
+        });
+    }
+
+    Status::Finished
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::can::faults::MessageFault;
+    use crate::can::{tcm, Message};
+    use crate::miu_state::KeyPosition;
+
+    const FAULT_SCENARIO: &str = r#"
+        [[steps]]
+        at_ms = 40
+        patch = { faults = { "3E0" = { dropout = false, period_ms = 300 } } }
+
+        [[steps]]
+        at_ms = 0
+        patch = { key_position = "On", faults = { "3E0" = { dropout = true } } }
+
+        [[steps]]
+        at_ms = 60
+        patch = { faults = { "3E0" = { reset = true } } }
+    "#;
+
+    #[test]
+    fn it_orders_the_steps() {
+        let scenario = Scenario::parse(FAULT_SCENARIO).unwrap();
+
+        let times: Vec<u64> = scenario.steps.iter().map(|step| step.at_ms).collect();
+        assert_eq!(times, [0, 40, 60]);
+        assert_eq!(scenario.duration(), Duration::from_millis(60));
+    }
+
+    #[test]
+    fn it_parses_the_example() {
+        let scenario = Scenario::parse(include_str!("../scenarios/tcm_timeout.toml")).unwrap();
+        assert_eq!(scenario.duration(), Duration::from_secs(25));
+    }
+
+    #[test]
+    fn it_rejects_steps_that_dont_fit_the_state() {
+        let result = Scenario::parse(
+            r#"
+            [[steps]]
+            at_ms = 100
+            patch = { warp_drive = true }
+            "#,
+        );
+        assert!(matches!(
+            result,
+            Err(ScenarioError::Step { at_ms: 100, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_patches_the_state_and_faults() {
+        let miu_state = watch::Sender::new(MiuState::default());
+        let mut receiver = miu_state.subscribe();
+        let running = Scenario::parse(FAULT_SCENARIO)
+            .unwrap()
+            .start(miu_state.clone());
+
+        let dropout = |state: &MiuState| state.faults.get(tcm::TransmissionStatus::CAN_ID).dropout;
+        time::timeout(Duration::from_secs(5), receiver.wait_for(dropout))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(miu_state.borrow().key_position, KeyPosition::On);
+
+        let mut status = running.status.clone();
+        time::timeout(
+            Duration::from_secs(5),
+            status.wait_for(|status| *status == Status::Finished),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        // The reset cleared the period that was set before
+        assert_eq!(
+            miu_state
+                .borrow()
+                .faults
+                .get(tcm::TransmissionStatus::CAN_ID),
+            MessageFault::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn it_stops() {
+        let miu_state = watch::Sender::new(MiuState::default());
+        let mut running = Scenario::parse(
+            r#"
+            [[steps]]
+            at_ms = 60000
+            patch = { cruise = true }
+            "#,
+        )
+        .unwrap()
+        .start(miu_state.clone());
+
+        assert_eq!(running.status(), Status::Running { steps_done: 0 });
+        running.stop();
+        assert_eq!(running.status(), Status::Stopped);
+        assert!(!miu_state.borrow().cruise);
+    }
+}
@@ -0,0 +1,497 @@
+//! Scripts for situations that need logic, written in [Rhai](https://rhai.rs).
+//!
+//! The script runs once when it is loaded. It can register callbacks, and keeps running as long as
+//! it has any. These functions are available:
+//!
+//! - `get(field)` returns a field of the state, `set(field, value)` changes it
+//! - `send(id, [bytes])` sends a raw frame
+//! - `on_frame(id, |frame| ...)` calls the function for every received frame with `id`. The frame
+//!   is a map with the `id`, the `data` and the decoded `fields` for known messages.
+//! - `every(ms, || ...)` calls the function every `ms` milliseconds, `after(ms, || ...)` once
+//!
+//! For example, to blink the check engine light at 2 Hz above 6000 rpm:
+//!
+//! ```text
+//! every(250, || {
+//!     set("check_engine", get("engine_speed") > 6000 && !get("check_engine"));
+//! });
+//! ```
+use rhai::{Array, Dynamic, Engine, EvalAltResult, FnPtr, Map, Scope, AST, INT};
+use socketcan::{EmbeddedFrame, Frame};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::{broadcast, watch, Notify};
+
+use crate::can::{layout, raw, CanClient};
+use crate::miu_state::{self, MiuState};
+
+#[derive(Debug, Error)]
+pub enum ScriptError {
+    #[error("unable to read script")]
+    IO(#[from] std::io::Error),
+    #[error("{0}")]
+    Parse(#[from] rhai::ParseError),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Status {
+    Running,
+    /// The script ran and has no callbacks left
+    Finished,
+    Stopped,
+    Failed(String),
+}
+
+struct Timer {
+    next: Instant,
+    period: Option<Duration>,
+    callback: FnPtr,
+}
+
+/// The callbacks registered by the script.
+#[derive(Default)]
+struct Callbacks {
+    frames: Vec<(u32, FnPtr)>,
+    timers: Vec<Timer>,
+}
+
+impl Callbacks {
+    fn is_empty(&self) -> bool {
+        self.frames.is_empty() && self.timers.is_empty()
+    }
+
+    /// Takes the timers that are due at `now` and schedules the next time they are due.
+    fn due(&mut self, now: Instant) -> Vec<FnPtr> {
+        let mut due = Vec::new();
+
+        self.timers.retain_mut(|timer| {
+            if timer.next > now {
+                return true;
+            }
+
+            due.push(timer.callback.clone());
+            match timer.period {
+                Some(period) => {
+                    // Skip the moments that were missed instead of catching up
+                    timer.next = (timer.next + period).max(now);
+                    true
+                }
+                None => false,
+            }
+        });
+
+        due
+    }
+}
+
+/// A running script.
+pub struct Script {
+    stop: Arc<AtomicBool>,
+    wake: Arc<Notify>,
+    status: watch::Receiver<Status>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Script {
+    pub fn load(
+        path: &Path,
+        can: CanClient,
+        miu_state: watch::Sender<MiuState>,
+    ) -> Result<Self, ScriptError> {
+        let source = std::fs::read_to_string(path)?;
+        Self::start(&source, can, miu_state)
+    }
+
+    /// Compiles the script and runs it on its own thread.
+    pub fn start(
+        source: &str,
+        can: CanClient,
+        miu_state: watch::Sender<MiuState>,
+    ) -> Result<Self, ScriptError> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let wake = Arc::new(Notify::new());
+        let callbacks = Arc::new(Mutex::new(Callbacks::default()));
+        let (status_sender, status) = watch::channel(Status::Running);
+
+        let engine = engine(&callbacks, &can, &miu_state, &stop);
+        let ast = engine.compile(source)?;
+
+        // Subscribe before the script runs, so no frames are missed.
+        let frames = can.received_frames();
+        let thread = std::thread::spawn({
+            let stop = stop.clone();
+            let wake = wake.clone();
+            move || {
+                let status = run(engine, ast, callbacks, frames, &stop, &wake);
+                tracing::info!("script ended: {:?}", status);
+                status_sender.send_replace(status);
+            }
+        });
+
+        Ok(Self {
+            stop,
+            wake,
+            status,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn status(&self) -> Status {
+        self.status.borrow().clone()
+    }
+
+    /// Stops the script and waits for it to end.
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.wake.notify_one();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Script {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn engine(
+    callbacks: &Arc<Mutex<Callbacks>>,
+    can: &CanClient,
+    miu_state: &watch::Sender<MiuState>,
+    stop: &Arc<AtomicBool>,
+) -> Engine {
+    let mut engine = Engine::new();
+
+    engine.on_print(|text| tracing::info!("script: {}", text));
+    engine.on_debug(|text, _, position| tracing::debug!("script {}: {}", position, text));
+
+    // Long running scripts are aborted when the script is stopped.
+    let stop = stop.clone();
+    engine.on_progress(move |_| stop.load(Ordering::Relaxed).then_some(Dynamic::UNIT));
+
+    let state = miu_state.clone();
+    engine.register_fn(
+        "get",
+        move |field: &str| -> Result<Dynamic, Box<EvalAltResult>> {
+            let value =
+                serde_json::to_value(*state.borrow()).expect("the state can always be serialized");
+            let field = value
+                .get(field)
+                .ok_or_else(|| format!("unknown field {}", field))?;
+            rhai::serde::to_dynamic(field)
+        },
+    );
+
+    let state = miu_state.clone();
+    engine.register_fn(
+        "set",
+        move |field: &str, value: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            let value: serde_json::Value = rhai::serde::from_dynamic(&value)?;
+            miu_state::patch(&state, serde_json::json!({ field: value }))
+                .map_err(|error| error.to_string().into())
+        },
+    );
+
+    let can = can.clone();
+    engine.register_fn(
+        "send",
+        move |id: INT, data: Array| -> Result<(), Box<EvalAltResult>> {
+            let data = data
+                .into_iter()
+                .map(|byte| byte.as_int().ok().and_then(|byte| u8::try_from(byte).ok()))
+                .collect::<Option<Vec<u8>>>()
+                .ok_or("data has to be an array of bytes")?;
+            let frame = u32::try_from(id)
+                .ok()
+                .and_then(|id| raw::frame(id, &data))
+                .ok_or("the id has to fit 11 bits and data 8 bytes")?;
+
+            can.send_frame(frame)
+                .map_err(|_| "the can task stopped".into())
+        },
+    );
+
+    let registered = callbacks.clone();
+    engine.register_fn(
+        "on_frame",
+        move |id: INT, callback: FnPtr| -> Result<(), Box<EvalAltResult>> {
+            let id = u32::try_from(id).map_err(|_| "invalid id")?;
+            registered.lock().unwrap().frames.push((id, callback));
+            Ok(())
+        },
+    );
+
+    let registered = callbacks.clone();
+    engine.register_fn(
+        "every",
+        move |ms: INT, callback: FnPtr| -> Result<(), Box<EvalAltResult>> {
+            let period = duration(ms)?;
+            registered.lock().unwrap().timers.push(Timer {
+                next: Instant::now() + period,
+                period: Some(period),
+                callback,
+            });
+            Ok(())
+        },
+    );
+
+    let registered = callbacks.clone();
+    engine.register_fn(
+        "after",
+        move |ms: INT, callback: FnPtr| -> Result<(), Box<EvalAltResult>> {
+            registered.lock().unwrap().timers.push(Timer {
+                next: Instant::now() + duration(ms)?,
+                period: None,
+                callback,
+            });
+            Ok(())
+        },
+    );
+
+    engine
+}
+
+fn duration(ms: INT) -> Result<Duration, Box<EvalAltResult>> {
+    u64::try_from(ms)
+        .ok()
+        .filter(|ms| *ms > 0)
+        .map(Duration::from_millis)
+        .ok_or_else(|| "the time has to be a positive number of milliseconds".into())
+}
+
+fn frame_map(frame: &socketcan::CanFrame) -> Map {
+    let mut map = Map::new();
+    map.insert("id".into(), Dynamic::from_int(INT::from(frame.raw_id())));
+    map.insert(
+        "data".into(),
+        frame
+            .data()
+            .iter()
+            .map(|byte| Dynamic::from_int(INT::from(*byte)))
+            .collect::<Array>()
+            .into(),
+    );
+
+    if let Some((_, fields)) = layout::find(frame.raw_id()) {
+        let fields: Map = fields
+            .iter()
+            .map(|field| {
+                // Fields are at most 16 bits wide, so they always fit
+                let value = INT::try_from(field.value(frame.data())).unwrap_or(INT::MAX);
+                (field.name.into(), Dynamic::from_int(value))
+            })
+            .collect();
+        map.insert("fields".into(), fields.into());
+    }
+
+    map
+}
+
+/// Runs the script and then its callbacks until it is stopped or has no callbacks left.
+fn run(
+    engine: Engine,
+    ast: AST,
+    callbacks: Arc<Mutex<Callbacks>>,
+    mut frames: broadcast::Receiver<crate::can::ReceivedFrame>,
+    stop: &AtomicBool,
+    wake: &Notify,
+) -> Status {
+    // The runtime is only used to wait for frames and timers. The callbacks run outside of it,
+    // because the can client blocks.
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .expect("Unable to create tokio runtime for script");
+
+    let mut scope = Scope::new();
+    if let Err(error) = engine.run_ast_with_scope(&mut scope, &ast) {
+        return failed(stop, &error);
+    }
+
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            return Status::Stopped;
+        }
+
+        let next_timer = {
+            let callbacks = callbacks.lock().unwrap();
+            if callbacks.is_empty() {
+                return Status::Finished;
+            }
+            callbacks.timers.iter().map(|timer| timer.next).min()
+        };
+
+        let received = runtime.block_on(async {
+            let timer = async {
+                match next_timer {
+                    Some(next) => tokio::time::sleep_until(next.into()).await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                result = frames.recv() => Some(result),
+                _ = timer => None,
+                _ = wake.notified() => None,
+            }
+        });
+
+        let mut due = Vec::new();
+        match received {
+            Some(Ok(received)) => {
+                let id = received.frame.raw_id();
+                let map = frame_map(&received.frame);
+                due.extend(
+                    callbacks
+                        .lock()
+                        .unwrap()
+                        .frames
+                        .iter()
+                        .filter(|(subscribed, _)| *subscribed == id)
+                        .map(|(_, callback)| (callback.clone(), Some(map.clone()))),
+                );
+            }
+            Some(Err(broadcast::error::RecvError::Lagged(count))) => {
+                tracing::warn!("script missed {} frames", count);
+            }
+            Some(Err(broadcast::error::RecvError::Closed)) => return Status::Stopped,
+            None => {}
+        }
+
+        let timers = callbacks.lock().unwrap().due(Instant::now());
+        due.extend(timers.into_iter().map(|callback| (callback, None)));
+
+        // The lock is released before calling, so callbacks can register new callbacks.
+        for (callback, frame) in due {
+            let result = match frame {
+                Some(frame) => callback.call::<Dynamic>(&engine, &ast, (frame,)),
+                None => callback.call::<Dynamic>(&engine, &ast, ()),
+            };
+
+            if let Err(error) = result {
+                return failed(stop, &error);
+            }
+        }
+    }
+}
+
+fn failed(stop: &AtomicBool, error: &EvalAltResult) -> Status {
+    // Scripts that are stopped in a long calculation end with an error, which is expected.
+    if stop.load(Ordering::Relaxed) {
+        return Status::Stopped;
+    }
+
+    tracing::warn!("script failed: {}", error);
+    Status::Failed(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::can;
+    use crate::miu_state::KeyPosition;
+
+    fn start(source: &str) -> (Script, watch::Sender<MiuState>) {
+        let (can, _) = can::task(tokio::runtime::Handle::current());
+        let miu_state = watch::Sender::new(MiuState::default());
+        let script = Script::start(source, can, miu_state.clone()).unwrap();
+
+        (script, miu_state)
+    }
+
+    async fn wait_for(script: &Script, done: impl FnMut(&Status) -> bool) {
+        let mut receiver = script.status.clone();
+        tokio::time::timeout(Duration::from_secs(5), receiver.wait_for(done))
+            .await
+            .expect("script did not reach the status in time")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_reads_and_writes_the_state() {
+        let (script, miu_state) = start(
+            r#"
+            set("key_position", "On");
+            set("engine_speed", get("engine_speed") + 850);
+            "#,
+        );
+
+        wait_for(&script, |status| *status == Status::Finished).await;
+        assert_eq!(miu_state.borrow().key_position, KeyPosition::On);
+        assert_eq!(miu_state.borrow().engine_speed, 850);
+    }
+
+    #[tokio::test]
+    async fn it_runs_timers() {
+        let (mut script, miu_state) = start(
+            r#"
+            every(5, || set("engine_speed", get("engine_speed") + 1));
+            after(1, || set("cruise", true));
+            "#,
+        );
+
+        let mut receiver = miu_state.subscribe();
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            receiver.wait_for(|state| state.engine_speed >= 3 && state.cruise),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(script.status(), Status::Running);
+        script.stop();
+        assert_eq!(script.status(), Status::Stopped);
+    }
+
+    #[tokio::test]
+    async fn it_reports_errors() {
+        let (script, _) = start(r#"set("warp_drive", true);"#);
+        wait_for(&script, |status| matches!(status, Status::Failed(_))).await;
+
+        let Status::Failed(error) = script.status() else {
+            unreachable!()
+        };
+        assert!(error.contains("unknown field /warp_drive"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn it_stops_endless_loops() {
+        let (mut script, _) = start("loop {}");
+
+        tokio::task::spawn_blocking(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            script.stop();
+            assert_eq!(script.status(), Status::Stopped);
+        })
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn it_rejects_invalid_scripts() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let (can, _) = can::task(runtime.handle().clone());
+
+        let result = Script::start("set(", can, watch::Sender::new(MiuState::default()));
+        assert!(matches!(result, Err(ScriptError::Parse(_))));
+    }
+
+    #[test]
+    fn it_converts_frames() {
+        let frame = raw::frame(0x1a0, &[0, 0x03, 0x52, 0, 0, 0, 0, 0]).unwrap();
+        let map = frame_map(&frame);
+
+        assert_eq!(map["id"].as_int().unwrap(), 0x1a0);
+        assert_eq!(map["data"].clone().into_array().unwrap().len(), 8);
+        let fields = map["fields"].clone().cast::<Map>();
+        assert_eq!(fields["speed"].as_int().unwrap(), 850);
+    }
+}
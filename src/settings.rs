@@ -0,0 +1,117 @@
+//! What the user set up in the last session, so it can be restored on startup.
+use serde::{Deserialize, Serialize};
+
+use crate::can::timing::Scheduling;
+use crate::config::{self, ConfigError};
+use crate::miu_state::MiuState;
+
+/// Which of the tool windows are open.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Windows {
+    pub analyser: bool,
+    pub cluster: bool,
+    pub inspector: bool,
+    pub plot: bool,
+    pub scenario: bool,
+    pub script: bool,
+    pub timing: bool,
+    pub transmitter: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    /// The interface that was selected last
+    pub interface: Option<String>,
+
+    /// Connect to the last interface on startup
+    pub auto_connect: bool,
+
+    pub dark_mode: bool,
+
+    /// Act as the MIU on the bus
+    pub emulate_miu: bool,
+
+    /// How the frames are scheduled
+    pub scheduling: Scheduling,
+
+    pub windows: Windows,
+    pub miu_state: MiuState,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            interface: None,
+            auto_connect: false,
+            dark_mode: true,
+            emulate_miu: false,
+            scheduling: Scheduling::Normal,
+            windows: Windows::default(),
+            miu_state: MiuState::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub const CONFIG_NAME: &'static str = "settings";
+
+    /// Loads the settings, falling back to the defaults when they can't be loaded.
+    pub fn load() -> Self {
+        config::load(Self::CONFIG_NAME).unwrap_or_else(|error: ConfigError| {
+            tracing::warn!("unable to load settings, using defaults: {}", error);
+            Self::default()
+        })
+    }
+
+    pub fn save(&self) -> Result<(), ConfigError> {
+        config::save(Self::CONFIG_NAME, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::miu_state::KeyPosition;
+
+    #[test]
+    fn it_uses_defaults_for_missing_settings() {
+        let settings: Settings = toml::from_str(
+            r#"
+            interface = "can0"
+
+            [miu_state]
+            key_position = "On"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(settings.interface.as_deref(), Some("can0"));
+        assert!(settings.dark_mode);
+        assert!(!settings.auto_connect);
+        assert_eq!(settings.miu_state.key_position, KeyPosition::On);
+    }
+
+    #[test]
+    fn it_survives_a_round_trip_through_toml() {
+        let settings = Settings {
+            interface: Some(String::from("vcan0")),
+            auto_connect: true,
+            dark_mode: false,
+            emulate_miu: true,
+            scheduling: Scheduling::Realtime,
+            windows: Windows {
+                inspector: true,
+                ..Default::default()
+            },
+            miu_state: MiuState {
+                engine_speed: 850,
+                ..Default::default()
+            },
+        };
+
+        let text = toml::to_string_pretty(&settings).unwrap();
+        assert_eq!(toml::from_str::<Settings>(&text).unwrap(), settings);
+    }
+}